@@ -6,16 +6,29 @@
  */
 
 use std::sync::Arc;
+use std::sync::Mutex;
 
+use anyhow::bail;
+use anyhow::Error;
 use blobrepo::BlobRepo;
 use blobrepo::BlobRepoInner;
 use blobstore::Blobstore;
 use bonsai_hg_mapping::ArcBonsaiHgMapping;
 use cacheblob::LeaseOps;
+use cacheblob::MemWritesBlobstore;
 use changeset_fetcher::SimpleChangesetFetcher;
 use changesets::ArcChangesets;
+use commit_graph::CommitGraph;
 use filenodes::ArcFilenodes;
+use filestore::FilestoreConfig;
 use repo_blobstore::RepoBlobstore;
+use repo_identity::RepoIdentity;
+
+/// Chunk sizes above this are rejected by `with_chunk_size`. There's no hard limit imposed by
+/// the filestore itself, but chunks this large stop exercising the chunking code in any
+/// meaningful way and start exercising "can we upload one giant blob", which isn't what tests
+/// reaching for this helper are usually after.
+pub const MAX_DANGEROUS_CHUNK_SIZE: u64 = 100 * 1024 * 1024;
 
 /// Create new instance of implementing object with overridden field of specified type.
 ///
@@ -25,6 +38,18 @@ pub trait DangerousOverride<T> {
     fn dangerous_override<F>(&self, modify: F) -> Self
     where
         F: FnOnce(T) -> T;
+
+    /// Run the override machinery for `T` with an identity closure. This
+    /// doesn't change the overridden field, but it does exercise the same
+    /// wiring that a real override would, which is useful for asserting
+    /// that a `DangerousOverride<T>` impl is hooked up correctly (e.g. in
+    /// a test that is only checking that construction doesn't panic).
+    fn dangerous_override_noop(&self) -> Self
+    where
+        Self: Sized,
+    {
+        self.dangerous_override(|field: T| field)
+    }
 }
 
 impl<T> DangerousOverride<T> for BlobRepo
@@ -103,6 +128,24 @@ impl DangerousOverride<ArcChangesets> for BlobRepoInner {
     }
 }
 
+impl DangerousOverride<CommitGraph> for BlobRepoInner {
+    fn dangerous_override<F>(&self, modify: F) -> Self
+    where
+        F: FnOnce(CommitGraph) -> CommitGraph,
+    {
+        let commit_graph = modify(self.commit_graph.clone());
+        let repo_derived_data = Arc::new(
+            self.repo_derived_data
+                .with_replaced_commit_graph(Arc::new(commit_graph.clone())),
+        );
+        Self {
+            commit_graph,
+            repo_derived_data,
+            ..self.clone()
+        }
+    }
+}
+
 impl DangerousOverride<ArcFilenodes> for BlobRepoInner {
     fn dangerous_override<F>(&self, modify: F) -> Self
     where
@@ -121,6 +164,66 @@ impl DangerousOverride<ArcFilenodes> for BlobRepoInner {
     }
 }
 
+/// Wrap a repo's blobstore in a [`MemWritesBlobstore`] via `dangerous_override`, so that
+/// writes are captured in memory instead of being persisted to the real blobstore while
+/// reads still fall through to it. This is intended for integration tests that exercise
+/// write paths but must not mutate the real blobstore.
+///
+/// Returns the overridden repo together with the `MemWritesBlobstore` itself, so that the
+/// caller can inspect what was written during the test via `MemWritesBlobstore::get_cache`,
+/// or flush the captured writes for real with `MemWritesBlobstore::persist`.
+pub fn with_in_memory_writes_blobstore(
+    repo: &BlobRepo,
+) -> (BlobRepo, Arc<MemWritesBlobstore<Arc<dyn Blobstore>>>) {
+    let mem_writes_blobstore = Arc::new(Mutex::new(None));
+    let repo = repo.dangerous_override(|blobstore: Arc<dyn Blobstore>| -> Arc<dyn Blobstore> {
+        let blobstore = Arc::new(MemWritesBlobstore::new(blobstore));
+        *mem_writes_blobstore.lock().expect("lock poisoned") = Some(blobstore.clone());
+        blobstore
+    });
+    let mem_writes_blobstore = mem_writes_blobstore
+        .lock()
+        .expect("lock poisoned")
+        .clone()
+        .expect("dangerous_override always calls the closure exactly once");
+    (repo, mem_writes_blobstore)
+}
+
+impl DangerousOverride<FilestoreConfig> for BlobRepoInner {
+    fn dangerous_override<F>(&self, modify: F) -> Self
+    where
+        F: FnOnce(FilestoreConfig) -> FilestoreConfig,
+    {
+        let filestore_config = modify(self.filestore_config);
+        Self {
+            filestore_config,
+            ..self.clone()
+        }
+    }
+}
+
+/// Override a repo's filestore chunk size via `dangerous_override`, validating that it's
+/// nonzero and no larger than [`MAX_DANGEROUS_CHUNK_SIZE`] instead of silently producing a
+/// `FilestoreConfig` that either can't chunk at all or never actually does. This is intended
+/// for tests that want to force small chunk sizes to exercise multi-chunk filestore paths
+/// without having to construct a whole `FilestoreConfig` by hand.
+pub fn with_chunk_size(repo: &BlobRepo, chunk_size: u64) -> Result<BlobRepo, Error> {
+    if chunk_size == 0 {
+        bail!("chunk_size must be nonzero");
+    }
+    if chunk_size > MAX_DANGEROUS_CHUNK_SIZE {
+        bail!(
+            "chunk_size {} is larger than the maximum supported value of {}",
+            chunk_size,
+            MAX_DANGEROUS_CHUNK_SIZE
+        );
+    }
+    Ok(repo.dangerous_override(|config: FilestoreConfig| FilestoreConfig {
+        chunk_size: Some(chunk_size),
+        ..config
+    }))
+}
+
 impl DangerousOverride<ArcBonsaiHgMapping> for BlobRepoInner {
     fn dangerous_override<F>(&self, modify: F) -> Self
     where
@@ -138,3 +241,48 @@ impl DangerousOverride<ArcBonsaiHgMapping> for BlobRepoInner {
         }
     }
 }
+
+/// Overriding the repo identity is more dangerous than the other overrides in this file,
+/// because far more subsystems key off `RepositoryId`/reponame than are reachable (or even
+/// knowable) from `BlobRepoInner` alone:
+///
+/// - `repo_derived_data`'s inner `DerivedDataManager` captures the repo id and name at
+///   construction time (see `RepoDerivedData::new`) and has no `with_replaced_*` method for
+///   updating them, unlike every other facet it depends on. This override cannot rebuild it,
+///   so derived data will keep being read from and written to mappings keyed by the *old*
+///   repo id even after this override changes `repo_identity`.
+/// - Anything outside this struct that was handed a `RepositoryId` up front (sharding,
+///   per-repo configuration lookups, scuba logging, SQL tables keyed by repo id) will not
+///   notice this override at all, since it never re-reads `repo_identity` from the repo.
+///
+/// `changeset_fetcher` is the one exception: it's rebuilt here exactly as the
+/// `DangerousOverride<ArcChangesets>` impl above does, since it only depends on `changesets`
+/// and the repo id and has no other state to go stale.
+///
+/// This only ever swaps the whole `RepoIdentity` in one piece, so `id()` and `name()` always
+/// stay mutually consistent with whatever the closure returns; it does not support changing
+/// one while leaving the other pinned to its old value. Intended for tests that need to
+/// observe a controlled repo name (e.g. in error messages or cross-repo routing) without
+/// exercising real derived-data or cross-repo behavior under the new identity.
+impl DangerousOverride<RepoIdentity> for BlobRepoInner {
+    fn dangerous_override<F>(&self, modify: F) -> Self
+    where
+        F: FnOnce(RepoIdentity) -> RepoIdentity,
+    {
+        let repo_identity = modify(RepoIdentity::new(
+            self.repo_identity.id(),
+            self.repo_identity.name().to_string(),
+        ));
+        let reponame = repo_identity.name().to_string();
+        let changeset_fetcher = Arc::new(SimpleChangesetFetcher::new(
+            self.changesets.clone(),
+            repo_identity.id(),
+        ));
+        Self {
+            repo_identity,
+            reponame,
+            changeset_fetcher,
+            ..self.clone()
+        }
+    }
+}
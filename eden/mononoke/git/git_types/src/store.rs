@@ -382,6 +382,13 @@ where
     })
 }
 
+/// Maximum number of delta instruction chunks that are allowed to be in
+/// flight (fetched but not yet consumed) at once. This bounds the peak
+/// memory used while assembling the instructions for a single delta, since
+/// at most this many chunks are ever held in memory simultaneously
+/// regardless of how many chunks the delta is split into.
+const MAX_CONCURRENT_CHUNK_FETCHES: usize = 24; // Same as the concurrency used for filestore
+
 /// Fetch all the delta instruction chunks corresponding to the given prefix and return the result
 /// as a boxed stream of bytes in order
 #[allow(dead_code)]
@@ -394,6 +401,9 @@ pub fn fetch_delta_instructions<'a, B>(
 where
     B: Blobstore + Clone,
 {
+    // Never buffer more in-flight fetches than there are chunks to fetch, so
+    // small deltas don't pay for unused concurrency slots.
+    let concurrency = std::cmp::min(MAX_CONCURRENT_CHUNK_FETCHES as u64, chunk_count.max(1)) as usize;
     stream::iter(0..chunk_count)
         .map(move |chunk_idx| async move {
             let chunk_id = chunk_prefix.as_id(chunk_idx as usize);
@@ -402,7 +412,7 @@ where
             })?;
             anyhow::Ok(chunk.into_bytes())
         })
-        .buffered(24) // Same as the concurrency used for filestore
+        .buffered(concurrency)
         .boxed()
 }
 
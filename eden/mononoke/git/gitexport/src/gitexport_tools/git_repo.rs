@@ -15,6 +15,7 @@ use packfile::bundle::BundleWriter;
 use packfile::pack::DeltaForm;
 use protocol::generator::generate_pack_item_stream;
 use protocol::generator::Repo;
+use protocol::types::DeltaBasePreference;
 use protocol::types::DeltaInclusion;
 use protocol::types::PackItemStreamRequest;
 use protocol::types::PackfileItemInclusion;
@@ -55,6 +56,7 @@ pub async fn create_git_repo_on_disk(
     let delta_inclusion = DeltaInclusion::Include {
         form: DeltaForm::RefAndOffset,
         inclusion_threshold: 0.6,
+        base_preference: DeltaBasePreference::SmallestSize,
     };
     let request = PackItemStreamRequest::full_repo(
         delta_inclusion,
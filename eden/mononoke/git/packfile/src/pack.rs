@@ -40,10 +40,84 @@ pub enum DeltaForm {
     OnlyOffset,
 }
 
+/// Reorder `items` so that, for every `OidDelta` item whose base object is also present in
+/// `items`, that base appears earlier in the returned `Vec` than the delta that depends on it.
+/// Items with no such dependency, or whose base isn't present in `items` at all (e.g. it's a
+/// prerequisite object the client already has, not part of this pack), keep their relative
+/// order unchanged.
+///
+/// This is needed before writing a pack with `DeltaForm::OnlyOffset`: offset deltas identify
+/// their base by backwards byte offset into the packfile being written, so the base must
+/// already have been written (and therefore already have a recorded offset) by the time
+/// `convert_ref_delta_to_offset_delta` gets to the delta. Plain ref deltas have no such
+/// requirement, since they identify their base by object id rather than position. Chains of
+/// deltas (a delta whose base is itself a delta) are followed all the way down.
+///
+/// Returns an error if the dependencies form a cycle, which would mean the input was
+/// already malformed (a `GitDeltaManifest` entry depending on itself, directly or
+/// transitively) rather than something this function can fix by reordering.
+pub fn order_for_offset_deltas(items: Vec<PackfileItem>) -> Result<Vec<PackfileItem>> {
+    let ids_by_index: FxHashMap<ObjectId, usize> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (item.id(), index))
+        .collect();
+    let mut items: Vec<Option<PackfileItem>> = items.into_iter().map(Some).collect();
+    let mut visited = vec![false; items.len()];
+    let mut in_progress = vec![false; items.len()];
+    let mut ordered = Vec::with_capacity(items.len());
+
+    fn visit(
+        index: usize,
+        items: &mut [Option<PackfileItem>],
+        ids_by_index: &FxHashMap<ObjectId, usize>,
+        visited: &mut [bool],
+        in_progress: &mut [bool],
+        ordered: &mut Vec<PackfileItem>,
+    ) -> Result<()> {
+        if visited[index] {
+            return Ok(());
+        }
+        anyhow::ensure!(
+            !in_progress[index],
+            "Cycle detected in delta base dependencies while ordering packfile items for offset deltas"
+        );
+        in_progress[index] = true;
+        let base_index = items[index]
+            .as_ref()
+            .and_then(|item| item.delta_base())
+            .and_then(|base_oid| ids_by_index.get(&base_oid).copied());
+        if let Some(base_index) = base_index {
+            visit(base_index, items, ids_by_index, visited, in_progress, ordered)?;
+        }
+        in_progress[index] = false;
+        visited[index] = true;
+        if let Some(item) = items[index].take() {
+            ordered.push(item);
+        }
+        Ok(())
+    }
+
+    for index in 0..items.len() {
+        visit(
+            index,
+            &mut items,
+            &ids_by_index,
+            &mut visited,
+            &mut in_progress,
+            &mut ordered,
+        )?;
+    }
+    Ok(ordered)
+}
+
 /// Struct responsible for encoding and writing incoming stream
 /// of git object bytes as a packfile to `raw_writer`.
 /// NOTE: The caller must ensure that the stream of objects passed to this
-/// writer are sorted topologically
+/// writer are sorted topologically. When the writer is constructed with
+/// `DeltaForm::OnlyOffset`, [`order_for_offset_deltas`] can be used to produce such an
+/// ordering from a batch of items whose deltas may reference bases anywhere in the batch
+/// (e.g. a cross-commit delta base), rather than only ones that already precede their delta.
 pub struct PackfileWriter<T>
 where
     T: AsyncWrite + Unpin,
@@ -64,6 +64,24 @@ impl PackfileItem {
             compressed_data,
         ))
     }
+
+    /// The ObjectId of the Git object this item represents.
+    pub fn id(&self) -> ObjectId {
+        match self {
+            Self::Base(base) => base.hash().into(),
+            Self::EncodedBase(entry) => entry.id,
+            Self::OidDelta(oid_delta) => oid_delta.oid,
+        }
+    }
+
+    /// The ObjectId of the base object this item is a delta against, if it is one.
+    /// `Base` and `EncodedBase` items are never deltas, so this is always `None` for them.
+    pub fn delta_base(&self) -> Option<ObjectId> {
+        match self {
+            Self::Base(_) | Self::EncodedBase(_) => None,
+            Self::OidDelta(oid_delta) => Some(oid_delta.base_oid),
+        }
+    }
 }
 
 impl TryFrom<PackfileItem> for output::Entry {
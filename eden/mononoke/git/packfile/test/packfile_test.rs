@@ -21,6 +21,7 @@ use gix_object::Object;
 use gix_object::ObjectRef;
 use gix_object::Tag;
 use packfile::bundle::BundleWriter;
+use packfile::pack::order_for_offset_deltas;
 use packfile::pack::DeltaForm;
 use packfile::pack::PackfileWriter;
 use packfile::thrift;
@@ -91,6 +92,50 @@ async fn get_objects_stream(
     Ok(objects_stream)
 }
 
+/// Build a list of packfile items where a delta appears *before* the base object it
+/// depends on, simulating a delta whose base was contributed by a different (later
+/// processed) commit. Returns the items in this out-of-order form.
+async fn get_out_of_order_delta_items() -> anyhow::Result<Vec<PackfileItem>> {
+    let tag_bytes = Bytes::from(to_vec_bytes(&gix_object::Object::Tag(Tag {
+        target: ObjectId::empty_tree(gix_hash::Kind::Sha1),
+        target_kind: gix_object::Kind::Tree,
+        name: "TreeTag".into(),
+        tagger: None,
+        message: "Tag pointing to a tree".into(),
+        pgp_signature: None,
+    }))?);
+    let another_tag_bytes = Bytes::from(to_vec_bytes(&gix_object::Object::Tag(Tag {
+        target: ObjectId::empty_tree(gix_hash::Kind::Sha1),
+        target_kind: gix_object::Kind::Tree,
+        name: "BlobTag".into(),
+        tagger: None,
+        message: "Tag pointing to a blob".into(),
+        pgp_signature: None,
+    }))?);
+    let another_tag_hash = BaseObject::new(another_tag_bytes.clone())?
+        .hash()
+        .to_owned();
+    let tag_hash = BaseObject::new(tag_bytes.clone())?.hash().to_owned();
+    let delta_instructions =
+        DeltaInstructions::generate(tag_bytes.clone(), another_tag_bytes, Algorithm::Myers)?;
+    let mut raw_instructions = Vec::new();
+    delta_instructions.write(&mut raw_instructions).await?;
+    let decompressed_size = raw_instructions.len() as u64;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&raw_instructions)?;
+    let compressed_instruction_bytes = Bytes::from(encoder.finish()?);
+    let delta_item = PackfileItem::new_delta(
+        another_tag_hash,
+        tag_hash,
+        decompressed_size,
+        compressed_instruction_bytes,
+    );
+    // The delta comes first here, with its base (`tag_bytes`) only appearing afterwards,
+    // which is exactly the shape `PackfileWriter::write` cannot handle with
+    // `DeltaForm::OnlyOffset` without first being reordered.
+    Ok(vec![delta_item, PackfileItem::new_base(tag_bytes)?])
+}
+
 #[test]
 fn validate_packitem_creation() -> anyhow::Result<()> {
     // Create a Git object
@@ -349,6 +394,49 @@ async fn validate_delta_packfile_generation() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn validate_order_for_offset_deltas() -> anyhow::Result<()> {
+    let items = get_out_of_order_delta_items().await?;
+    // Writing the out-of-order items directly should fail, since the delta's base hasn't
+    // been written (and so has no recorded offset) by the time the delta is converted.
+    let mut packfile_writer = PackfileWriter::new(Vec::new(), 2, 100, DeltaForm::OnlyOffset);
+    let write_result = packfile_writer
+        .write(stream::iter(items.into_iter().map(anyhow::Ok)))
+        .await;
+    assert!(write_result.is_err());
+
+    // Reordering the items so the base precedes its delta should fix this, and the
+    // resulting packfile should be readable by a strict pack indexer with no
+    // "delta base not found" (or any other) errors.
+    let items = get_out_of_order_delta_items().await?;
+    let ordered_items = order_for_offset_deltas(items)?;
+    assert_eq!(ordered_items[0].id(), ordered_items[1].delta_base().unwrap());
+
+    let mut packfile_writer = PackfileWriter::new(Vec::new(), 2, 100, DeltaForm::OnlyOffset);
+    packfile_writer
+        .write(stream::iter(ordered_items.into_iter().map(anyhow::Ok)))
+        .await
+        .expect("Expected successful write of reordered objects to packfile");
+    packfile_writer
+        .finish()
+        .await
+        .expect("Expected successful checksum computation for packfile");
+    let written_content = packfile_writer.into_write();
+    let mut created_file = NamedTempFile::new()?;
+    created_file.write_all(written_content.as_ref())?;
+    let opened_packfile = gix_pack::data::File::at(created_file.path(), gix_hash::Kind::Sha1)
+        .expect("Expected successful opening of packfile");
+    for entry in opened_packfile
+        .streaming_iter()
+        .expect("Expected successful iteration of packfile entries")
+    {
+        // A strict pack indexer resolving this entry (including offset deltas by walking
+        // backwards to their base) should never fail to find the base object.
+        entry.expect("Expected valid Git object in packfile entry, base should be resolvable");
+    }
+    Ok(())
+}
+
 #[fbinit::test]
 async fn validate_basic_bundle_generation() -> anyhow::Result<()> {
     // Create a few Git objects
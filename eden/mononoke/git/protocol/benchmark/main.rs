@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::time::Instant;
+
+use bookmarks::BookmarkKey;
+use gix_hash::ObjectId;
+use mononoke_types::hash::Blake2;
+use mononoke_types::ChangesetId;
+use protocol::generator::build_refs_to_include;
+use protocol::types::TagInclusion;
+use rustc_hash::FxHashMap;
+
+const NUM_REFS: usize = 500_000;
+
+fn changeset_id(i: usize) -> ChangesetId {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&(i as u64).to_le_bytes());
+    ChangesetId::new(Blake2::from_byte_array(bytes))
+}
+
+fn main() {
+    // The actual object a ref points to doesn't matter for this benchmark, so
+    // every ref shares the same target to avoid paying for hashing NUM_REFS
+    // distinct objects.
+    let object_id = ObjectId::empty_tree(gix_hash::Kind::Sha1);
+
+    let mut bookmarks = FxHashMap::default();
+    let mut bonsai_git_map = FxHashMap::default();
+    let mut bonsai_tag_map = FxHashMap::default();
+    for i in 0..NUM_REFS {
+        let cs_id = changeset_id(i);
+        // Exercise every branch of the classification logic: plain branches,
+        // lightweight tags (no entry in `bonsai_tag_map`), and annotated tags.
+        let name = if i % 3 == 0 {
+            format!("tags/tag-{}", i)
+        } else {
+            format!("heads/branch-{}", i)
+        };
+        let bookmark = BookmarkKey::new(&name).expect("valid bookmark name");
+        if bookmark.is_tag() && i % 2 == 0 {
+            bonsai_tag_map.insert(name, object_id);
+        }
+        bonsai_git_map.insert(cs_id, object_id);
+        bookmarks.insert(bookmark, cs_id);
+    }
+
+    let start = Instant::now();
+    let (refs_to_include, _peeled_refs, _tag_kinds) = build_refs_to_include(
+        &bookmarks,
+        &bonsai_git_map,
+        &bonsai_tag_map,
+        TagInclusion::WithTarget,
+    )
+    .expect("classification should succeed for well-formed synthetic input");
+    let elapsed = start.elapsed();
+
+    assert_eq!(refs_to_include.len(), NUM_REFS);
+    println!(
+        "Classified {} refs in {:?} ({:.0} refs/sec)",
+        NUM_REFS,
+        elapsed,
+        NUM_REFS as f64 / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,55 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use git_types::GitDeltaManifestEntry;
+use lru::LruCache;
+use mononoke_types::path::MPath;
+use mononoke_types::ChangesetId;
+use once_cell::sync::Lazy;
+
+/// Maximum number of commits' worth of delta manifest entries to keep cached at once.
+/// This bounds the cache to a fixed amount of memory regardless of how many distinct
+/// commits are fetched, at the cost of evicting colder commits first.
+const CAPACITY: usize = 1_000;
+
+/// The (path, entry) pairs of a commit's Git delta manifest, as yielded by
+/// [`git_types::GitDeltaManifest::into_subentries`].
+type DeltaManifestEntries = Arc<Vec<(MPath, GitDeltaManifestEntry)>>;
+
+/// Process-wide cache of [`DeltaManifestEntries`], keyed by the commit's `ChangesetId`.
+///
+/// Busy git servers can receive overlapping fetches that touch the same hot commits, each
+/// of which would otherwise independently derive the commit's `RootGitDeltaManifestId` and
+/// load and enumerate its delta manifest from the blobstore. Sharing one bounded,
+/// process-wide cache across requests lets later fetches skip that redundant work.
+static DELTA_MANIFEST_CACHE: Lazy<Mutex<LruCache<ChangesetId, DeltaManifestEntries>>> =
+    Lazy::new(|| {
+        Mutex::new(LruCache::new(
+            NonZeroUsize::new(CAPACITY).expect("CAPACITY is non-zero"),
+        ))
+    });
+
+/// Return the cached delta manifest entries for `changeset_id`, if present.
+pub fn get(changeset_id: &ChangesetId) -> Option<DeltaManifestEntries> {
+    DELTA_MANIFEST_CACHE
+        .lock()
+        .expect("DELTA_MANIFEST_CACHE lock poisoned")
+        .get(changeset_id)
+        .cloned()
+}
+
+/// Populate the cache with the delta manifest entries for `changeset_id`.
+pub fn put(changeset_id: ChangesetId, entries: DeltaManifestEntries) {
+    DELTA_MANIFEST_CACHE
+        .lock()
+        .expect("DELTA_MANIFEST_CACHE lock poisoned")
+        .put(changeset_id, entries);
+}
@@ -0,0 +1,19 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum GitProtocolError {
+    /// The number of refs that would be included in the response exceeds the
+    /// configured limit.
+    #[error(
+        "The number of refs ({0}) to be included in the response exceeds the limit of {1}. \
+        Consider using ref-prefix filtering to narrow down the set of refs requested"
+    )]
+    TooManyRefs(usize, u64),
+}
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -26,10 +27,12 @@ use bytes::Bytes;
 use bytes::BytesMut;
 use commit_graph::CommitGraphRef;
 use context::CoreContext;
+use futures::future;
 use futures::stream;
 use futures::stream::BoxStream;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use git_symbolic_refs::GitSymbolicRefsEntry;
 use git_symbolic_refs::GitSymbolicRefsRef;
 use git_types::fetch_delta_instructions;
 use git_types::fetch_git_object_bytes;
@@ -47,13 +50,24 @@ use mononoke_types::hash::GitSha1;
 use mononoke_types::hash::RichGitSha1;
 use mononoke_types::path::MPath;
 use mononoke_types::ChangesetId;
+use packfile::pack::order_for_offset_deltas;
 use packfile::types::PackfileItem;
 use repo_blobstore::RepoBlobstoreArc;
 use repo_derived_data::RepoDerivedDataRef;
 use repo_identity::RepoIdentityRef;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
+use slog::error;
+use slog::warn;
+use tokio::sync::Semaphore;
+use stats::prelude::*;
+use tokio_util::sync::CancellationToken;
 
+use crate::cardinality::HyperLogLog;
+use crate::delta_manifest_cache;
+use crate::errors::GitProtocolError;
+use crate::types::BundleUri;
+use crate::types::DeltaBasePreference;
 use crate::types::DeltaInclusion;
 use crate::types::FetchRequest;
 use crate::types::FetchResponse;
@@ -61,16 +75,34 @@ use crate::types::LsRefsRequest;
 use crate::types::LsRefsResponse;
 use crate::types::PackItemStreamRequest;
 use crate::types::PackItemStreamResponse;
+use crate::types::PackValidationReport;
+use crate::types::PackfileConcurrency;
 use crate::types::PackfileItemInclusion;
+use crate::types::PackfileItemStreamOrdering;
+use crate::types::PackfileObjectCounts;
 use crate::types::RefTarget;
 use crate::types::RequestedRefs;
 use crate::types::RequestedSymrefs;
 use crate::types::SymrefFormat;
 use crate::types::TagInclusion;
+use crate::types::TagKind;
 
 const HEAD_REF: &str = "HEAD";
 const TAGS_PREFIX: &str = "tags/";
 
+define_stats! {
+    prefix = "mononoke.git_protocol";
+    // Branch taken by base_packfile_item, by packfile_item_inclusion mode. The two
+    // fetch_and_store counters in particular show the cache hit rate of the
+    // packfile-base-item store, i.e. how often FetchAndStore avoids regenerating the
+    // item from the raw git object.
+    base_packfile_item_generated: timeseries(Rate, Sum),
+    base_packfile_item_fetched: timeseries(Rate, Sum),
+    base_packfile_item_fetch_and_store_hit: timeseries(Rate, Sum),
+    base_packfile_item_fetch_and_store_miss: timeseries(Rate, Sum),
+    base_packfile_item_generated_and_stored: timeseries(Rate, Sum),
+}
+
 pub trait Repo = RepoIdentityRef
     + RepoBlobstoreArc
     + BookmarksRef
@@ -90,14 +122,29 @@ async fn bookmarks(
     ctx: &CoreContext,
     repo: &impl Repo,
     requested_refs: &RequestedRefs,
+    freshness: Freshness,
 ) -> Result<FxHashMap<BookmarkKey, ChangesetId>> {
+    // If the caller has asked only for refs under refs/tags/, we don't need
+    // to fetch (and then filter out) every branch bookmark from the store.
+    const TAGS_REF_PREFIX: &str = "refs/tags/";
+    let categories: &[BookmarkCategory] = match requested_refs {
+        RequestedRefs::IncludedWithPrefix(ref_prefixes)
+            if !ref_prefixes.is_empty()
+                && ref_prefixes
+                    .iter()
+                    .all(|prefix| prefix.starts_with(TAGS_REF_PREFIX)) =>
+        {
+            &[BookmarkCategory::Tag]
+        }
+        _ => BookmarkCategory::ALL,
+    };
     let mut bookmarks = repo
         .bookmarks()
         .list(
             ctx.clone(),
-            Freshness::MostRecent,
+            freshness,
             &BookmarkPrefix::empty(),
-            BookmarkCategory::ALL,
+            categories,
             BookmarkKind::ALL_PUBLISHING,
             &BookmarkPagination::FromStart,
             u64::MAX,
@@ -138,6 +185,8 @@ async fn bookmarks(
     // bookmarks known at the server, we need to manually include them in the output
     if let RequestedRefs::IncludedWithValue(ref ref_value_map) = requested_refs {
         for (ref_name, ref_value) in ref_value_map {
+            let ref_name = normalize_ref_name(ref_name)
+                .with_context(|| format!("Invalid ref name {} in requested refs", ref_name))?;
             bookmarks.insert(
                 BookmarkKey::with_name(ref_name.as_str().try_into()?),
                 ref_value.clone(),
@@ -147,57 +196,170 @@ async fn bookmarks(
     Ok(bookmarks)
 }
 
-/// Get the count of distinct blob and tree items to be included in the packfile
-async fn trees_and_blobs_count(
+/// Validate a caller-provided ref name and normalize it to the form used for bookmark
+/// names internally, i.e. without the leading "refs/" prefix that callers conventionally
+/// include (e.g. "refs/heads/master" -> "heads/master").
+fn normalize_ref_name(ref_name: &str) -> Result<String> {
+    let ref_name = ref_name.strip_prefix("refs/").unwrap_or(ref_name);
+    if ref_name.is_empty()
+        || ref_name.starts_with('/')
+        || ref_name.ends_with('/')
+        || ref_name.contains("//")
+        || ref_name.contains("..")
+        || ref_name.contains(char::is_whitespace)
+    {
+        anyhow::bail!("Ref name {} is not a valid ref name", ref_name);
+    }
+    Ok(ref_name.to_string())
+}
+
+/// Load the (oid, uncompressed size) of every tree and blob object referenced by `changeset_id`'s
+/// Git delta manifest.
+async fn commit_trees_and_blobs(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    changeset_id: ChangesetId,
+) -> Result<Vec<(GitSha1, u64)>> {
+    let entries = delta_manifest_entries(ctx, repo, changeset_id).await?;
+    Ok(entries
+        .iter()
+        .map(|(_, entry)| (entry.full.oid, entry.full.size))
+        .collect())
+}
+
+/// Load the (path, entry) pairs of `changeset_id`'s Git delta manifest, consulting and
+/// populating the process-wide [`delta_manifest_cache`] so that repeated fetches touching
+/// the same commit (e.g. a dry-run size estimate followed by the real object stream) don't
+/// each pay to derive and load the manifest from the blobstore.
+async fn delta_manifest_entries(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    changeset_id: ChangesetId,
+) -> Result<Arc<Vec<(MPath, GitDeltaManifestEntry)>>> {
+    if let Some(entries) = delta_manifest_cache::get(&changeset_id) {
+        return Ok(entries);
+    }
+    let blobstore = repo.repo_blobstore_arc();
+    let root_mf_id = repo
+        .repo_derived_data()
+        .derive::<RootGitDeltaManifestId>(ctx, changeset_id)
+        .await
+        .with_context(|| {
+            format!(
+                "Error in deriving RootGitDeltaManifestId for commit {:?}",
+                changeset_id
+            )
+        })?;
+    let delta_manifest = root_mf_id
+        .manifest_id()
+        .load(ctx, &blobstore)
+        .await
+        .with_context(|| {
+            format!(
+                "Error in loading Git Delta Manifest from root id {:?}",
+                root_mf_id
+            )
+        })?;
+    let entries = delta_manifest
+        .into_subentries(ctx, &blobstore)
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| {
+            format!(
+                "Error while listing entries from GitDeltaManifest {:?}",
+                root_mf_id
+            )
+        })?;
+    let entries = Arc::new(entries);
+    delta_manifest_cache::put(changeset_id, entries.clone());
+    Ok(entries)
+}
+
+/// Count the tree and blob objects reachable from `target_commits`, and their total
+/// uncompressed size.
+///
+/// When `exact` is true, the count is the cheap sum of each commit's Git delta manifest
+/// subentry count (the same entries [`blob_and_tree_packfile_items`] goes on to emit one
+/// packfile item per), with no cross-commit deduplication pass. This is exactly as precise
+/// as the packfile that actually gets generated needs it to be: per-commit delta manifests
+/// are derived from that commit's own tree/blob changes, and the generated stream itself
+/// does not deduplicate across commits either, so a global dedup pass here would only have
+/// made the count diverge from what's actually streamed, while also paying to keep every
+/// distinct object's oid in memory for the duration of the count (expensive for a packfile
+/// covering millions of objects). This count feeds into the packfile header, as it does in
+/// [`generate_pack_item_stream`], so deriving it cheaply rather than skipping it lets the
+/// item generation stream that follows start running sooner.
+///
+/// When `exact` is false, the count is estimated with a [`HyperLogLog`], which uses fixed,
+/// small memory no matter how many objects are seen, at the cost of the count being an
+/// estimate (~1% typical error) that *does* deduplicate objects across commits. The
+/// accompanying size in this mode is the sum of every object's size *without
+/// deduplication*, so it over-counts objects shared between commits; this is consistent
+/// with the other approximations already made for `dry_run` responses, which never see
+/// this count leave the process (it's just an input to a size estimate), so an
+/// approximation of the true distinct object count is an acceptable trade for the
+/// bounded memory use, unlike the `exact` sum above which favours matching the real stream.
+///
+/// When `tolerate_derivation_errors` is true, a commit whose trees and blobs can't be derived
+/// is logged as a warning and excluded from the count (and returned in the third element of
+/// the tuple) instead of failing the whole count. Callers relying on this count to determine
+/// which commits will actually be included in a generated stream (e.g.
+/// [`generate_pack_item_stream`]) must exclude the same commits from that stream.
+async fn trees_and_blobs_stats(
     ctx: &CoreContext,
     repo: &impl Repo,
     target_commits: BoxStream<'_, Result<ChangesetId>>,
-) -> Result<usize> {
-    // Sum up the entries in the delta manifest for each commit included in packfile
-    target_commits
-        .map_ok(|changeset_id| {
-            async move {
-                let blobstore = repo.repo_blobstore_arc();
-                let root_mf_id = repo
-                    .repo_derived_data()
-                    .derive::<RootGitDeltaManifestId>(ctx, changeset_id)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Error in deriving RootGitDeltaManifestId for commit {:?}",
-                            changeset_id
-                        )
-                    })?;
-                let delta_manifest = root_mf_id
-                    .manifest_id()
-                    .load(ctx, &blobstore)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Error in loading Git Delta Manifest from root id {:?}",
-                            root_mf_id
-                        )
-                    })?;
-                // Get the FxHashSet of the tree and blob object Ids that will be included
-                // in the packfile
-                let objects = delta_manifest
-                    .into_subentries(ctx, &blobstore)
-                    .map_ok(|(_, entry)| entry.full.oid)
-                    .try_collect::<FxHashSet<_>>()
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Error while listing entries from GitDeltaManifest {:?}",
-                            root_mf_id
-                        )
-                    })?;
-                anyhow::Ok(objects)
+    concurrency: PackfileConcurrency,
+    exact: bool,
+    tolerate_derivation_errors: bool,
+) -> Result<(usize, u64, FxHashSet<ChangesetId>)> {
+    let mut per_commit_objects = target_commits
+        .map_ok(|changeset_id| async move {
+            let result = commit_trees_and_blobs(ctx, repo, changeset_id).await;
+            if tolerate_derivation_errors {
+                match result {
+                    Ok(objects) => Ok((objects, None)),
+                    Err(e) => {
+                        warn!(
+                            ctx.logger(),
+                            "Skipping commit {:?} while counting trees and blobs: {:#}",
+                            changeset_id,
+                            e
+                        );
+                        Ok((Vec::new(), Some(changeset_id)))
+                    }
+                }
+            } else {
+                result.map(|objects| (objects, None))
             }
         })
-        .try_buffer_unordered(1000)
-        .try_concat()
-        .await
-        .map(|objects| objects.len())
+        .try_buffer_unordered(concurrency.per_commit_concurrency);
+
+    let mut skipped_commits = FxHashSet::default();
+
+    if exact {
+        let mut object_count = 0usize;
+        let mut total_size = 0u64;
+        while let Some((commit_objects, skipped)) = per_commit_objects.try_next().await? {
+            object_count += commit_objects.len();
+            for (_oid, size) in commit_objects {
+                total_size = total_size.saturating_add(size);
+            }
+            skipped_commits.extend(skipped);
+        }
+        return Ok((object_count, total_size, skipped_commits));
+    }
+
+    let mut estimator = HyperLogLog::new();
+    let mut total_size = 0u64;
+    while let Some((commit_objects, skipped)) = per_commit_objects.try_next().await? {
+        for (oid, size) in commit_objects {
+            estimator.insert(&oid);
+            total_size = total_size.saturating_add(size);
+        }
+        skipped_commits.extend(skipped);
+    }
+    Ok((estimator.estimate() as usize, total_size, skipped_commits))
 }
 
 fn delta_below_threshold(
@@ -212,16 +374,33 @@ fn delta_below_threshold(
 fn delta_base(
     entry: &mut GitDeltaManifestEntry,
     delta_inclusion: DeltaInclusion,
+    exclude_delta_for: &Arc<FxHashSet<ObjectId>>,
 ) -> Option<ObjectDelta> {
+    if exclude_delta_for.contains(&entry.full.oid) {
+        return None;
+    }
     match delta_inclusion {
         DeltaInclusion::Include {
             inclusion_threshold,
+            base_preference,
             ..
         } => {
             entry.deltas.sort_by(|a, b| {
                 a.instructions_compressed_size
                     .cmp(&b.instructions_compressed_size)
             });
+            if base_preference == DeltaBasePreference::SamePathPredecessor {
+                let full_path = entry.full.path.clone();
+                let same_path_delta = entry
+                    .deltas
+                    .iter()
+                    .find(|delta| delta.base.path == full_path)
+                    .filter(|delta| delta_below_threshold(delta, entry, inclusion_threshold))
+                    .cloned();
+                if same_path_delta.is_some() {
+                    return same_path_delta;
+                }
+            }
             entry
                 .deltas
                 .first()
@@ -237,31 +416,107 @@ fn to_commit_stream(commits: Vec<ChangesetId>) -> BoxStream<'static, Result<Chan
     stream::iter(commits.into_iter().map(Ok)).boxed()
 }
 
+/// Stop yielding packfile items from `stream` as soon as `cancellation_token` is
+/// cancelled (e.g. because the client that requested the pack has disconnected),
+/// instead of continuing to derive and load objects that nobody will read.
+fn cancellable_packfile_stream<'a>(
+    stream: BoxStream<'a, Result<PackfileItem>>,
+    cancellation_token: Option<CancellationToken>,
+) -> BoxStream<'a, Result<PackfileItem>> {
+    match cancellation_token {
+        Some(cancellation_token) => stream
+            .take_while(move |_| future::ready(!cancellation_token.is_cancelled()))
+            .boxed(),
+        None => stream,
+    }
+}
+
+/// Whether [`verify_object_count_packfile_stream`] should actually count items as the
+/// stream is consumed. Always on in tests, since the check is cheap and this is exactly
+/// the kind of regression (object count drifting from the stream) that's easy to
+/// reintroduce silently. Gated behind a justknob in prod since it still costs a counter
+/// increment per item on every pack/bundle generated.
+fn should_verify_object_count() -> bool {
+    if cfg!(test) {
+        return true;
+    }
+    justknobs::eval(
+        "scm/mononoke:git_protocol_verify_packfile_object_count",
+        None,
+        None,
+    )
+    .unwrap_or(false)
+}
+
+/// Wrap `stream` so that, once [`should_verify_object_count`] is true, every yielded
+/// `PackfileItem` is counted and, once the stream is exhausted, the count is compared
+/// against `object_count` -- the count that was already baked into the pack/bundle header.
+/// The two can drift apart because they're computed by different code paths (e.g. the
+/// `trees_and_blobs_stats` count, tag filtering, or a commit skipped due to derivation
+/// errors can each change one without the other), and a pack whose header lies about its
+/// object count is rejected by clients only after they've read the whole thing. Mismatches
+/// are therefore reported as a stream error rather than just a log line.
+fn verify_object_count_packfile_stream<'a>(
+    ctx: &'a CoreContext,
+    stream: BoxStream<'a, Result<PackfileItem>>,
+    object_count: usize,
+) -> BoxStream<'a, Result<PackfileItem>> {
+    if !should_verify_object_count() {
+        return stream;
+    }
+    try_stream! {
+        let mut actual_count = 0usize;
+        let mut stream = stream;
+        while let Some(item) = stream.try_next().await? {
+            actual_count += 1;
+            yield item;
+        }
+        if actual_count != object_count {
+            let err = anyhow::anyhow!(
+                "Packfile stream emitted {} items but the pack/bundle header declared object_count {}",
+                actual_count,
+                object_count
+            );
+            error!(ctx.logger(), "{:#}", err);
+            Err(err)?;
+        }
+    }
+    .boxed()
+}
+
 /// Fetch all the bonsai commits pointed to by the annotated tags corresponding
-/// to the input object ids
+/// to the input object ids, along with the subset of the input git shas that
+/// are actually known to be tags (i.e. have an entry in the tag mapping).
 async fn tagged_commits(
     ctx: &CoreContext,
     repo: &impl Repo,
     git_shas: Vec<GitSha1>,
-) -> Result<Vec<ChangesetId>> {
+    freshness: Freshness,
+) -> Result<(Vec<ChangesetId>, FxHashSet<GitSha1>)> {
     if git_shas.is_empty() {
-        return Ok(vec![]);
+        return Ok((vec![], FxHashSet::default()));
     }
-    // Fetch the names of the tags corresponding to the tag object represented by the input object ids
-    let tag_names = repo
+    let tag_entries = repo
         .bonsai_tag_mapping()
         .get_entries_by_tag_hashes(git_shas)
         .await
-        .context("Error while fetching tag entries from tag hashes")?
+        .context("Error while fetching tag entries from tag hashes")?;
+    let known_tag_shas = tag_entries
+        .iter()
+        .map(|entry| entry.tag_hash)
+        .collect::<FxHashSet<_>>();
+    // Fetch the names of the tags corresponding to the tag object represented by the input object ids
+    let tag_names = tag_entries
         .into_iter()
         .map(|entry| entry.tag_name)
         .collect::<FxHashSet<String>>();
     let tag_names = Arc::new(tag_names);
     // Fetch the commits pointed to by those tags
-    repo.bookmarks()
+    let commits = repo
+        .bookmarks()
         .list(
             ctx.clone(),
-            Freshness::MostRecent,
+            freshness,
             &BookmarkPrefix::new(TAGS_PREFIX)?,
             BookmarkCategory::ALL,
             BookmarkKind::ALL_PUBLISHING,
@@ -279,24 +534,38 @@ async fn tagged_commits(
             }
         })
         .try_collect::<Vec<_>>()
-        .await
+        .await?;
+    Ok((commits, known_tag_shas))
 }
 
 /// Fetch the corresponding bonsai commits for the input Git object ids. If the object id doesn't
-/// correspond to a bonsai commit, try to resolve it to a tag and then fetch the bonsai commit
+/// correspond to a bonsai commit, try to resolve it to a tag and then fetch the bonsai commit.
+///
+/// Returns the resolved bonsai commits alongside the subset of the input object ids that are
+/// unknown to this repo, i.e. neither present in the bonsai_git_mapping nor resolvable as a tag.
+/// Callers that only care about the resolved commits (e.g. when resolving `heads`, which are
+/// validated elsewhere) can ignore the second element of the tuple.
 async fn git_shas_to_bonsais(
     ctx: &CoreContext,
     repo: &impl Repo,
     oids: impl Iterator<Item = impl AsRef<gix_hash::oid>>,
-) -> Result<Vec<ChangesetId>> {
-    let shas = oids
-        .map(|oid| GitSha1::from_object_id(oid.as_ref()))
+    freshness: Freshness,
+) -> Result<(Vec<ChangesetId>, Vec<ObjectId>)> {
+    let oids_and_shas = oids
+        .map(|oid| {
+            let oid = oid.as_ref();
+            anyhow::Ok((oid.to_owned(), GitSha1::from_object_id(oid)?))
+        })
         .collect::<Result<Vec<_>>>()
         .context("Error while converting Git object Ids to Git Sha1 during fetch")?;
+    let shas = oids_and_shas
+        .iter()
+        .map(|(_oid, sha)| *sha)
+        .collect::<Vec<_>>();
     // Get the bonsai commits corresponding to the Git shas
     let entries = repo
         .bonsai_git_mapping()
-        .get(ctx, BonsaisOrGitShas::GitSha1(shas.clone()))
+        .get(ctx, BonsaisOrGitShas::GitSha1(shas))
         .await
         .with_context(|| {
             format!(
@@ -306,15 +575,24 @@ async fn git_shas_to_bonsais(
         })?;
     // Filter out the git shas for which we don't have an entry in the bonsai_git_mapping table
     // These are likely annotated tags which need to be resolved separately
-    let tag_shas = shas
-        .into_iter()
+    let tag_shas = oids_and_shas
+        .iter()
+        .map(|(_oid, sha)| *sha)
         .filter(|&sha| !entries.iter().any(|entry| entry.git_sha1 == sha))
         .collect::<Vec<_>>();
-    let mut commits_from_tags = tagged_commits(ctx, repo, tag_shas)
+    let (mut commits_from_tags, known_tag_shas) = tagged_commits(ctx, repo, tag_shas, freshness)
         .await
         .context("Error while resolving annotated tags to their commits")?;
-    commits_from_tags.extend(entries.into_iter().map(|entry| entry.bcs_id));
-    anyhow::Ok(commits_from_tags)
+    commits_from_tags.extend(entries.iter().map(|entry| entry.bcs_id));
+    // An oid is unknown if it's neither a commit in the bonsai_git_mapping nor a known tag
+    let unknown_oids = oids_and_shas
+        .into_iter()
+        .filter(|(_oid, sha)| {
+            !entries.iter().any(|entry| entry.git_sha1 == *sha) && !known_tag_shas.contains(sha)
+        })
+        .map(|(oid, _sha)| oid)
+        .collect::<Vec<_>>();
+    anyhow::Ok((commits_from_tags, unknown_oids))
 }
 
 /// Fetch the Bonsai Git Mappings for the given bonsais
@@ -342,34 +620,40 @@ async fn bonsai_git_mappings_by_bonsai(
 /// Get the list of Git refs that need to be included in the stream of PackfileItem. On Mononoke end, this
 /// will be bookmarks created from branches and tags. Branches and simple tags will be mapped to the
 /// Git commit that they point to. Annotated tags will be handled based on the `tag_inclusion` parameter
-async fn refs_to_include(
-    ctx: &CoreContext,
-    repo: &impl Repo,
+/// Build the ref-name-to-target mapping (plus the peeled-refs and tag-kind
+/// side tables) for a set of bookmarks, given their pre-fetched Git object ID
+/// and tag mappings. Split out from [`refs_to_include`] so the allocation-
+/// sensitive per-ref classification has no `Repo`/`CoreContext` dependency,
+/// which makes it straightforward to benchmark and test in isolation from the
+/// fetches that feed it. Each bookmark is stringified and formatted into its
+/// `refs/...` name at most once, and the resulting `String`s are reused (via
+/// clone of the already-computed value, not a fresh allocation) everywhere
+/// else they're needed.
+pub fn build_refs_to_include(
     bookmarks: &FxHashMap<BookmarkKey, ChangesetId>,
+    bonsai_git_map: &FxHashMap<ChangesetId, ObjectId>,
+    bonsai_tag_map: &FxHashMap<String, ObjectId>,
     tag_inclusion: TagInclusion,
-) -> Result<FxHashMap<String, RefTarget>> {
-    let bonsai_git_map =
-        bonsai_git_mappings_by_bonsai(ctx, repo, bookmarks.values().cloned().collect()).await?;
-    let bonsai_tag_map = repo
-        .bonsai_tag_mapping()
-        .get_all_entries()
-        .await
-        .with_context(|| {
-            format!(
-                "Error while fetching tag entries for repo {}",
-                repo.repo_identity().name()
-            )
-        })?
-        .into_iter()
-        .map(|entry| anyhow::Ok((entry.tag_name, entry.tag_hash.to_object_id()?)))
-        .collect::<Result<FxHashMap<_, _>>>()?;
-
-    bookmarks.iter().map(|(bookmark, cs_id)| {
+) -> Result<(
+    FxHashMap<String, RefTarget>,
+    FxHashMap<String, ObjectId>,
+    FxHashMap<String, TagKind>,
+)> {
+    let mut peeled_refs = FxHashMap::default();
+    let mut tag_kinds = FxHashMap::default();
+    let refs_to_include = bookmarks.iter().map(|(bookmark, cs_id)| {
+        let ref_name = format!("refs/{}", bookmark);
         if bookmark.is_tag() {
+            let bookmark_name = bookmark.to_string();
+            let tag_kind = if bonsai_tag_map.contains_key(&bookmark_name) {
+                TagKind::Annotated
+            } else {
+                TagKind::Lightweight
+            };
+            tag_kinds.insert(ref_name.clone(), tag_kind);
             match tag_inclusion {
                 TagInclusion::AsIs => {
-                    if let Some(git_objectid) = bonsai_tag_map.get(&bookmark.to_string()) {
-                        let ref_name = format!("refs/{}", bookmark);
+                    if let Some(git_objectid) = bonsai_tag_map.get(&bookmark_name) {
                         return anyhow::Ok((ref_name, RefTarget::Plain(git_objectid.clone())));
                     }
                 }
@@ -377,16 +661,15 @@ async fn refs_to_include(
                     let git_objectid = bonsai_git_map.get(cs_id).ok_or_else(|| {
                         anyhow::anyhow!("No Git ObjectId found for changeset {:?} during refs-to-include", cs_id)
                     })?;
-                    let ref_name = format!("refs/{}", bookmark);
                     return anyhow::Ok((ref_name, RefTarget::Plain(git_objectid.clone())));
                 }
                 TagInclusion::WithTarget => {
-                    if let Some(tag_objectid) = bonsai_tag_map.get(&bookmark.to_string()) {
+                    if let Some(tag_objectid) = bonsai_tag_map.get(&bookmark_name) {
                         let commit_objectid = bonsai_git_map.get(cs_id).ok_or_else(|| {
                             anyhow::anyhow!("No Git ObjectId found for changeset {:?} during refs-to-include", cs_id)
                         })?;
-                        let ref_name = format!("refs/{}", bookmark);
                         let metadata = format!("peeled:{}", commit_objectid.to_hex());
+                        peeled_refs.insert(ref_name.clone(), commit_objectid.clone());
                         return anyhow::Ok((
                             ref_name,
                             RefTarget::WithMetadata(tag_objectid.clone(), metadata),
@@ -400,27 +683,122 @@ async fn refs_to_include(
         let git_objectid = bonsai_git_map.get(cs_id).ok_or_else(|| {
             anyhow::anyhow!("No Git ObjectId found for changeset {:?} during refs-to-include", cs_id)
         })?;
-        let ref_name = format!("refs/{}", bookmark);
         anyhow::Ok((ref_name, RefTarget::Plain(git_objectid.clone())))
     })
-    .collect::<Result<FxHashMap<_, _>>>()
+    .collect::<Result<FxHashMap<_, _>>>()?;
+    Ok((refs_to_include, peeled_refs, tag_kinds))
 }
 
-/// Generate the appropriate RefTarget for symref based on the symref format
-fn symref_target(
-    symref_target: &str,
-    commit_id: ObjectId,
-    symref_format: SymrefFormat,
-) -> RefTarget {
+async fn refs_to_include(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    bookmarks: &FxHashMap<BookmarkKey, ChangesetId>,
+    tag_inclusion: TagInclusion,
+) -> Result<(
+    FxHashMap<String, RefTarget>,
+    FxHashMap<String, ObjectId>,
+    FxHashMap<String, TagKind>,
+)> {
+    let bonsai_git_map =
+        bonsai_git_mappings_by_bonsai(ctx, repo, bookmarks.values().cloned().collect()).await?;
+    let bonsai_tag_map = repo
+        .bonsai_tag_mapping()
+        .get_all_entries()
+        .await
+        .with_context(|| {
+            format!(
+                "Error while fetching tag entries for repo {}",
+                repo.repo_identity().name()
+            )
+        })?
+        .into_iter()
+        .map(|entry| anyhow::Ok((entry.tag_name, entry.tag_hash.to_object_id()?)))
+        .collect::<Result<FxHashMap<_, _>>>()?;
+
+    build_refs_to_include(bookmarks, &bonsai_git_map, &bonsai_tag_map, tag_inclusion)
+}
+
+/// Generate the appropriate RefTarget for symref based on the symref format.
+/// `chain` contains the ordered list of ref names that the symref resolves
+/// through, starting with its immediate target and ending with the final,
+/// non-symref target.
+fn symref_target(chain: &[String], commit_id: ObjectId, symref_format: SymrefFormat) -> RefTarget {
     match symref_format {
         SymrefFormat::NameWithTarget => {
-            let metadata = format!("symref-target:{}", symref_target);
+            let metadata = format!(
+                "symref-target:{}",
+                chain.first().map(String::as_str).unwrap_or_default()
+            );
+            RefTarget::WithMetadata(commit_id, metadata)
+        }
+        SymrefFormat::NameWithTargetChain => {
+            let metadata = chain
+                .iter()
+                .map(|target| format!("symref-target:{}", target))
+                .collect::<Vec<_>>()
+                .join(" ");
             RefTarget::WithMetadata(commit_id, metadata)
         }
         SymrefFormat::NameOnly => RefTarget::Plain(commit_id),
     }
 }
 
+/// Given a symref entry, follow the chain of symrefs (a symref whose target
+/// happens to be the name of another known symref) until reaching the final,
+/// non-symref target. Returns the ordered list of ref names that make up the
+/// chain, starting with the immediate target and ending with the final
+/// branch/tag ref. Guards against cycles by bounding the chain length to the
+/// number of known symrefs.
+fn resolve_symref_chain(
+    entry: &GitSymbolicRefsEntry,
+    symrefs_by_name: &FxHashMap<String, GitSymbolicRefsEntry>,
+) -> Vec<String> {
+    let mut chain = vec![entry.ref_name_with_type()];
+    let mut seen = FxHashSet::default();
+    seen.insert(entry.symref_name.clone());
+    let mut current = entry;
+    while let Some(next) = symrefs_by_name.get(&current.ref_name_with_type()) {
+        if !seen.insert(next.symref_name.clone()) {
+            // Cycle detected; stop following the chain.
+            break;
+        }
+        chain.push(next.ref_name_with_type());
+        current = next;
+    }
+    chain
+}
+
+/// Index a set of symref entries by name so that [`resolve_symref_chain`] can follow
+/// chains (a symref whose target is itself the name of another known symref).
+fn index_symrefs_by_name(
+    entries: &[GitSymbolicRefsEntry],
+) -> FxHashMap<String, GitSymbolicRefsEntry> {
+    entries
+        .iter()
+        .map(|entry| (entry.symref_name.clone(), entry.clone()))
+        .collect()
+}
+
+/// Fetch all known symrefs for `repo`, indexed by name as required by
+/// [`resolve_symref_chain`]. Used by any caller that needs to follow a symref chain for
+/// fewer than all symrefs (e.g. just HEAD, or a caller-specified subset), since chain
+/// resolution still needs to know about every symref a chain might pass through.
+async fn fetch_symrefs_by_name(
+    repo: &impl Repo,
+) -> Result<FxHashMap<String, GitSymbolicRefsEntry>> {
+    let symref_entries = repo
+        .git_symbolic_refs()
+        .list_all_symrefs()
+        .await
+        .with_context(|| {
+            format!(
+                "Error in getting all symrefs for repo {:?}",
+                repo.repo_identity().name()
+            )
+        })?;
+    Ok(index_symrefs_by_name(&symref_entries))
+}
+
 /// The HEAD ref in Git doesn't have a direct counterpart in Mononoke bookmarks and is instead
 /// stored in the git_symbolic_refs. Fetch the mapping and add them to the list of refs to include
 async fn include_symrefs(
@@ -447,9 +825,13 @@ async fn include_symrefs(
                         repo.repo_identity().name()
                     )
                 })?;
-            // Get the commit id pointed by the HEAD reference
+            // Index all known symrefs by name so that a HEAD chain (HEAD pointing at
+            // another symref, which may itself point at another, and so on) can be
+            // followed to its final, non-symref target
+            let symrefs_by_name = fetch_symrefs_by_name(repo).await?;
+            let chain = resolve_symref_chain(&head_ref, &symrefs_by_name);
             let head_commit_id = refs_to_include
-                .get(&head_ref.ref_name_with_type())
+                .get(chain.last().expect("chain always has at least one entry"))
                 .ok_or_else(|| {
                     anyhow::anyhow!(
                         "HEAD reference points to branch/tag {} which does not exist. Known refs: {:?}",
@@ -458,11 +840,7 @@ async fn include_symrefs(
                     )
                 })?
                 .id();
-            let ref_target = symref_target(
-                &head_ref.ref_name_with_type(),
-                head_commit_id.clone(),
-                symref_format,
-            );
+            let ref_target = symref_target(&chain, head_commit_id.clone(), symref_format);
             FxHashMap::from_iter([(head_ref.symref_name, ref_target)])
         }
         RequestedSymrefs::IncludeAll(symref_format) => {
@@ -477,10 +855,14 @@ async fn include_symrefs(
                         repo.repo_identity().name()
                     )
                 })?;
+            // Index the symrefs by name so that chains (a symref whose target
+            // is itself the name of another known symref) can be followed
+            let symrefs_by_name = index_symrefs_by_name(&symref_entries);
             // Get the commit ids pointed by each symref
             symref_entries.into_iter().map(|entry| {
+                let chain = resolve_symref_chain(&entry, &symrefs_by_name);
                 let ref_commit_id = refs_to_include
-                    .get(&entry.ref_name_with_type())
+                    .get(chain.last().expect("chain always has at least one entry"))
                     .ok_or_else(|| {
                         anyhow::anyhow!(
                             "{} reference points to branch/tag {} which does not exist. Known refs: {:?}",
@@ -490,10 +872,54 @@ async fn include_symrefs(
                         )
                     })?
                     .id();
-                let ref_target = symref_target(&entry.ref_name_with_type(), ref_commit_id.clone(), symref_format);
+                let ref_target = symref_target(&chain, ref_commit_id.clone(), symref_format);
                 Ok((entry.symref_name, ref_target))
             }).collect::<Result<FxHashMap<_, _>>>()?
         }
+        RequestedSymrefs::IncludeNamed(symref_names, symref_format) => {
+            // Index all known symrefs by name so that chains (a symref whose target is
+            // itself the name of another known symref) can be followed for the requested
+            // names too, not just for `IncludeAll`
+            let symrefs_by_name = fetch_symrefs_by_name(repo).await?;
+            // Get the branch/tag that each of the requested symrefs points to,
+            // erroring clearly if a requested name isn't actually a symref
+            let mut symref_commit_mapping = FxHashMap::default();
+            for symref_name in symref_names {
+                let entry = repo
+                    .git_symbolic_refs()
+                    .get_ref_by_symref(symref_name.clone())
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Error in getting {} reference for repo {:?}",
+                            &symref_name,
+                            repo.repo_identity().name()
+                        )
+                    })?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "{} is not a known symref for repo {:?}",
+                            &symref_name,
+                            repo.repo_identity().name()
+                        )
+                    })?;
+                let chain = resolve_symref_chain(&entry, &symrefs_by_name);
+                let commit_id = refs_to_include
+                    .get(chain.last().expect("chain always has at least one entry"))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "{} reference points to branch/tag {} which does not exist. Known refs: {:?}",
+                            &entry.symref_name,
+                            &entry.ref_name_with_type(),
+                            refs_to_include.keys()
+                        )
+                    })?
+                    .id();
+                let ref_target = symref_target(&chain, commit_id.clone(), symref_format);
+                symref_commit_mapping.insert(entry.symref_name, ref_target);
+            }
+            symref_commit_mapping
+        }
         RequestedSymrefs::ExcludeAll => FxHashMap::default(),
     };
 
@@ -522,12 +948,29 @@ impl ObjectIdentifierType {
     }
 }
 
-/// Fetch the raw content of the Git object based on the type of identifier provided
+/// Fetch the raw content of the Git object based on the type of identifier provided.
+/// If `max_object_size` is set, the object is never loaded into memory if it (or the
+/// bytes actually fetched for it) would exceed that limit; the fetch fails with an
+/// error instead
 async fn object_bytes(
     ctx: &CoreContext,
     repo: &impl Repo,
     id: ObjectIdentifierType,
+    max_object_size: Option<u64>,
 ) -> Result<Bytes> {
+    // When the identifier carries its own size (i.e. it's a RichGitSha1), reject the
+    // fetch upfront instead of loading a pathologically large object into memory just
+    // to find out it's too big.
+    if let (ObjectIdentifierType::AllObjects(sha), Some(max_object_size)) = (&id, max_object_size)
+    {
+        anyhow::ensure!(
+            sha.size() <= max_object_size,
+            "Git object {} has size {} bytes which exceeds the configured max object size of {} bytes",
+            sha.to_hex(),
+            sha.size(),
+            max_object_size,
+        );
+    }
     let blobstore = repo.repo_blobstore_arc();
     let bytes = match id {
         ObjectIdentifierType::AllObjects(sha) => {
@@ -538,11 +981,19 @@ async fn object_bytes(
             fetch_git_object_bytes(ctx, blobstore.clone(), &sha, HeaderState::Included).await?
         }
         ObjectIdentifierType::NonBlobObjects(oid) => {
-            // The object identifier has only been passed with an ObjectId. This means that it must be a
-            // non-blob Git object that can be fetched directly from the blobstore.
+            // The object identifier has only been passed with an ObjectId, so its size isn't known
+            // upfront. Fetch it and check its size afterwards instead.
             fetch_non_blob_git_object_bytes(ctx, &blobstore, oid.as_ref()).await?
         }
     };
+    if let Some(max_object_size) = max_object_size {
+        anyhow::ensure!(
+            (bytes.len() as u64) <= max_object_size,
+            "Git object has size {} bytes which exceeds the configured max object size of {} bytes",
+            bytes.len(),
+            max_object_size,
+        );
+    }
     Ok(bytes)
 }
 
@@ -553,18 +1004,22 @@ async fn base_packfile_item(
     repo: &impl Repo,
     id: ObjectIdentifierType,
     packfile_item_inclusion: PackfileItemInclusion,
+    max_object_size: Option<u64>,
 ) -> Result<PackfileItem> {
     let blobstore = repo.repo_blobstore_arc();
     let git_objectid = id.to_object_id()?;
     match packfile_item_inclusion {
         // Generate the packfile item based on the raw commit object
         PackfileItemInclusion::Generate => {
-            let object_bytes = object_bytes(ctx, repo, id).await.with_context(|| {
-                format!(
-                    "Error in fetching raw git object bytes for object {:?} while generating packfile item",
-                    &git_objectid
-                )
-            })?;
+            STATS::base_packfile_item_generated.add_value(1);
+            let object_bytes = object_bytes(ctx, repo, id, max_object_size)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Error in fetching raw git object bytes for object {:?} while generating packfile item",
+                        &git_objectid
+                    )
+                })?;
             let packfile_item = PackfileItem::new_base(object_bytes).with_context(|| {
                 format!(
                     "Error in creating packfile item from git object bytes for {:?}",
@@ -575,6 +1030,7 @@ async fn base_packfile_item(
         }
         // Return the stored packfile item if it exists, otherwise error out
         PackfileItemInclusion::FetchOnly => {
+            STATS::base_packfile_item_fetched.add_value(1);
             let packfile_base_item =
                 fetch_packfile_base_item(ctx, &blobstore, git_objectid.as_ref())
                     .await
@@ -603,16 +1059,22 @@ async fn base_packfile_item(
                 )
             })?;
             match fetch_result {
-                Some(packfile_base_item) => anyhow::Ok(PackfileItem::new_encoded_base(
-                    packfile_base_item.try_into()?,
-                )),
+                Some(packfile_base_item) => {
+                    STATS::base_packfile_item_fetch_and_store_hit.add_value(1);
+                    anyhow::Ok(PackfileItem::new_encoded_base(
+                        packfile_base_item.try_into()?,
+                    ))
+                }
                 None => {
-                    let object_bytes = object_bytes(ctx, repo, id).await.with_context(|| {
-                        format!(
-                            "Error in fetching raw git object bytes for object {:?} while fetching-and-storing packfile item",
-                            &git_objectid
-                        )
-                    })?;
+                    STATS::base_packfile_item_fetch_and_store_miss.add_value(1);
+                    let object_bytes = object_bytes(ctx, repo, id, max_object_size)
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "Error in fetching raw git object bytes for object {:?} while fetching-and-storing packfile item",
+                                &git_objectid
+                            )
+                        })?;
                     let packfile_base_item = upload_packfile_base_item(
                         ctx,
                         &blobstore,
@@ -626,6 +1088,29 @@ async fn base_packfile_item(
                 }
             }
         }
+        // Always generate the packfile item afresh and store it, overwriting any
+        // previously stored item for this git object
+        PackfileItemInclusion::GenerateAndStore => {
+            STATS::base_packfile_item_generated_and_stored.add_value(1);
+            let object_bytes = object_bytes(ctx, repo, id, max_object_size)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Error in fetching raw git object bytes for object {:?} while generating-and-storing packfile item",
+                        &git_objectid
+                    )
+                })?;
+            let packfile_base_item = upload_packfile_base_item(
+                ctx,
+                &blobstore,
+                git_objectid.as_ref(),
+                object_bytes.to_vec(),
+            )
+            .await?;
+            anyhow::Ok(PackfileItem::new_encoded_base(
+                packfile_base_item.try_into()?,
+            ))
+        }
     }
 }
 
@@ -638,10 +1123,12 @@ async fn packfile_entry(
     changeset_id: ChangesetId,
     path: MPath,
     mut entry: GitDeltaManifestEntry,
+    max_object_size: Option<u64>,
+    exclude_delta_for: &Arc<FxHashSet<ObjectId>>,
 ) -> Result<PackfileItem> {
     let blobstore = repo.repo_blobstore_arc();
     // Determine if the delta variant should be used or the base variant
-    let delta = delta_base(&mut entry, delta_inclusion);
+    let delta = delta_base(&mut entry, delta_inclusion, exclude_delta_for);
     match delta {
         Some(delta) => {
             let chunk_id_prefix =
@@ -678,20 +1165,59 @@ async fn packfile_entry(
                 repo,
                 ObjectIdentifierType::AllObjects(entry.full.as_rich_git_sha1()?),
                 packfile_item_inclusion,
+                max_object_size,
             )
             .await
         }
     }
 }
 
-/// Fetch the stream of blob and tree objects as packfile items for the given changeset
-async fn blob_and_tree_packfile_items<'a>(
-    ctx: &'a CoreContext,
-    repo: &'a impl Repo,
+/// The smallest delta candidate (if any) for an object, along with whether it clears
+/// the inclusion threshold. This is the delta `packfile_entry` chooses under
+/// `DeltaBasePreference::SmallestSize` with no `exclude_delta_for` entry for the object;
+/// `base_preference` and `exclude_delta_for` are not taken into account here.
+#[derive(Debug, Clone)]
+pub struct DeltaDiagnostics {
+    /// The object id of the object the delta is based against
+    pub base_oid: ObjectId,
+    /// The size of the delta instructions before Zlib compression
+    pub instructions_uncompressed_size: u64,
+    /// The size of the delta instructions after Zlib compression
+    pub instructions_compressed_size: u64,
+    /// Whether this delta passed `delta_below_threshold`, i.e. whether `packfile_entry`
+    /// would actually emit it instead of falling back to the full object
+    pub below_threshold: bool,
+}
+
+/// Diagnostic summary of how `packfile_entry` would represent a single Git object
+/// (identified by its changeset and path) in a packfile: as a delta against some base
+/// object, or as a full object.
+#[derive(Debug, Clone)]
+pub struct PackfileEntryDiagnostics {
+    /// The object id of the object being diagnosed
+    pub oid: ObjectId,
+    /// The uncompressed size of the full object
+    pub full_size: u64,
+    /// The best delta candidate considered for this object, if the manifest recorded
+    /// any and `delta_inclusion` allows deltas at all. Note this is populated even if
+    /// `below_threshold` is false, i.e. even if `packfile_entry` would not actually
+    /// choose it.
+    pub delta: Option<DeltaDiagnostics>,
+}
+
+/// Inspect how `packfile_entry` would represent the Git object at `path` in `changeset_id`
+/// without materializing it, i.e. without fetching delta instruction bytes or full object
+/// content. Returns `None` if there is no entry for `path` in the commit's
+/// GitDeltaManifest. Intended as a debugging entry point for scripting delta-quality
+/// audits across a repo, e.g. to find paths whose best delta candidate narrowly misses
+/// the inclusion threshold.
+pub async fn packfile_entry_diagnostics(
+    ctx: &CoreContext,
+    repo: &impl Repo,
     delta_inclusion: DeltaInclusion,
-    packfile_item_inclusion: PackfileItemInclusion,
     changeset_id: ChangesetId,
-) -> Result<BoxStream<'a, Result<PackfileItem>>> {
+    path: &MPath,
+) -> Result<Option<PackfileEntryDiagnostics>> {
     let blobstore = repo.repo_blobstore_arc();
     let root_mf_id = repo
         .repo_derived_data()
@@ -713,18 +1239,144 @@ async fn blob_and_tree_packfile_items<'a>(
                 root_mf_id
             )
         })?;
+    let mut entry = match delta_manifest.lookup(ctx, &blobstore, path).await? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let inclusion_threshold = match delta_inclusion {
+        DeltaInclusion::Include {
+            inclusion_threshold,
+            ..
+        } => Some(inclusion_threshold),
+        DeltaInclusion::Exclude => None,
+    };
+    entry
+        .deltas
+        .sort_by(|a, b| a.instructions_compressed_size.cmp(&b.instructions_compressed_size));
+    let delta = match (entry.deltas.first(), inclusion_threshold) {
+        (Some(delta), Some(inclusion_threshold)) => Some(DeltaDiagnostics {
+            base_oid: delta.base.oid,
+            instructions_uncompressed_size: delta.instructions_uncompressed_size,
+            instructions_compressed_size: delta.instructions_compressed_size,
+            below_threshold: delta_below_threshold(delta, &entry, inclusion_threshold),
+        }),
+        _ => None,
+    };
+    Ok(Some(PackfileEntryDiagnostics {
+        oid: entry.full.oid,
+        full_size: entry.full.size,
+        delta,
+    }))
+}
+
+/// Every delta candidate recorded for a single commit's Git object at some path, without
+/// filtering down to the one `packfile_entry` would pick. Unlike `DeltaDiagnostics` (which
+/// `packfile_entry_diagnostics` only populates for the best candidate), this keeps every
+/// base considered so they can all be reviewed.
+#[derive(Debug, Clone)]
+pub struct PathDeltaHistoryEntry {
+    /// The commit this entry's GitDeltaManifest was derived from
+    pub changeset_id: ChangesetId,
+    /// The object id of the full object at this commit
+    pub oid: ObjectId,
+    /// The uncompressed size of the full object at this commit
+    pub full_size: u64,
+    /// Every delta candidate recorded for this object in its GitDeltaManifest entry,
+    /// in the manifest's original order (not sorted by size, unlike
+    /// `packfile_entry_diagnostics`)
+    pub deltas: Vec<ObjectDelta>,
+}
+
+/// For each commit in `commits` that has a GitDeltaManifest entry for `path`, return every
+/// delta candidate recorded for it (bases and compressed sizes), without filtering down to
+/// the one `packfile_entry` would actually pick. Commits where `path` has no entry (e.g. it
+/// wasn't touched by that commit) are omitted.
+///
+/// Intended for debugging why a path isn't delta-compressing well across its history:
+/// compare the listed candidates' `instructions_compressed_size` against `full_size` to see
+/// whether a good base was actually recorded that `delta_below_threshold` is merely
+/// rejecting for some `inclusion_threshold`, as opposed to no good base existing at all.
+pub async fn path_delta_history(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    commits: &[ChangesetId],
+    path: &MPath,
+) -> Result<Vec<PathDeltaHistoryEntry>> {
+    let mut history = Vec::with_capacity(commits.len());
+    for &changeset_id in commits {
+        let entries = delta_manifest_entries(ctx, repo, changeset_id).await?;
+        if let Some((_, entry)) = entries.iter().find(|(entry_path, _)| entry_path == path) {
+            history.push(PathDeltaHistoryEntry {
+                changeset_id,
+                oid: entry.full.oid,
+                full_size: entry.full.size,
+                deltas: entry.deltas.clone(),
+            });
+        }
+    }
+    Ok(history)
+}
+
+/// The largest weight a single `acquire_many_owned` call can request, since
+/// `tokio::sync::Semaphore` counts permits with a `u32`.
+const MAX_BYTE_BUDGET_PERMITS: u64 = u32::MAX as u64;
+
+/// Estimate the in-flight bytes a packfile item for `entry` will use, for the purposes of
+/// `PackfileConcurrency::per_object_bytes_budget`. This is the largest of the full object's
+/// size and its delta candidates' compressed sizes, since that's an upper bound on what
+/// `packfile_entry` will actually materialize: it always picks the full object or one of
+/// the delta candidates, never something larger than all of them.
+fn estimated_packfile_item_bytes(entry: &GitDeltaManifestEntry) -> u64 {
+    entry
+        .deltas
+        .iter()
+        .map(|delta| delta.instructions_compressed_size)
+        .chain(std::iter::once(entry.full.size))
+        .max()
+        .expect("chain always yields at least entry.full.size")
+}
+
+/// Fetch the stream of blob and tree objects as packfile items for the given changeset
+async fn blob_and_tree_packfile_items<'a>(
+    ctx: &'a CoreContext,
+    repo: &'a impl Repo,
+    delta_inclusion: DeltaInclusion,
+    packfile_item_inclusion: PackfileItemInclusion,
+    changeset_id: ChangesetId,
+    concurrency: PackfileConcurrency,
+    max_object_size: Option<u64>,
+    exclude_delta_for: Arc<FxHashSet<ObjectId>>,
+) -> Result<BoxStream<'a, Result<PackfileItem>>> {
+    let entries = delta_manifest_entries(ctx, repo, changeset_id).await?;
+    // Bounds the total estimated bytes of objects that are being fetched at once, on top of
+    // `per_object_concurrency`'s item-count bound, so a burst of large objects can't spike
+    // memory the way a pure item-count limit would allow.
+    let byte_budget_limit = concurrency
+        .per_object_bytes_budget
+        .min(MAX_BYTE_BUDGET_PERMITS);
+    let byte_budget = Arc::new(Semaphore::new(byte_budget_limit as usize));
     let objects_stream = try_stream! {
-        let mut entries = Box::pin(delta_manifest.into_subentries(ctx, &blobstore).ready_chunks(1000));
         // NOTE: The order of the entries needs to be maintained
-        while let Some(entries) = entries.next().await {
-            for entry in entries {
-                let (path, entry) = entry?;
-                let packfile_item = packfile_entry(ctx, repo, delta_inclusion, packfile_item_inclusion, changeset_id, path, entry);
-                yield packfile_item
-            }
+        for (path, entry) in entries.iter().cloned() {
+            // Clamp so a single object larger than the whole budget can still make
+            // progress, just without anything else running alongside it.
+            let weight = estimated_packfile_item_bytes(&entry).clamp(1, byte_budget_limit) as u32;
+            let byte_budget = Arc::clone(&byte_budget);
+            let packfile_item = async move {
+                let _permit = byte_budget
+                    .acquire_many_owned(weight)
+                    .await
+                    .context("byte budget semaphore should never be closed")?;
+                packfile_entry(ctx, repo, delta_inclusion, packfile_item_inclusion, changeset_id, path, entry, max_object_size, &exclude_delta_for).await
+            };
+            yield packfile_item
         }
     };
-    anyhow::Ok(objects_stream.try_buffered(1000).boxed())
+    anyhow::Ok(
+        objects_stream
+            .try_buffered(concurrency.per_object_concurrency)
+            .boxed(),
+    )
 }
 
 /// Create a stream of packfile items containing blob and tree objects that need to be included in the packfile/bundle.
@@ -735,6 +1387,9 @@ async fn blob_and_tree_packfile_stream<'a>(
     target_commits: BoxStream<'a, Result<ChangesetId>>,
     delta_inclusion: DeltaInclusion,
     packfile_item_inclusion: PackfileItemInclusion,
+    concurrency: PackfileConcurrency,
+    max_object_size: Option<u64>,
+    exclude_delta_for: Arc<FxHashSet<ObjectId>>,
 ) -> Result<BoxStream<'a, Result<PackfileItem>>> {
     // Get the packfile items corresponding to blob and tree objects in the repo. Where applicable, use delta to represent them
     // efficiently in the packfile/bundle
@@ -746,9 +1401,12 @@ async fn blob_and_tree_packfile_stream<'a>(
                 delta_inclusion,
                 packfile_item_inclusion,
                 changeset_id,
+                concurrency,
+                max_object_size,
+                Arc::clone(&exclude_delta_for),
             )
         })
-        .try_buffered(500)
+        .try_buffered(concurrency.per_commit_concurrency)
         .try_flatten()
         .boxed();
     Ok(packfile_item_stream)
@@ -760,6 +1418,7 @@ async fn commit_packfile_stream<'a>(
     repo: &'a impl Repo,
     target_commits: BoxStream<'a, Result<ChangesetId>>,
     packfile_item_inclusion: PackfileItemInclusion,
+    max_object_size: Option<u64>,
 ) -> Result<BoxStream<'a, Result<PackfileItem>>> {
     let commit_stream = target_commits
         .map_ok(move |changeset_id| async move {
@@ -782,6 +1441,7 @@ async fn commit_packfile_stream<'a>(
                 repo,
                 ObjectIdentifierType::NonBlobObjects(git_objectid), // Since we know its not a blob
                 packfile_item_inclusion,
+                max_object_size,
             )
             .await
         })
@@ -796,6 +1456,7 @@ fn tag_entries_to_stream<'a>(
     repo: &'a impl Repo,
     tag_entries: Vec<BonsaiTagMappingEntry>,
     packfile_item_inclusion: PackfileItemInclusion,
+    max_object_size: Option<u64>,
 ) -> BoxStream<'a, Result<PackfileItem>> {
     stream::iter(tag_entries.into_iter().map(anyhow::Ok))
         .map_ok(move |entry| async move {
@@ -805,6 +1466,7 @@ fn tag_entries_to_stream<'a>(
                 repo,
                 ObjectIdentifierType::NonBlobObjects(git_objectid), // Since we know its not a blob
                 packfile_item_inclusion,
+                max_object_size,
             )
             .await
         })
@@ -822,8 +1484,10 @@ async fn tag_packfile_stream<'a>(
 ) -> Result<(BoxStream<'a, Result<PackfileItem>>, usize)> {
     // Since we need the count of items, we would have to consume the stream either for counting or collecting the items.
     // This is fine, since unlike commits, blobs and trees there will only be thousands of tags in the worst case.
+    // Resolve the bonsai_tag_mapping lookups concurrently instead of one at a time, since each lookup is an
+    // independent round-trip to the store.
     let annotated_tags = stream::iter(bookmarks.keys())
-        .filter_map(|bookmark| async move {
+        .map(|bookmark| async move {
             // If the bookmark is actually a tag but there is no mapping in bonsai_tag_mapping table for it, then it
             // means that its a simple tag and won't be included in the packfile as an object. If a mapping exists, then
             // it will be included in the packfile as a raw Git object
@@ -838,33 +1502,53 @@ async fn tag_packfile_stream<'a>(
                             tag_name
                         )
                     })
-                    .transpose()
             } else {
-                None
+                Ok(None)
             }
         })
+        .buffer_unordered(100)
+        .try_filter_map(|entry| future::ready(anyhow::Ok(entry)))
         .try_collect::<Vec<_>>()
         .await?;
     let tags_count = annotated_tags.len();
     let packfile_item_inclusion = request.packfile_item_inclusion;
-    let tag_stream = tag_entries_to_stream(ctx, repo, annotated_tags, packfile_item_inclusion);
+    let tag_stream = tag_entries_to_stream(
+        ctx,
+        repo,
+        annotated_tags,
+        packfile_item_inclusion,
+        request.max_object_size,
+    );
     anyhow::Ok((tag_stream, tags_count))
 }
 
-/// Create a stream of packfile items containing annotated tag objects that exist in the repo
+/// Create a stream of packfile items containing annotated tag objects that exist in the repo.
+/// If `target_commits` is provided, the tags are filtered down to only those whose target
+/// commit is present in `target_commits`, saving the client from receiving tags for commits
+/// it has no use for. When `target_commits` is `None`, all annotated tags are included
 async fn all_tags_packfile_stream<'a>(
     ctx: &'a CoreContext,
     repo: &'a impl Repo,
+    target_commits: Option<&HashSet<ChangesetId>>,
+    max_object_size: Option<u64>,
 ) -> Result<(BoxStream<'a, Result<PackfileItem>>, usize)> {
     // Fetch entries corresponding to annotated tags in the repo
-    let tag_entries = repo
+    let mut tag_entries = repo
         .bonsai_tag_mapping()
         .get_all_entries()
         .await
         .context("Error in getting tags during fetch")?;
+    if let Some(target_commits) = target_commits {
+        tag_entries.retain(|entry| target_commits.contains(&entry.changeset_id));
+    }
     let tags_count = tag_entries.len();
-    let tag_stream =
-        tag_entries_to_stream(ctx, repo, tag_entries, PackfileItemInclusion::FetchAndStore);
+    let tag_stream = tag_entries_to_stream(
+        ctx,
+        repo,
+        tag_entries,
+        PackfileItemInclusion::FetchAndStore,
+        max_object_size,
+    );
     anyhow::Ok((tag_stream, tags_count))
 }
 
@@ -876,7 +1560,7 @@ pub async fn generate_pack_item_stream<'a>(
     request: PackItemStreamRequest,
 ) -> Result<PackItemStreamResponse<'a>> {
     // We need to include the bookmarks (i.e. branches, tags) in the pack based on the request parameters
-    let bookmarks = bookmarks(ctx, repo, &request.requested_refs)
+    let bookmarks = bookmarks(ctx, repo, &request.requested_refs, request.freshness)
         .await
         .with_context(|| {
             format!(
@@ -898,21 +1582,41 @@ pub async fn generate_pack_item_stream<'a>(
         .await?;
     // Reverse the list of commits so that we can prevent delta cycles from appearing in the packfile
     target_commits.reverse();
-    let commits_count = target_commits.len();
+    // If the request is resuming a previously interrupted stream, skip past the commits
+    // that the client has already received
+    if let Some(after) = request.after {
+        if let Some(pos) = target_commits.iter().position(|cs_id| *cs_id == after) {
+            target_commits.drain(..=pos);
+        }
+    }
     // STEP 1: Get the count of distinct blob and tree objects to be included in the packfile/bundle.
-    let trees_and_blobs_count =
-        trees_and_blobs_count(ctx, repo, to_commit_stream(target_commits.clone()))
-            .await
-            .context("Error while calculating object count")?;
+    // This count ends up in the packfile's header, so it must be exact.
+    let (trees_and_blobs_count, _trees_and_blobs_size, skipped_commits) = trees_and_blobs_stats(
+        ctx,
+        repo,
+        to_commit_stream(target_commits.clone()),
+        request.concurrency,
+        true, /* exact */
+        request.tolerate_derivation_errors,
+    )
+    .await
+    .context("Error while calculating object count")?;
+    // Exclude commits skipped above from the stream so that the object count calculated as
+    // part of STEP 1 stays consistent with the packfile items actually generated below
+    if !skipped_commits.is_empty() {
+        target_commits.retain(|changeset_id| !skipped_commits.contains(changeset_id));
+    }
+    let commits_count = target_commits.len();
 
     // STEP 2: Create a mapping of all known bookmarks (i.e. branches, tags) and the commit that they point to. The commit should be represented
     // as a Git hash instead of a Bonsai hash since it will be part of the packfile/bundle
-    let mut refs_to_include = refs_to_include(ctx, repo, &bookmarks, request.tag_inclusion)
-        .await
-        .context("Error while determining refs to include in the pack")?;
+    let (mut refs_to_include, _peeled_refs, _tag_kinds) =
+        refs_to_include(ctx, repo, &bookmarks, request.tag_inclusion)
+            .await
+            .context("Error while determining refs to include in the pack")?;
 
     // STEP 2.5: Add symrefs to the refs_to_include map based on the request parameters
-    include_symrefs(repo, request.requested_symrefs, &mut refs_to_include)
+    include_symrefs(repo, request.requested_symrefs.clone(), &mut refs_to_include)
         .await
         .context("Error while adding symrefs to included set of refs")?;
 
@@ -924,9 +1628,28 @@ pub async fn generate_pack_item_stream<'a>(
         to_commit_stream(target_commits.clone()),
         request.delta_inclusion,
         request.packfile_item_inclusion,
+        request.concurrency,
+        request.max_object_size,
+        Arc::new(FxHashSet::default()),
     )
     .await
     .context("Error while generating blob and tree packfile item stream")?;
+    // When the pack can only contain offset deltas, a delta's base may come from a
+    // different (earlier) commit than the delta itself, which `blob_and_tree_stream`
+    // doesn't otherwise guarantee appears first. Materialize the stream and reorder it
+    // topologically over delta base dependencies so `PackfileWriter` can always resolve a
+    // base's offset by the time it gets to the delta referencing it.
+    let blob_and_tree_stream = if request.delta_inclusion.include_only_offset_deltas() {
+        let items = blob_and_tree_stream
+            .try_collect::<Vec<_>>()
+            .await
+            .context("Error while collecting blob and tree packfile items for offset-delta ordering")?;
+        let items = order_for_offset_deltas(items)
+            .context("Error while ordering packfile items for offset deltas")?;
+        stream::iter(items.into_iter().map(Ok)).boxed()
+    } else {
+        blob_and_tree_stream
+    };
 
     // STEP 4: Get the stream of commit packfile items to include in the pack/bundle. Note that we have already counted these items
     // as part of object count.
@@ -935,6 +1658,7 @@ pub async fn generate_pack_item_stream<'a>(
         repo,
         to_commit_stream(target_commits.clone()),
         request.packfile_item_inclusion,
+        request.max_object_size,
     )
     .await
     .context("Error while generating commit packfile item stream")?;
@@ -945,22 +1669,112 @@ pub async fn generate_pack_item_stream<'a>(
         .await
         .context("Error while generating tag packfile item stream")?;
     // Compute the overall object count by summing the trees, blobs, tags and commits count
-    let object_count = commits_count + trees_and_blobs_count + tags_count;
+    let object_counts =
+        PackfileObjectCounts::new(commits_count, trees_and_blobs_count, tags_count);
 
-    // STEP 6: Combine all streams together and return the response. The ordering of the streams in this case is irrelevant since the commit
-    // and tag stream include full objects and the blob_and_tree_stream has deltas in the correct order
-    let packfile_stream = tag_stream
-        .chain(commit_stream)
-        .chain(blob_and_tree_stream)
-        .boxed();
+    // STEP 6: Combine all streams together and return the response.
+    // `CommitsFirst` guarantees that tags and commits precede the trees and blobs they
+    // reference, which strict bundle/pack readers (e.g. `git index-pack --strict`) require.
+    // `Unordered` interleaves the streams instead, which can reduce overall latency since the
+    // (usually much larger) blob_and_tree_stream no longer has to wait for the tag and commit
+    // streams to fully drain first, at the cost of that ordering guarantee.
+    let packfile_stream = match request.object_order {
+        PackfileItemStreamOrdering::CommitsFirst => tag_stream
+            .chain(commit_stream)
+            .chain(blob_and_tree_stream)
+            .boxed(),
+        PackfileItemStreamOrdering::Unordered => {
+            stream::select_all([tag_stream, commit_stream, blob_and_tree_stream]).boxed()
+        }
+    };
+    // Run the consistency check before cancellation can truncate the stream, so that a
+    // routine client disconnect (which makes the `take_while` below stop polling, and
+    // therefore stop driving the check's loop, before it reaches its own end-of-stream
+    // comparison) is never misreported as an object-count mismatch.
+    let packfile_stream =
+        verify_object_count_packfile_stream(ctx, packfile_stream, object_counts.total());
+    let packfile_stream = cancellable_packfile_stream(packfile_stream, request.cancellation_token);
     let response = PackItemStreamResponse::new(
         packfile_stream,
-        object_count,
+        object_counts.total(),
+        object_counts,
         refs_to_include.into_iter().collect(),
     );
     Ok(response)
 }
 
+/// Run the same resolution performed by [`generate_pack_item_stream`] (bonsai-to-git
+/// mapping, tree/blob manifest derivation, ref resolution) for the given request
+/// without constructing any `PackfileItem`s, and report any objects that failed to
+/// resolve. Useful for an operator health check that wants to catch "No Git ObjectId
+/// found" and missing-manifest errors proactively, before a client ever requests the pack
+pub async fn validate_pack_item_stream(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    request: &PackItemStreamRequest,
+) -> Result<PackValidationReport> {
+    let bookmarks = bookmarks(ctx, repo, &request.requested_refs, request.freshness)
+        .await
+        .with_context(|| {
+            format!(
+                "Error in fetching bookmarks for repo {}",
+                repo.repo_identity().name()
+            )
+        })?;
+    let mut target_commits = repo
+        .commit_graph()
+        .ancestors_difference_stream(
+            ctx,
+            bookmarks.values().copied().collect(),
+            request.have_heads.clone(),
+        )
+        .await
+        .context("Error in getting ancestors difference while validating packitem stream")?
+        .try_collect::<Vec<_>>()
+        .await?;
+    if let Some(after) = request.after {
+        if let Some(pos) = target_commits.iter().position(|cs_id| *cs_id == after) {
+            target_commits.drain(..=pos);
+        }
+    }
+
+    let bonsai_git_map =
+        bonsai_git_mappings_by_bonsai(ctx, repo, target_commits.iter().cloned().collect())
+            .await
+            .context("Error while validating bonsai to git mapping for target commits")?;
+    let missing_git_objects = target_commits
+        .iter()
+        .filter(|cs_id| !bonsai_git_map.contains_key(cs_id))
+        .cloned()
+        .collect();
+
+    let manifest_errors = match trees_and_blobs_stats(
+        ctx,
+        repo,
+        to_commit_stream(target_commits.clone()),
+        request.concurrency,
+        true,  /* exact */
+        false, /* tolerate_derivation_errors: surface these errors in the report instead */
+    )
+    .await
+    {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![e.to_string()],
+    };
+
+    let unresolved_refs =
+        match refs_to_include(ctx, repo, &bookmarks, request.tag_inclusion).await {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        };
+
+    Ok(PackValidationReport {
+        missing_git_objects,
+        manifest_errors,
+        unresolved_refs,
+    })
+}
+
 /// Based on the input request parameters, generate the response to the
 /// ls-refs request command
 pub async fn ls_refs_response(
@@ -969,7 +1783,7 @@ pub async fn ls_refs_response(
     request: LsRefsRequest,
 ) -> Result<LsRefsResponse> {
     // We need to include the bookmarks (i.e. branches, tags) based on the request parameters
-    let bookmarks = bookmarks(ctx, repo, &request.requested_refs)
+    let bookmarks = bookmarks(ctx, repo, &request.requested_refs, request.freshness)
         .await
         .with_context(|| {
             format!(
@@ -977,33 +1791,91 @@ pub async fn ls_refs_response(
                 repo.repo_identity().name()
             )
         })?;
+    // Bail out before materializing the (potentially unbounded) refs_to_include map if the
+    // number of refs to include would exceed the configured limit
+    if let Some(max_ref_count) = request.max_ref_count {
+        if (bookmarks.len() as u64) > max_ref_count {
+            return Err(GitProtocolError::TooManyRefs(bookmarks.len(), max_ref_count).into());
+        }
+    }
     // Convert the above bookmarks into refs that can be sent in the response
-    let mut refs_to_include = refs_to_include(ctx, repo, &bookmarks, request.tag_inclusion)
-        .await
-        .context("Error while determining refs to include in the response")?;
+    let (mut refs_to_include, peeled_refs, tag_kinds) =
+        refs_to_include(ctx, repo, &bookmarks, request.tag_inclusion)
+            .await
+            .context("Error while determining refs to include in the response")?;
 
     // Add symrefs to the refs_to_include map based on the request parameters
-    include_symrefs(repo, request.requested_symrefs, &mut refs_to_include)
+    include_symrefs(repo, request.requested_symrefs.clone(), &mut refs_to_include)
         .await
         .context("Error while adding symrefs to included set of refs")?;
 
-    Ok(LsRefsResponse::new(refs_to_include.into_iter().collect()))
+    // Each requested bundle URI is advertised as covering the exact same set of refs
+    // that were just computed above, since that's the set of refs a bundle generated
+    // from this request's parameters would have baked into it.
+    let bundle_uris = request
+        .bundle_uris
+        .into_iter()
+        .map(|uri| BundleUri {
+            uri,
+            refs: refs_to_include.clone().into_iter().collect(),
+        })
+        .collect();
+
+    Ok(LsRefsResponse::new(
+        refs_to_include.into_iter().collect(),
+        peeled_refs.into_iter().collect(),
+        tag_kinds.into_iter().collect(),
+        bundle_uris,
+    ))
 }
 
+/// Approximate average size (in bytes) of an encoded Git commit object, including
+/// packfile item framing. Used only to cheaply estimate the total packfile size for a
+/// dry-run fetch request, where commit objects are not actually fetched
+const AVERAGE_COMMIT_OBJECT_SIZE_BYTES: u64 = 300;
+
+/// Approximate average size (in bytes) of an encoded annotated Git tag object,
+/// including packfile item framing. Used only to cheaply estimate the total packfile
+/// size for a dry-run fetch request, where tag objects are not actually fetched
+const AVERAGE_TAG_OBJECT_SIZE_BYTES: u64 = 200;
+
 /// Based on the input request parameters, generate the response to the
 /// fetch request command
+///
+/// Contract for the `bases`/`heads` edge cases:
+/// * Empty `heads` means the client isn't asking for anything: regardless of
+///   `bases` or `include_annotated_tags`, the response is an empty-but-valid pack
+///   (zero objects, just the packfile trailer once written), not "every tag in the repo".
+/// * Empty `bases` with non-empty `heads` is a full clone: every ancestor of `heads` is
+///   included, which `ancestors_difference_stream` already does with no special-casing.
+/// * Both empty is just the empty-`heads` case above.
+/// * A `have` in `bases` that doesn't exist in this repo (neither as a commit nor as a
+///   tag) is excluded from the ancestry computation, as before, but is also reported back
+///   via `FetchResponse::unknown_bases` so the negotiation layer can tell a bogus `have`
+///   apart from one that is simply not an ancestor of the requested `heads`.
 pub async fn fetch_response<'a>(
     ctx: &'a CoreContext,
     repo: &'a impl Repo,
     request: FetchRequest,
 ) -> Result<FetchResponse<'a>> {
-    let delta_inclusion = DeltaInclusion::standard();
+    if request.heads.is_empty() {
+        // Nothing was asked for, so nothing (not even unrelated tags) should come back.
+        let object_counts = PackfileObjectCounts::new(0, 0, 0);
+        if request.dry_run {
+            return Ok(FetchResponse::dry_run(object_counts, 0));
+        }
+        return Ok(FetchResponse::new(stream::empty().boxed(), 0, object_counts));
+    }
+    let delta_inclusion = request.delta_inclusion.unwrap_or_else(DeltaInclusion::standard);
     let packfile_item_inclusion = PackfileItemInclusion::FetchAndStore;
-    // Convert the base commits and head commits, which are represented as Git hashes, into Bonsai hashes
-    let bases = git_shas_to_bonsais(ctx, repo, request.bases.iter())
-        .await
-        .context("Error converting base Git commits to Bonsai duing fetch")?;
-    let heads = git_shas_to_bonsais(ctx, repo, request.heads.iter())
+    // Convert the base commits and head commits, which are represented as Git hashes, into Bonsai hashes.
+    // Bases that don't exist in the repo (neither as a commit nor as a tag) are reported back to the
+    // caller instead of being silently dropped, so the negotiation layer can decide whether to continue.
+    let (bases, unknown_bases) =
+        git_shas_to_bonsais(ctx, repo, request.bases.iter(), request.freshness)
+            .await
+            .context("Error converting base Git commits to Bonsai duing fetch")?;
+    let (heads, _) = git_shas_to_bonsais(ctx, repo, request.heads.iter(), request.freshness)
         .await
         .context("Error converting head Git commits to Bonsai during fetch")?;
     // Get the stream of commits between the bases and heads
@@ -1019,11 +1891,52 @@ pub async fn fetch_response<'a>(
     let commits_count = target_commits.len();
     // Reverse the list of commits so that we can prevent delta cycles from appearing in the packfile
     target_commits.reverse();
-    // Get the count of unique blob and tree objects to be included in the packfile
-    let trees_and_blobs_count =
-        trees_and_blobs_count(ctx, repo, to_commit_stream(target_commits.clone()))
-            .await
-            .context("Error while calculating object count during fetch")?;
+    // Get the count (and, cheaply, the total uncompressed size) of the blob and tree
+    // objects to be included in the packfile. A `dry_run` request only wants an estimate, so
+    // it's safe (and far cheaper in memory) to use a bounded-memory approximate count of
+    // distinct objects; a real fetch needs the exact count, i.e. the per-commit sum of delta
+    // manifest entries that matches the items streamed below.
+    let (trees_and_blobs_count, trees_and_blobs_size, _skipped_commits) = trees_and_blobs_stats(
+        ctx,
+        repo,
+        to_commit_stream(target_commits.clone()),
+        request.concurrency,
+        !request.dry_run, /* exact */
+        false,            /* tolerate_derivation_errors */
+    )
+    .await
+    .context("Error while calculating object count during fetch")?;
+    // Get the stream of all annotated tag items in the repo. Constructing the stream itself
+    // is cheap since fetching the actual tag objects is deferred until it is polled.
+    // By default, all tags are sent since filtering requires collecting the full set of
+    // target commits upfront, and the extra size overhead in the pack is just a few KBs.
+    // Bandwidth-sensitive clients can opt into filtering the tags down to only those whose
+    // target commit is part of this fetch's response by setting `include_annotated_tags`
+    let tag_target_commits = request
+        .include_annotated_tags
+        .then(|| target_commits.iter().copied().collect::<HashSet<_>>());
+    let (tag_stream, tags_count) = all_tags_packfile_stream(
+        ctx,
+        repo,
+        tag_target_commits.as_ref(),
+        request.max_object_size,
+    )
+    .await
+    .context("Error while generating tag packfile item stream during fetch")?;
+    let object_counts = PackfileObjectCounts::new(commits_count, trees_and_blobs_count, tags_count);
+    if request.dry_run {
+        // The request only wants a size estimate, so skip generating (and storing) any
+        // packfile items altogether. Commit and tag objects are not fetched for this
+        // estimate, so their contribution to the total size is approximated instead of
+        // calculated exactly like it is for trees and blobs
+        let estimated_size_bytes = trees_and_blobs_size
+            + (commits_count as u64) * AVERAGE_COMMIT_OBJECT_SIZE_BYTES
+            + (tags_count as u64) * AVERAGE_TAG_OBJECT_SIZE_BYTES;
+        return Ok(
+            FetchResponse::dry_run(object_counts, estimated_size_bytes)
+                .with_unknown_bases(unknown_bases),
+        );
+    }
     // Get the stream of blob and tree packfile items (with deltas where possible) to include in the pack/bundle. Note that
     // we have already counted these items as part of object count.
     let blob_and_tree_stream = blob_and_tree_packfile_stream(
@@ -1032,6 +1945,9 @@ pub async fn fetch_response<'a>(
         to_commit_stream(target_commits.clone()),
         delta_inclusion,
         packfile_item_inclusion,
+        request.concurrency,
+        request.max_object_size,
+        Arc::new(request.exclude_delta_for),
     )
     .await
     .context("Error while generating blob and tree packfile item stream during fetch")?;
@@ -1042,22 +1958,362 @@ pub async fn fetch_response<'a>(
         repo,
         to_commit_stream(target_commits.clone()),
         packfile_item_inclusion,
+        request.max_object_size,
     )
     .await
     .context("Error while generating commit packfile item stream during fetch")?;
-    // Get the stream of all annotated tag items in the repo
-    // NOTE: Ideally, we should filter it based on the requested refs but its much faster to just send all the tags.
-    // Git ignores the unnecessary objects and the extra size overhead in the pack is just a few KBs
-    let (tag_stream, tags_count) = all_tags_packfile_stream(ctx, repo)
-        .await
-        .context("Error while generating tag packfile item stream during fetch")?;
     // Compute the overall object count by summing the trees, blobs, tags and commits count
-    let object_count = commits_count + trees_and_blobs_count + tags_count;
+    let object_count = object_counts.total();
     // Combine all streams together and return the response. The ordering of the streams in this case is irrelevant since the commit
     // and tag stream include full objects and the blob_and_tree_stream has deltas in the correct order
     let packfile_stream = tag_stream
         .chain(commit_stream)
         .chain(blob_and_tree_stream)
         .boxed();
-    Ok(FetchResponse::new(packfile_stream, object_count))
+    // Run the consistency check before cancellation can truncate the stream, so that a
+    // routine client disconnect (which makes the `take_while` below stop polling, and
+    // therefore stop driving the check's loop, before it reaches its own end-of-stream
+    // comparison) is never misreported as an object-count mismatch.
+    let packfile_stream = verify_object_count_packfile_stream(ctx, packfile_stream, object_count);
+    let packfile_stream = cancellable_packfile_stream(packfile_stream, request.cancellation_token);
+    Ok(
+        FetchResponse::new(packfile_stream, object_count, object_counts)
+            .with_unknown_bases(unknown_bases),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use bonsai_git_mapping::BonsaiGitMapping;
+    use bonsai_git_mapping::BonsaiGitMappingEntry;
+    use bonsai_hg_mapping::BonsaiHgMapping;
+    use bonsai_tag_mapping::BonsaiTagMapping;
+    use bookmarks::Bookmarks;
+    use commit_graph::CommitGraph;
+    use fbinit::FacebookInit;
+    use git_symbolic_refs::GitSymbolicRefs;
+    use git_symbolic_refs::GitSymbolicRefsEntry;
+    use mononoke_types_mocks::hash::ONES_GIT_SHA1;
+    use mononoke_types_mocks::hash::TWOS_GIT_SHA1;
+    use repo_blobstore::RepoBlobstore;
+    use repo_derived_data::RepoDerivedData;
+    use repo_identity::RepoIdentity;
+    use tests_utils::CreateCommitContext;
+
+    use super::*;
+
+    #[facet::container]
+    struct TestRepo {
+        #[facet]
+        repo_identity: RepoIdentity,
+        #[facet]
+        repo_blobstore: RepoBlobstore,
+        #[facet]
+        bookmarks: dyn Bookmarks,
+        #[facet]
+        bonsai_hg_mapping: dyn BonsaiHgMapping,
+        #[facet]
+        bonsai_git_mapping: dyn BonsaiGitMapping,
+        #[facet]
+        bonsai_tag_mapping: dyn BonsaiTagMapping,
+        #[facet]
+        repo_derived_data: RepoDerivedData,
+        #[facet]
+        git_symbolic_refs: dyn GitSymbolicRefs,
+        #[facet]
+        commit_graph: CommitGraph,
+        #[facet]
+        changesets: dyn changesets::Changesets,
+        #[facet]
+        filestore_config: filestore::FilestoreConfig,
+    }
+
+    fn empty_request() -> FetchRequest {
+        FetchRequest {
+            heads: vec![],
+            bases: vec![],
+            include_out_of_pack_deltas: false,
+            include_annotated_tags: false,
+            offset_delta: true,
+            shallow: vec![],
+            deepen: None,
+            deepen_relative: false,
+            deepen_since: None,
+            deepen_not: None,
+            filter: None,
+            dry_run: false,
+            freshness: Freshness::MostRecent,
+            concurrency: PackfileConcurrency::default(),
+            max_object_size: None,
+            delta_inclusion: None,
+            exclude_delta_for: FxHashSet::default(),
+            cancellation_token: None,
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_fetch_with_empty_heads_is_empty(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        // A commit exists in the repo, but no head is requested. An empty-heads fetch must
+        // return nothing, even though a fetch with heads and `include_annotated_tags` unset
+        // sends every annotated tag in the repo.
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .commit()
+            .await?;
+        repo.bonsai_git_mapping()
+            .add(
+                &ctx,
+                BonsaiGitMappingEntry {
+                    bcs_id: root,
+                    git_sha1: ONES_GIT_SHA1,
+                },
+            )
+            .await?;
+
+        let response = fetch_response(&ctx, &repo, empty_request()).await?;
+        assert_eq!(response.num_items, 0);
+        assert_eq!(response.object_counts.commits, 0);
+        assert_eq!(response.object_counts.trees_and_blobs, 0);
+        assert_eq!(response.object_counts.tags, 0);
+        assert_eq!(response.items.collect::<Vec<_>>().await.len(), 0);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_fetch_dry_run_with_empty_heads_is_empty(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        let request = FetchRequest {
+            dry_run: true,
+            ..empty_request()
+        };
+        let response = fetch_response(&ctx, &repo, request).await?;
+        assert_eq!(response.num_items, 0);
+        assert_eq!(response.object_counts.total(), 0);
+        assert_eq!(response.estimated_size_bytes, Some(0));
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_fetch_with_empty_bases_is_full_clone(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .commit()
+            .await?;
+        repo.bonsai_git_mapping()
+            .add(
+                &ctx,
+                BonsaiGitMappingEntry {
+                    bcs_id: root,
+                    git_sha1: ONES_GIT_SHA1,
+                },
+            )
+            .await?;
+
+        let request = FetchRequest {
+            heads: vec![ONES_GIT_SHA1.to_object_id()?],
+            ..empty_request()
+        };
+        let response = fetch_response(&ctx, &repo, request).await?;
+        // Empty bases with a populated head is a full clone: the single commit (and its
+        // tree/blob) should be present, with no special-casing needed beyond the
+        // ancestors-difference query that `bases = []` already feeds correctly.
+        assert_eq!(response.object_counts.commits, 1);
+        assert_eq!(response.object_counts.trees_and_blobs, 2);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_fetch_reports_unknown_have_base(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .commit()
+            .await?;
+        repo.bonsai_git_mapping()
+            .add(
+                &ctx,
+                BonsaiGitMappingEntry {
+                    bcs_id: root,
+                    git_sha1: ONES_GIT_SHA1,
+                },
+            )
+            .await?;
+
+        // TWOS_GIT_SHA1 is neither a commit nor a tag known to this repo, so it should be
+        // reported back as unknown instead of being silently dropped from `bases`.
+        let request = FetchRequest {
+            heads: vec![ONES_GIT_SHA1.to_object_id()?],
+            bases: vec![TWOS_GIT_SHA1.to_object_id()?],
+            ..empty_request()
+        };
+        let response = fetch_response(&ctx, &repo, request).await?;
+        assert_eq!(response.unknown_bases, vec![TWOS_GIT_SHA1.to_object_id()?]);
+        // The unknown base doesn't exclude anything from the ancestry computation beyond
+        // what it already didn't match, so the fetch still proceeds as a full clone.
+        assert_eq!(response.object_counts.commits, 1);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_fetch_stops_when_cancelled(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        // Several commits, each with its own file, so the packfile has more than a
+        // handful of items to derive and stream.
+        let mut head = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file0", "content0")
+            .commit()
+            .await?;
+        for i in 1..5 {
+            head = CreateCommitContext::new(&ctx, &repo, vec![head])
+                .add_file(format!("file{i}").as_str(), format!("content{i}"))
+                .commit()
+                .await?;
+        }
+        repo.bonsai_git_mapping()
+            .add(
+                &ctx,
+                BonsaiGitMappingEntry {
+                    bcs_id: head,
+                    git_sha1: ONES_GIT_SHA1,
+                },
+            )
+            .await?;
+
+        // Simulate the client having already disconnected before the response stream
+        // is ever polled.
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+        let request = FetchRequest {
+            heads: vec![ONES_GIT_SHA1.to_object_id()?],
+            cancellation_token: Some(cancellation_token),
+            ..empty_request()
+        };
+        let response = fetch_response(&ctx, &repo, request).await?;
+        // The uncancelled fetch would stream every commit, tree and blob for all 5
+        // commits. Since the token was already cancelled, the stream must stop well
+        // short of that instead of deriving and loading every object.
+        let total_items = response.object_counts.total();
+        assert!(total_items > 1);
+        let items = response.items.collect::<Vec<_>>().await;
+        assert!(
+            items.len() <= 1,
+            "expected cancellation to halt the stream almost immediately, got {} of {} items",
+            items.len(),
+            total_items,
+        );
+        // A cancelled stream necessarily emits fewer items than `object_counts` declared,
+        // which must not be misreported as a corrupt-pack object-count mismatch.
+        assert!(
+            items.iter().all(|item| item.is_ok()),
+            "cancellation must not surface as an object-count mismatch error"
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_include_symrefs_follows_multi_hop_chain(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        // A two-hop chain: HEAD -> refs/heads/main -> refs/heads/master, where
+        // "refs/heads/main" is itself a symref rather than the final target.
+        repo.git_symbolic_refs()
+            .add_or_update_entries(vec![
+                GitSymbolicRefsEntry::new(
+                    "HEAD".to_string(),
+                    "main".to_string(),
+                    "branch".to_string(),
+                )?,
+                GitSymbolicRefsEntry::new(
+                    "refs/heads/main".to_string(),
+                    "master".to_string(),
+                    "branch".to_string(),
+                )?,
+            ])
+            .await?;
+
+        let mut refs_to_include = FxHashMap::default();
+        refs_to_include.insert(
+            "refs/heads/master".to_string(),
+            RefTarget::Plain(ONES_GIT_SHA1.to_object_id()?),
+        );
+
+        include_symrefs(
+            &repo,
+            RequestedSymrefs::IncludeHead(SymrefFormat::NameWithTargetChain),
+            &mut refs_to_include,
+        )
+        .await?;
+
+        assert_eq!(
+            refs_to_include.get("HEAD"),
+            Some(&RefTarget::WithMetadata(
+                ONES_GIT_SHA1.to_object_id()?,
+                "symref-target:refs/heads/main symref-target:refs/heads/master".to_string(),
+            )),
+            "HEAD should resolve through both hops to the final branch target"
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_include_symrefs_named_follows_multi_hop_chain(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: TestRepo = test_repo_factory::build_empty(ctx.fb).await?;
+
+        // A two-hop chain that isn't HEAD, to exercise `IncludeNamed`'s own chain
+        // resolution rather than `IncludeHead`'s.
+        repo.git_symbolic_refs()
+            .add_or_update_entries(vec![
+                GitSymbolicRefsEntry::new(
+                    "refs/heads/develop".to_string(),
+                    "staging".to_string(),
+                    "branch".to_string(),
+                )?,
+                GitSymbolicRefsEntry::new(
+                    "refs/heads/staging".to_string(),
+                    "master".to_string(),
+                    "branch".to_string(),
+                )?,
+            ])
+            .await?;
+
+        let mut refs_to_include = FxHashMap::default();
+        refs_to_include.insert(
+            "refs/heads/master".to_string(),
+            RefTarget::Plain(ONES_GIT_SHA1.to_object_id()?),
+        );
+
+        include_symrefs(
+            &repo,
+            RequestedSymrefs::IncludeNamed(
+                vec!["refs/heads/develop".to_string()],
+                SymrefFormat::NameWithTargetChain,
+            ),
+            &mut refs_to_include,
+        )
+        .await?;
+
+        assert_eq!(
+            refs_to_include.get("refs/heads/develop"),
+            Some(&RefTarget::WithMetadata(
+                ONES_GIT_SHA1.to_object_id()?,
+                "symref-target:refs/heads/staging symref-target:refs/heads/master".to_string(),
+            )),
+            "requested symref should resolve through both hops to the final branch target"
+        );
+        Ok(())
+    }
 }
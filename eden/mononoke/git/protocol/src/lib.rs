@@ -7,5 +7,9 @@
 
 #![feature(trait_alias)]
 
+mod cardinality;
+mod delta_manifest_cache;
+pub mod errors;
 pub mod generator;
+pub mod pack_bytes;
 pub mod types;
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::io::ErrorKind;
+
+use anyhow::Context;
+use anyhow::Result;
+use async_stream::try_stream;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::SinkExt;
+use futures::StreamExt;
+use gix_features::hash::Sha1;
+use gix_hash::ObjectId;
+use packfile::pack::DeltaForm;
+use packfile::pack::PackfileWriter;
+use packfile::types::PackfileItem;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::CopyToBytes;
+use tokio_util::io::SinkWriter;
+use tokio_util::sync::PollSender;
+
+/// Buffer size for the channel used to pipe packfile bytes from the writer task to the
+/// returned byte stream.
+const PACKFILE_BYTES_CHANNEL_SIZE: usize = 10_000;
+
+/// Length in bytes of the SHA1 checksum trailer appended to the end of a packfile.
+const SHA1_DIGEST_LEN: usize = 20;
+
+/// Serialize a stream of packfile items (as produced by `PackItemStreamResponse::items`
+/// or `FetchResponse::items`) into the raw bytes of a packfile: header, objects and the
+/// trailing SHA1 checksum. This encapsulates the `packfile` crate's `PackfileWriter` so
+/// that a caller (e.g. an HTTP handler) can pipe the result straight into a response
+/// body instead of driving the writer itself.
+///
+/// If `verify_checksum` is set, the emitted bytes are independently re-hashed as they're
+/// streamed out, and the result is compared against the trailer `PackfileWriter` computed
+/// internally while writing. This catches a bug in item serialization producing a pack
+/// whose body doesn't actually match its own trailer, which would otherwise only surface
+/// once the client downloaded the whole pack and verified it. It's opt-in because hashing
+/// the pack a second time isn't free.
+pub fn packfile_item_stream_to_bytes<'a>(
+    items: BoxStream<'a, Result<PackfileItem>>,
+    num_items: usize,
+    concurrency: usize,
+    delta_form: DeltaForm,
+    verify_checksum: bool,
+) -> BoxStream<'a, Result<Bytes>> {
+    let (chunk_sender, chunk_receiver) = mpsc::channel::<Bytes>(PACKFILE_BYTES_CHANNEL_SIZE);
+    let (result_sender, result_receiver) = oneshot::channel::<Result<ObjectId>>();
+    let sink_writer = SinkWriter::new(CopyToBytes::new(
+        PollSender::new(chunk_sender).sink_map_err(|_| std::io::Error::from(ErrorKind::BrokenPipe)),
+    ));
+    tokio::spawn(async move {
+        let mut pack_writer =
+            PackfileWriter::new(sink_writer, num_items as u32, concurrency, delta_form);
+        let result = async {
+            pack_writer.write(items).await?;
+            let checksum = pack_writer.finish().await?;
+            anyhow::Ok(checksum)
+        }
+        .await;
+        // The receiver may already be gone if the output stream was dropped before we
+        // finished writing. That's not an error worth reporting.
+        let _ = result_sender.send(result);
+    });
+    try_stream! {
+        let mut chunks = ReceiverStream::new(chunk_receiver);
+        let mut verify_hasher = verify_checksum.then(Sha1::default);
+        // Bytes held back from `verify_hasher` because they might still turn out to be
+        // (part of) the trailer, which isn't itself covered by the checksum. Trimmed back
+        // down to at most `SHA1_DIGEST_LEN` bytes every time a new chunk arrives.
+        let mut pending_tail = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            if let Some(hasher) = verify_hasher.as_mut() {
+                pending_tail.extend_from_slice(&chunk);
+                if pending_tail.len() > SHA1_DIGEST_LEN {
+                    let hashable_len = pending_tail.len() - SHA1_DIGEST_LEN;
+                    hasher.update(&pending_tail[..hashable_len]);
+                    pending_tail.drain(..hashable_len);
+                }
+            }
+            yield chunk;
+        }
+        let checksum = result_receiver
+            .await
+            .context("packfile writer task terminated without a result")??;
+        if let Some(hasher) = verify_hasher {
+            let recomputed = ObjectId::from(hasher.digest());
+            if pending_tail.len() != SHA1_DIGEST_LEN || recomputed != checksum {
+                anyhow::bail!(
+                    "packfile checksum verification failed: recomputed hash {} does not match trailer {}",
+                    recomputed,
+                    checksum,
+                );
+            }
+        }
+    }
+    .boxed()
+}
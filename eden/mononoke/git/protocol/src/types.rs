@@ -12,17 +12,25 @@ use std::fmt::Formatter;
 use std::marker::Unpin;
 
 use anyhow::Result;
+use async_stream::try_stream;
+use bookmarks::Freshness;
+use bytes::Bytes;
+use futures::stream;
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use gix_hash::ObjectId;
 use mononoke_types::ChangesetId;
 use packetline::encode::write_binary_packetline;
 use packfile::pack::DeltaForm;
 use packfile::types::PackfileItem;
+use rustc_hash::FxHashSet;
 use tokio::io::AsyncWrite;
+use tokio_util::sync::CancellationToken;
 
 const SYMREF_HEAD: &str = "HEAD";
 
 /// Enum defining the type of data associated with a ref target
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RefTarget {
     /// The target is a plain Git object
     Plain(ObjectId),
@@ -66,13 +74,27 @@ impl Display for RefTarget {
     }
 }
 
+/// Whether a Git tag ref points directly at a commit (lightweight) or at an
+/// intermediate tag object (annotated)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    /// The tag ref points directly at a commit
+    Lightweight,
+    /// The tag ref points at a tag object, which in turn points at a commit
+    Annotated,
+}
+
 /// The set of symrefs that are to be included in or excluded from the pack
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum RequestedSymrefs {
     /// Only include the HEAD symref in the pack/bundle
     IncludeHead(SymrefFormat),
     /// Incldue all known symrefs in the pack/bundle
     IncludeAll(SymrefFormat),
+    /// Only include the given, explicitly named symrefs in the pack/bundle.
+    /// Each name must already be known to be a symref; unlike `IncludeAll`,
+    /// this doesn't silently drop names that turn out not to be symrefs.
+    IncludeNamed(Vec<String>, SymrefFormat),
     /// Exclude all known symrefs from the pack/bundle
     ExcludeAll,
 }
@@ -83,6 +105,10 @@ pub enum SymrefFormat {
     /// Include the symref along with the ref that it points to, e.g.
     /// object_id_here HEAD symref-target:refs/heads/master
     NameWithTarget,
+    /// Include the symref along with the full chain of symrefs that need to
+    /// be followed to reach the final, non-symref target, e.g.
+    /// object_id_here HEAD symref-target:refs/heads/master symref-target:refs/heads/main
+    NameWithTargetChain,
     /// Only include the symref name, e.g. object_id_here HEAD
     NameOnly,
 }
@@ -123,6 +149,22 @@ pub enum TagInclusion {
     WithTarget,
 }
 
+/// Enum defining which delta candidate should be chosen as the base when
+/// multiple deltas are available for the same object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeltaBasePreference {
+    /// Choose the delta with the smallest compressed size, regardless of
+    /// what the base object is. This is the existing, default behaviour
+    #[default]
+    SmallestSize,
+    /// Prefer the delta whose base is the same-path predecessor of the
+    /// object (i.e. the previous version of the object at the same path),
+    /// for better delta reuse and locality across fetches. Falls back to
+    /// [`DeltaBasePreference::SmallestSize`] if no same-path delta is
+    /// available
+    SamePathPredecessor,
+}
+
 /// Enum defining whether a delta should be included in the pack
 /// and if so, what kind of delta should be used
 #[derive(Debug, Clone, Copy)]
@@ -136,6 +178,9 @@ pub enum DeltaInclusion {
         /// uncompressed object size. e.g. If original object size is 100 bytes and the
         /// delta_inclusion_threshold is 0.5, then the delta size should be less than 50 bytes
         inclusion_threshold: f32,
+        /// Which delta candidate should be preferred when an object has more
+        /// than one delta to choose from
+        base_preference: DeltaBasePreference,
     },
     /// Do not include deltas
     Exclude,
@@ -148,6 +193,7 @@ impl DeltaInclusion {
         DeltaInclusion::Include {
             form: DeltaForm::RefAndOffset,
             inclusion_threshold: 0.8,
+            base_preference: DeltaBasePreference::SmallestSize,
         }
     }
 }
@@ -161,6 +207,58 @@ impl DeltaInclusion {
     }
 }
 
+/// Concurrency limits used while counting and generating the blob and tree
+/// packfile items for a set of commits. The same limits are used for the upfront
+/// object-count pass and the actual packfile item stream, so that the two stages
+/// put comparable load on the blobstore
+#[derive(Debug, Clone, Copy)]
+pub struct PackfileConcurrency {
+    /// The number of commits whose blob and tree objects are counted/streamed concurrently
+    pub per_commit_concurrency: usize,
+    /// For a single commit, the number of blob and tree objects that are fetched concurrently
+    pub per_object_concurrency: usize,
+    /// Carried with the other limits but not read by blob and tree packfile item
+    /// generation, which loads a commit's manifest entries in full up front and
+    /// pipelines their content fetches only up to `per_object_concurrency`. Setting
+    /// it has no effect on the generated stream
+    pub read_ahead: usize,
+    /// For a single commit, the total estimated bytes of blob and tree objects that
+    /// may be in flight at once, on top of `per_object_concurrency`. Unlike
+    /// `per_object_concurrency`, which counts items, this bounds memory directly:
+    /// a burst of objects near `max_object_size` is throttled to fewer concurrent
+    /// fetches, while a burst of tiny objects can still use the full
+    /// `per_object_concurrency`. Each object's estimated size is the largest of its
+    /// full size and its delta candidates' compressed sizes, since that's an upper
+    /// bound on what `packfile_entry` will actually materialize for it.
+    pub per_object_bytes_budget: u64,
+}
+
+impl PackfileConcurrency {
+    pub fn new(per_commit_concurrency: usize, per_object_concurrency: usize) -> Self {
+        Self {
+            per_commit_concurrency,
+            per_object_concurrency,
+            read_ahead: Self::default().read_ahead,
+            per_object_bytes_budget: Self::default().per_object_bytes_budget,
+        }
+    }
+}
+
+impl Default for PackfileConcurrency {
+    /// The concurrency values used throughout Mononoke prior to these limits
+    /// becoming configurable
+    fn default() -> Self {
+        Self {
+            per_commit_concurrency: 500,
+            per_object_concurrency: 1000,
+            read_ahead: 1000,
+            // 512MiB: generous enough to not throttle typical object sizes, but
+            // enough to keep a burst of unusually large objects from spiking memory.
+            per_object_bytes_budget: 512 * 1024 * 1024,
+        }
+    }
+}
+
 /// Enum defining how packfile items for raw git objects be fetched
 #[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PackfileItemInclusion {
@@ -174,6 +272,29 @@ pub enum PackfileItemInclusion {
     // If the packfile item for the raw git object already exists, use it. If
     // it doesn't exist, generate it and store it
     FetchAndStore,
+    // Always generate the packfile item for the raw git object, regardless of
+    // whether it already exists, and store the freshly generated item,
+    // overwriting any existing stored item
+    GenerateAndStore,
+}
+
+/// Enum defining the order in which packfile items are streamed in the
+/// response to a pack item stream request
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackfileItemStreamOrdering {
+    /// Stream tags, then commits, then trees and blobs. This is the order
+    /// expected by strict bundle/pack readers (e.g. `git index-pack --strict`),
+    /// since it guarantees that tags and commits are streamed before the trees
+    /// and blobs they reference
+    #[default]
+    CommitsFirst,
+    /// Stream tags, commits, trees and blobs concurrently, interleaved in
+    /// whatever order each stream produces items. This can reduce overall
+    /// latency (the trees-and-blobs stream, which is usually the bulk of the
+    /// data, does not have to wait for the tag and commit streams to drain
+    /// first), but does not guarantee that tags and commits precede the trees
+    /// and blobs they reference, so shouldn't be used with strict readers
+    Unordered,
 }
 
 /// The request parameters used to specify the constraints that need to be
@@ -192,6 +313,38 @@ pub struct PackItemStreamRequest {
     pub tag_inclusion: TagInclusion,
     /// How packfile items for raw git objects should be fetched
     pub packfile_item_inclusion: PackfileItemInclusion,
+    /// The changeset after which the packfile item stream should resume, if this
+    /// request is continuing a previously interrupted stream. The commit
+    /// identified here (and everything before it) is assumed to have already
+    /// been sent to the client and will not be included again
+    pub after: Option<ChangesetId>,
+    /// The freshness that should be used while listing bookmarks for this request.
+    /// Defaults to `Freshness::MostRecent`
+    pub freshness: Freshness,
+    /// The concurrency limits to use while counting and generating the blob and tree
+    /// packfile items included in the response. Defaults to `PackfileConcurrency::default()`
+    pub concurrency: PackfileConcurrency,
+    /// The order in which packfile items should be streamed in the response.
+    /// Defaults to `PackfileItemStreamOrdering::CommitsFirst`
+    pub object_order: PackfileItemStreamOrdering,
+    /// The maximum size (in bytes) of a single Git object that can be fetched while
+    /// generating a base packfile item. If fetching an object would exceed this limit,
+    /// the request fails with an error instead of loading the object into memory.
+    /// Defaults to `None`, i.e. no limit
+    pub max_object_size: Option<u64>,
+    /// Token used to signal that the caller is no longer interested in the
+    /// generated packfile item stream, e.g. because the client has
+    /// disconnected. When cancelled, the stream stops yielding further
+    /// items instead of continuing to derive and load objects that nobody
+    /// will read. Defaults to `None`, i.e. the stream always runs to
+    /// completion
+    pub cancellation_token: Option<CancellationToken>,
+    /// Whether derivation errors encountered while counting the trees and blobs
+    /// reachable from the target commits should be tolerated and logged as
+    /// warnings instead of failing the request. The offending commits are
+    /// excluded from both the packfile item count and the generated stream.
+    /// Defaults to `false`, i.e. such errors fail the request
+    pub tolerate_derivation_errors: bool,
 }
 
 impl PackItemStreamRequest {
@@ -210,6 +363,13 @@ impl PackItemStreamRequest {
             delta_inclusion,
             tag_inclusion,
             packfile_item_inclusion,
+            after: None,
+            freshness: Freshness::MostRecent,
+            concurrency: PackfileConcurrency::default(),
+            object_order: PackfileItemStreamOrdering::default(),
+            max_object_size: None,
+            cancellation_token: None,
+            tolerate_derivation_errors: false,
         }
     }
 
@@ -225,8 +385,67 @@ impl PackItemStreamRequest {
             delta_inclusion,
             tag_inclusion,
             packfile_item_inclusion,
+            after: None,
+            freshness: Freshness::MostRecent,
+            concurrency: PackfileConcurrency::default(),
+            object_order: PackfileItemStreamOrdering::default(),
+            max_object_size: None,
+            cancellation_token: None,
+            tolerate_derivation_errors: false,
         }
     }
+
+    /// Resume the stream after the given changeset, which the caller has
+    /// already received and processed as part of a previous, interrupted
+    /// invocation of this request
+    pub fn with_resume_after(mut self, after: Option<ChangesetId>) -> Self {
+        self.after = after;
+        self
+    }
+
+    /// Use the given freshness while listing bookmarks for this request, instead of
+    /// the default `Freshness::MostRecent`
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    /// Use the given concurrency limits while counting and generating the blob and
+    /// tree packfile items for this request, instead of `PackfileConcurrency::default()`
+    pub fn with_concurrency(mut self, concurrency: PackfileConcurrency) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Use the given object order while streaming packfile items, instead of the
+    /// default `PackfileItemStreamOrdering::CommitsFirst`
+    pub fn with_object_order(mut self, object_order: PackfileItemStreamOrdering) -> Self {
+        self.object_order = object_order;
+        self
+    }
+
+    /// Reject the fetch of any single Git object larger than `max_object_size` bytes
+    /// instead of loading it into memory in full, instead of the default of no limit
+    pub fn with_max_object_size(mut self, max_object_size: Option<u64>) -> Self {
+        self.max_object_size = max_object_size;
+        self
+    }
+
+    /// Stop generating further packfile items once `cancellation_token` is
+    /// cancelled, instead of the default of always running to completion
+    pub fn with_cancellation_token(mut self, cancellation_token: Option<CancellationToken>) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Tolerate derivation errors encountered while counting the trees and blobs
+    /// reachable from the target commits, logging them as warnings and excluding
+    /// the offending commits from the count and the generated stream, instead of
+    /// the default of failing the request
+    pub fn with_tolerate_derivation_errors(mut self, tolerate_derivation_errors: bool) -> Self {
+        self.tolerate_derivation_errors = tolerate_derivation_errors;
+        self
+    }
 }
 
 /// The request parameters used to specify the constraints that need to be
@@ -240,6 +459,19 @@ pub struct LsRefsRequest {
     pub requested_refs: RequestedRefs,
     /// How annotated tags should be included in the output
     pub tag_inclusion: TagInclusion,
+    /// The freshness that should be used while listing bookmarks for this request.
+    /// Defaults to `Freshness::MostRecent`
+    pub freshness: Freshness,
+    /// The pre-generated bundle URIs (e.g. CDN-hosted Git bundles) that should be
+    /// advertised alongside the refs in the response, so that clients can seed a
+    /// clone from the bundle(s) and only fetch the remaining history incrementally.
+    /// Defaults to empty, i.e. no bundle-uri advertisement
+    pub bundle_uris: Vec<String>,
+    /// The maximum number of refs that can be included in the response before
+    /// the request is failed with [`crate::errors::GitProtocolError::TooManyRefs`]
+    /// instead of materializing the full (potentially unbounded) set of refs.
+    /// Defaults to `None`, i.e. no limit
+    pub max_ref_count: Option<u64>,
 }
 
 impl LsRefsRequest {
@@ -252,8 +484,33 @@ impl LsRefsRequest {
             requested_symrefs,
             requested_refs,
             tag_inclusion,
+            freshness: Freshness::MostRecent,
+            bundle_uris: Vec::new(),
+            max_ref_count: None,
         }
     }
+
+    /// Use the given freshness while listing bookmarks for this request, instead of
+    /// the default `Freshness::MostRecent`
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    /// Advertise the given pre-generated bundle URIs in the response, instead of the
+    /// default of not advertising any bundles
+    pub fn with_bundle_uris(mut self, bundle_uris: Vec<String>) -> Self {
+        self.bundle_uris = bundle_uris;
+        self
+    }
+
+    /// Fail the request with `GitProtocolError::TooManyRefs` instead of
+    /// materializing the response if the number of refs to include would
+    /// exceed the given limit, instead of the default of no limit
+    pub fn with_max_ref_count(mut self, max_ref_count: Option<u64>) -> Self {
+        self.max_ref_count = max_ref_count;
+        self
+    }
 }
 
 /// The request parameters used to specify the constraints that need to be
@@ -296,6 +553,64 @@ pub struct FetchRequest {
     /// Request that various objects from the packfile be omitted using
     /// one of several filtering techniques
     pub filter: Option<String>,
+    /// If set, the fetch request is not actually executed. Instead, the response
+    /// contains only the object counts and an estimate of the total size of the
+    /// packfile that would have been generated for this request
+    pub dry_run: bool,
+    /// The freshness that should be used while listing bookmarks (e.g. while resolving
+    /// annotated tags) for this request. Defaults to `Freshness::MostRecent`
+    pub freshness: Freshness,
+    /// The concurrency limits to use while counting and generating the blob and tree
+    /// packfile items included in the response. Defaults to `PackfileConcurrency::default()`
+    pub concurrency: PackfileConcurrency,
+    /// The maximum size (in bytes) of a single Git object that can be fetched while
+    /// generating a base packfile item. If fetching an object would exceed this limit,
+    /// the request fails with an error instead of loading the object into memory.
+    /// Defaults to `None`, i.e. no limit
+    pub max_object_size: Option<u64>,
+    /// The type of delta that should be included in the generated packfile, if any.
+    /// Defaults to `None`, in which case `DeltaInclusion::standard()` is used
+    pub delta_inclusion: Option<DeltaInclusion>,
+    /// Object ids that must always be sent as full base objects, even when a delta
+    /// against some other object in the pack would otherwise be chosen for them.
+    /// Intended for debugging a suspected corrupt or low-quality delta for a specific
+    /// object, without having to disable deltas for the whole fetch via
+    /// `delta_inclusion`. Defaults to empty, i.e. no object is excluded
+    pub exclude_delta_for: FxHashSet<ObjectId>,
+    /// Token used to signal that the caller is no longer interested in the
+    /// generated packfile item stream, e.g. because the client has
+    /// disconnected. When cancelled, the stream stops yielding further
+    /// items instead of continuing to derive and load objects that nobody
+    /// will read. Defaults to `None`, i.e. the stream always runs to
+    /// completion
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+/// Struct containing the breakdown of the number of packfile items of each
+/// Git object type that were generated for a given range of commits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PackfileObjectCounts {
+    /// The number of commit objects included in the generated stream of packfile items
+    pub commits: usize,
+    /// The number of tree and blob objects included in the generated stream of packfile items
+    pub trees_and_blobs: usize,
+    /// The number of annotated tag objects included in the generated stream of packfile items
+    pub tags: usize,
+}
+
+impl PackfileObjectCounts {
+    pub fn new(commits: usize, trees_and_blobs: usize, tags: usize) -> Self {
+        Self {
+            commits,
+            trees_and_blobs,
+            tags,
+        }
+    }
+
+    /// The total number of packfile items across all object types
+    pub fn total(&self) -> usize {
+        self.commits + self.trees_and_blobs + self.tags
+    }
 }
 
 /// Struct representing the packfile item response generated for the
@@ -305,6 +620,8 @@ pub struct PackItemStreamResponse<'a> {
     pub items: BoxStream<'a, Result<PackfileItem>>,
     /// The number of packfile items that were generated for the given range of commits
     pub num_items: usize,
+    /// The breakdown of num_items by Git object type
+    pub object_counts: PackfileObjectCounts,
     /// The set of refs mapped to their Git commit ID or tag ID that are included in the
     /// generated stream of packfile items along with optional metadata for the mapping
     pub included_refs: HashMap<String, RefTarget>,
@@ -314,22 +631,79 @@ impl<'a> PackItemStreamResponse<'a> {
     pub fn new(
         items: BoxStream<'a, Result<PackfileItem>>,
         num_items: usize,
+        object_counts: PackfileObjectCounts,
         included_refs: HashMap<String, RefTarget>,
     ) -> Self {
         Self {
             items,
             num_items,
+            object_counts,
             included_refs,
         }
     }
 }
 
+/// Report produced by [`crate::generator::validate_pack_item_stream`], a dry-run
+/// pass over the same inputs as [`generate_pack_item_stream`] that resolves every
+/// referenced object without constructing any `PackfileItem`s. Each field is empty
+/// when that category of resolution succeeded for every object
+#[derive(Debug, Clone, Default)]
+pub struct PackValidationReport {
+    /// Bonsai changesets reachable from the requested refs that have no
+    /// corresponding Git object in `bonsai_git_mapping`
+    pub missing_git_objects: Vec<ChangesetId>,
+    /// Errors encountered while deriving the tree/blob manifests for the
+    /// requested commits
+    pub manifest_errors: Vec<String>,
+    /// Errors encountered while resolving the requested refs (e.g. an annotated
+    /// tag or branch whose target is missing from the Git mapping)
+    pub unresolved_refs: Vec<String>,
+}
+
+impl PackValidationReport {
+    /// Whether every object referenced by the request resolved successfully
+    pub fn is_valid(&self) -> bool {
+        self.missing_git_objects.is_empty()
+            && self.manifest_errors.is_empty()
+            && self.unresolved_refs.is_empty()
+    }
+}
+
+/// A pre-generated Git bundle (e.g. hosted on a CDN) that can be fetched directly
+/// by a client instead of streaming the equivalent history from Mononoke, along
+/// with the ref tips that it contains
+#[derive(Debug, Clone)]
+pub struct BundleUri {
+    /// The URI that the bundle can be fetched from
+    pub uri: String,
+    /// The refs (and the Git object they point to) that are covered by this bundle,
+    /// i.e. a client that has fetched this bundle already has these ref tips and
+    /// only needs to fetch anything beyond them incrementally. Derived from the
+    /// same `refs_to_include` computation used to populate `included_refs`
+    pub refs: HashMap<String, RefTarget>,
+}
+
 /// Struct representing the ls-refs response generated for the
 /// given request parameters
 pub struct LsRefsResponse {
     /// The set of refs mapped to their Git commit ID or tag ID that are included in the
     /// output along with optional metadata for the mapping
     pub included_refs: HashMap<String, RefTarget>,
+    /// The peeled (i.e. underlying commit) target of each annotated tag ref in
+    /// `included_refs`, keyed by the same ref name. Populated from `bonsai_git_map`
+    /// so that callers who want the peeled commit id don't have to parse it out of
+    /// the `peeled:<hex>` metadata embedded in the ref's `RefTarget`. Only populated
+    /// when the request's `tag_inclusion` is `TagInclusion::WithTarget`; empty otherwise
+    pub peeled_refs: HashMap<String, ObjectId>,
+    /// Whether each tag ref in `included_refs` is lightweight or annotated, keyed by
+    /// the same ref name. Populated from the same `bonsai_tag_map` lookups used to
+    /// build `included_refs`, so callers don't have to peel every tag to discover
+    /// its kind. Only contains entries for refs under `refs/tags`
+    pub tag_kinds: HashMap<String, TagKind>,
+    /// The bundle URIs requested to be advertised for this response, each annotated
+    /// with the refs it covers. Empty unless the request asked for bundle-uri
+    /// advertisement via `LsRefsRequest::bundle_uris`
+    pub bundle_uris: Vec<BundleUri>,
 }
 
 fn ref_line(name: &str, target: &RefTarget) -> String {
@@ -344,8 +718,18 @@ fn ref_line(name: &str, target: &RefTarget) -> String {
 }
 
 impl LsRefsResponse {
-    pub fn new(included_refs: HashMap<String, RefTarget>) -> Self {
-        Self { included_refs }
+    pub fn new(
+        included_refs: HashMap<String, RefTarget>,
+        peeled_refs: HashMap<String, ObjectId>,
+        tag_kinds: HashMap<String, TagKind>,
+        bundle_uris: Vec<BundleUri>,
+    ) -> Self {
+        Self {
+            included_refs,
+            peeled_refs,
+            tag_kinds,
+            bundle_uris,
+        }
     }
 
     pub async fn write_packetline<W>(&self, writer: &mut W) -> Result<()>
@@ -363,19 +747,86 @@ impl LsRefsResponse {
         }
         Ok(())
     }
+
+    /// Convert this response into a stream of already packetline-encoded ref entries,
+    /// so that the server can start writing output for very large ref sets before
+    /// every entry has been individually encoded.
+    ///
+    /// Symrefs are already resolved by the time an `LsRefsResponse` exists (resolving
+    /// them requires the full set of included refs), so this only streams out the
+    /// remainder of the work: encoding each already-resolved entry. HEAD is always
+    /// yielded first, matching [`Self::write_packetline`].
+    pub fn into_packetline_stream(self) -> BoxStream<'static, Result<Bytes>> {
+        let mut included_refs = self.included_refs;
+        let head = included_refs.remove(SYMREF_HEAD);
+        try_stream! {
+            if let Some(target) = head {
+                let mut buf = Vec::new();
+                let line = ref_line(SYMREF_HEAD, &target);
+                write_binary_packetline(line.as_bytes(), &mut buf).await?;
+                yield Bytes::from(buf);
+            }
+            for (name, target) in included_refs {
+                let mut buf = Vec::new();
+                write_binary_packetline(ref_line(&name, &target).as_bytes(), &mut buf).await?;
+                yield Bytes::from(buf);
+            }
+        }
+        .boxed()
+    }
 }
 
 /// Struct representing the packfile item response generated for the
 /// fetch request command
 pub struct FetchResponse<'a> {
-    /// The stream of packfile items that were generated for the fetch request command
+    /// The stream of packfile items that were generated for the fetch request command.
+    /// Empty if the request was a dry-run
     pub items: BoxStream<'a, Result<PackfileItem>>,
     /// The number of packfile items that were generated for the fetch request command
     pub num_items: usize,
+    /// The breakdown of num_items by Git object type
+    pub object_counts: PackfileObjectCounts,
+    /// The estimated total size (in bytes) of the packfile that would be generated for
+    /// this request. Only populated when the request is a dry-run, since otherwise the
+    /// exact size can be determined from the generated packfile itself
+    pub estimated_size_bytes: Option<u64>,
+    /// The subset of the request's `bases` that are unknown to this repo, i.e. neither
+    /// a commit nor a tag that this repo has ever heard of. Lets the negotiation layer
+    /// distinguish a bogus `have` from one that is simply not an ancestor of the heads
+    pub unknown_bases: Vec<ObjectId>,
 }
 
 impl<'a> FetchResponse<'a> {
-    pub fn new(items: BoxStream<'a, Result<PackfileItem>>, num_items: usize) -> Self {
-        Self { items, num_items }
+    pub fn new(
+        items: BoxStream<'a, Result<PackfileItem>>,
+        num_items: usize,
+        object_counts: PackfileObjectCounts,
+    ) -> Self {
+        Self {
+            items,
+            num_items,
+            object_counts,
+            estimated_size_bytes: None,
+            unknown_bases: Vec::new(),
+        }
+    }
+
+    /// Construct a response for a dry-run fetch request, i.e. one that contains no
+    /// packfile items but only the object counts and an estimate of the total size of
+    /// the packfile that would have been generated
+    pub fn dry_run(object_counts: PackfileObjectCounts, estimated_size_bytes: u64) -> Self {
+        Self {
+            items: stream::empty().boxed(),
+            num_items: 0,
+            object_counts,
+            estimated_size_bytes: Some(estimated_size_bytes),
+            unknown_bases: Vec::new(),
+        }
+    }
+
+    /// Attach the set of caller-provided `bases` that turned out to be unknown to this repo
+    pub fn with_unknown_bases(mut self, unknown_bases: Vec<ObjectId>) -> Self {
+        self.unknown_bases = unknown_bases;
+        self
     }
 }
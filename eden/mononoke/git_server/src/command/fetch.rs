@@ -6,11 +6,15 @@
  */
 
 use anyhow::Context;
+use bookmarks::Freshness;
 use gix_hash::ObjectId;
 use gix_packetline::PacketLineRef;
 use gix_packetline::StreamingPeekableIter;
 use gix_transport::bstr::ByteSlice;
 use protocol::types::FetchRequest;
+use protocol::types::PackfileConcurrency;
+use rustc_hash::FxHashSet;
+use tokio_util::sync::CancellationToken;
 
 const DONE: &[u8] = b"done";
 const THIN_PACK: &[u8] = b"thin-pack";
@@ -205,7 +209,7 @@ impl FetchArgs {
     }
 
     /// Convert the fetch command args into FetchRequest instance
-    pub fn into_request(self) -> FetchRequest {
+    pub fn into_request(self, cancellation_token: Option<CancellationToken>) -> FetchRequest {
         FetchRequest {
             heads: self.wants,
             bases: self.haves,
@@ -218,6 +222,13 @@ impl FetchArgs {
             deepen_not: self.deepen_not,
             deepen_relative: self.deepen_relative,
             filter: self.filter,
+            dry_run: false,
+            freshness: Freshness::MostRecent,
+            concurrency: PackfileConcurrency::default(),
+            max_object_size: None,
+            delta_inclusion: None,
+            exclude_delta_for: FxHashSet::default(),
+            cancellation_token,
         }
     }
 }
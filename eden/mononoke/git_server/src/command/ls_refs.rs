@@ -8,6 +8,7 @@
 use std::collections::HashSet;
 
 use anyhow::Context;
+use bookmarks::Freshness;
 use gix_packetline::PacketLineRef;
 use gix_packetline::StreamingPeekableIter;
 use gix_transport::bstr::ByteSlice;
@@ -94,6 +95,8 @@ impl LsRefsArgs {
             requested_symrefs,
             tag_inclusion,
             requested_refs,
+            freshness: Freshness::MostRecent,
+            bundle_uris: Vec::new(),
         }
     }
 }
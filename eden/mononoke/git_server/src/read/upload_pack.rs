@@ -20,7 +20,6 @@ use gotham::state::FromState;
 use gotham::state::State;
 use gotham_ext::body_ext::BodyExt;
 use gotham_ext::error::HttpError;
-use gotham_ext::response::BytesBody;
 use gotham_ext::response::EmptyBody;
 use gotham_ext::response::ResponseStream;
 use gotham_ext::response::ResponseTryStreamExt;
@@ -42,6 +41,7 @@ use tokio::io::ErrorKind;
 use tokio::sync::mpsc;
 use tokio_util::io::CopyToBytes;
 use tokio_util::io::SinkWriter;
+use tokio_util::sync::CancellationToken;
 use tokio_util::sync::PollSender;
 
 use crate::command::Command;
@@ -269,10 +269,19 @@ pub async fn ls_refs(
         args.into_request(),
     )
     .await?;
-    let mut output = Vec::new();
-    response.write_packetline(&mut output).await?;
-    flush_to_write(&mut output).await?;
-    Ok(BytesBody::new(Bytes::from(output), mime::TEXT_PLAIN))
+    // Refs are streamed out as they're encoded rather than buffered into a single
+    // `Vec` up front, so repos with very large ref sets don't pay for one huge
+    // allocation and a latency spike before the client sees anything.
+    let bytes_stream = ResponseStream::new(try_stream! {
+        for await chunk in response.into_packetline_stream() {
+            yield chunk?;
+        }
+        let mut buf = Vec::with_capacity(FLUSH_LINE.len());
+        flush_to_write(&mut buf).await?;
+        yield Bytes::from(buf);
+    })
+    .end_on_err::<anyhow::Error>();
+    Ok(StreamBody::new(bytes_stream, mime::TEXT_PLAIN))
 }
 
 /// Method responsible for generating the response to fetch command request
@@ -281,6 +290,19 @@ pub async fn fetch(
     args: FetchArgs,
 ) -> Result<impl TryIntoResponse, Error> {
     let (writer, reader) = mpsc::channel::<Bytes>(100_000_000);
+    // Signal the packfile generator to stop deriving and loading further objects as soon
+    // as the client drops its end of the response, instead of letting it run to completion.
+    // The watcher task is aborted once the generator is done so that its extra `Sender`
+    // clone doesn't keep the data channel open forever on the successful-completion path.
+    let cancellation_token = CancellationToken::new();
+    let cancellation_watcher = tokio::spawn({
+        let writer = writer.clone();
+        let cancellation_token = cancellation_token.clone();
+        async move {
+            writer.closed().await;
+            cancellation_token.cancel();
+        }
+    });
     let sink_writer = SinkWriter::new(CopyToBytes::new(
         PollSender::new(writer).sink_map_err(|_| std::io::Error::from(ErrorKind::BrokenPipe)),
     ));
@@ -308,25 +330,33 @@ pub async fn fetch(
     tokio::spawn({
         let request_context = request_context.clone();
         async move {
-            // If we don't need to send back a packfile, just return early
-            if !include_pack {
-                return Ok(());
+            let result: anyhow::Result<()> = async {
+                // If we don't need to send back a packfile, just return early
+                if !include_pack {
+                    return Ok(());
+                }
+                let response_stream = fetch_response(
+                    &request_context.ctx,
+                    &request_context.repo,
+                    args.into_request(Some(cancellation_token)),
+                )
+                .await?;
+                let mut pack_writer = PackfileWriter::new(
+                    sink_writer,
+                    response_stream.num_items as u32,
+                    5000,
+                    DeltaForm::RefAndOffset,
+                );
+                pack_writer.write(response_stream.items).await?;
+                pack_writer.finish().await?;
+                Ok(())
             }
-            let response_stream = fetch_response(
-                &request_context.ctx,
-                &request_context.repo,
-                args.into_request(),
-            )
-            .await?;
-            let mut pack_writer = PackfileWriter::new(
-                sink_writer,
-                response_stream.num_items as u32,
-                5000,
-                DeltaForm::RefAndOffset,
-            );
-            pack_writer.write(response_stream.items).await?;
-            pack_writer.finish().await?;
-            anyhow::Ok(())
+            .await;
+            // Drop the cancellation watcher's `Sender` clone now that the generator is
+            // done, so it doesn't keep the data channel open if the client didn't
+            // disconnect.
+            cancellation_watcher.abort();
+            result
         }
     });
 
@@ -63,20 +63,58 @@ where
     where
         I: IntoIterator<Item = P>,
         PathOrPrefix: From<P>,
+    {
+        self.find_entries_with_excludes(ctx, store, paths_or_prefixes, std::iter::empty())
+    }
+
+    /// Like [`Self::find_entries`], but also prunes any subtree rooted at one of
+    /// `exclude_prefixes` before descending into it, rather than filtering excluded
+    /// entries out of the output after the traversal has already paid to visit them.
+    /// If a path matches both an include and an exclude prefix, the exclude wins.
+    fn find_entries_with_excludes<I, P, J, Q>(
+        &self,
+        ctx: CoreContext,
+        store: Store,
+        paths_or_prefixes: I,
+        exclude_prefixes: J,
+    ) -> BoxStream<
+        'static,
+        Result<
+            (
+                MPath,
+                Entry<Self, <<Self as StoreLoadable<Store>>::Value as Manifest<Store>>::LeafId>,
+            ),
+            Error,
+        >,
+    >
+    where
+        I: IntoIterator<Item = P>,
+        PathOrPrefix: From<P>,
+        J: IntoIterator<Item = Q>,
+        PathOrPrefix: From<Q>,
     {
         let selector = select_path_tree(paths_or_prefixes);
+        let exclude_selector = select_path_tree(exclude_prefixes);
 
-        let init = Some((self.clone(), selector, MPath::ROOT, false));
+        let init = Some((self.clone(), selector, exclude_selector, MPath::ROOT, false));
         (async_stream::stream! {
             let store = &store;
             borrowed!(ctx, store);
             let s = bounded_traversal::bounded_traversal_stream(
                 256,
                 init,
-                move |(manifest_id, selector, path, recursive)| {
+                move |(manifest_id, selector, exclude_selector, path, recursive)| {
                     let (select, subentries) = selector.deconstruct();
+                    let (exclude_select, exclude_subentries) = exclude_selector.deconstruct();
                     cloned!(ctx, store);
                     async move {
+                        // This whole subtree is excluded: don't even load the manifest
+                        // for it, let alone recurse any further into it.
+                        if exclude_select.is_recursive() {
+                            return Ok::<_, Error>((Vec::new(), Vec::new()));
+                        }
+                        let mut exclude_subentries: HashMap<_, _> =
+                            exclude_subentries.into_iter().collect();
                         tokio::spawn(async move {
                             let manifest = manifest_id.load(&ctx, &store).await?;
                             let mut output = Vec::new();
@@ -85,13 +123,24 @@ where
                                 output.push((path.clone(), Entry::Tree(manifest_id)));
                                 let mut stream = manifest.list(&ctx, &store).await?;
                                 while let Some((name, entry)) = stream.try_next().await? {
+                                    let exclude_selector =
+                                        exclude_subentries.remove(&name).unwrap_or_default();
+                                    if exclude_selector.value.is_recursive() {
+                                        continue;
+                                    }
                                     let path = path.join(&name);
                                     match entry {
                                         Entry::Leaf(_) => {
                                             output.push((path.clone(), entry));
                                         }
                                         Entry::Tree(manifest_id) => {
-                                            recurse.push((manifest_id, Default::default(), path, true));
+                                            recurse.push((
+                                                manifest_id,
+                                                Default::default(),
+                                                exclude_selector,
+                                                path,
+                                                true,
+                                            ));
                                         }
                                     }
                                 }
@@ -100,6 +149,11 @@ where
                                     output.push((path.clone(), Entry::Tree(manifest_id)));
                                 }
                                 for (name, selector) in subentries {
+                                    let exclude_selector =
+                                        exclude_subentries.remove(&name).unwrap_or_default();
+                                    if exclude_selector.value.is_recursive() {
+                                        continue;
+                                    }
                                     if let Some(entry) = manifest.lookup(&ctx, &store, &name).await? {
                                         let path = path.join(&name);
                                         match entry {
@@ -109,7 +163,13 @@ where
                                                 }
                                             }
                                             Entry::Tree(manifest_id) => {
-                                                recurse.push((manifest_id, selector, path, false));
+                                                recurse.push((
+                                                    manifest_id,
+                                                    selector,
+                                                    exclude_selector,
+                                                    path,
+                                                    false,
+                                                ));
                                             }
                                         }
                                     }
@@ -6,6 +6,7 @@
  */
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::iter::Peekable;
 
 use anyhow::Error;
@@ -132,8 +133,47 @@ where
     where
         I: IntoIterator<Item = P>,
         PathOrPrefix: From<P>,
+    {
+        self.find_entries_ordered_with_excludes(
+            ctx,
+            store,
+            paths_or_prefixes,
+            std::iter::empty(),
+            after,
+        )
+    }
+
+    /// Like [`Self::find_entries_ordered`], but also prunes any subtree rooted at one
+    /// of `exclude_prefixes` before descending into it. Excluded subtrees are skipped
+    /// at the point they would be recursed into, so they never affect `after` or the
+    /// rest of the ordered traversal, which keeps pagination correct when resuming a
+    /// query with `after` set. If a path matches both an include and an exclude
+    /// prefix, the exclude wins.
+    fn find_entries_ordered_with_excludes<I, P, J, Q>(
+        &self,
+        ctx: CoreContext,
+        store: Store,
+        paths_or_prefixes: I,
+        exclude_prefixes: J,
+        after: impl Into<After>,
+    ) -> BoxStream<
+        'static,
+        Result<
+            (
+                MPath,
+                Entry<Self, <<Self as StoreLoadable<Store>>::Value as Manifest<Store>>::LeafId>,
+            ),
+            Error,
+        >,
+    >
+    where
+        I: IntoIterator<Item = P>,
+        PathOrPrefix: From<P>,
+        J: IntoIterator<Item = Q>,
+        PathOrPrefix: From<Q>,
     {
         let selector = select_path_tree(paths_or_prefixes);
+        let exclude_selector = select_path_tree(exclude_prefixes);
 
         // Schedule a maximum of 256 concurrently unfolding directories.
         let schedule_max = nonzero!(256usize);
@@ -148,7 +188,14 @@ where
 
         let init = Some((
             queue_max.get(),
-            (self.clone(), selector, MPath::ROOT, false, after),
+            (
+                self.clone(),
+                selector,
+                exclude_selector,
+                MPath::ROOT,
+                false,
+                after,
+            ),
         ));
         (async_stream::stream! {
             borrowed!(ctx, store);
@@ -156,10 +203,19 @@ where
                 schedule_max,
                 queue_max,
                 init,
-                move |(manifest_id, selector, path, recursive, after)| {
+                move |(manifest_id, selector, exclude_selector, path, recursive, after)| {
                     let (select, subentries) = selector.deconstruct();
+                    let (exclude_select, exclude_subentries) = exclude_selector.deconstruct();
 
                     async move {
+                        // This whole subtree is excluded: don't even load the manifest
+                        // for it, let alone recurse any further into it.
+                        if exclude_select.is_recursive() {
+                            return Ok::<_, Error>(Vec::new());
+                        }
+                        let mut exclude_subentries: HashMap<_, _> =
+                            exclude_subentries.into_iter().collect();
+
                         let manifest = manifest_id.load(ctx, store).await?;
 
                         let mut output = Vec::new();
@@ -176,6 +232,11 @@ where
                                 if after.skip(&name) {
                                     continue;
                                 }
+                                let exclude_selector =
+                                    exclude_subentries.remove(&name).unwrap_or_default();
+                                if exclude_selector.value.is_recursive() {
+                                    continue;
+                                }
                                 let path = path.join(&name);
                                 match entry {
                                     Entry::Leaf(leaf) => {
@@ -192,6 +253,7 @@ where
                                             (
                                                 manifest_id,
                                                 Default::default(),
+                                                exclude_selector,
                                                 path,
                                                 true,
                                                 after.enter_dir(&name),
@@ -211,6 +273,11 @@ where
                                 if after.skip(&name) {
                                     continue;
                                 }
+                                let exclude_selector =
+                                    exclude_subentries.remove(&name).unwrap_or_default();
+                                if exclude_selector.value.is_recursive() {
+                                    continue;
+                                }
                                 if let Some(entry) = manifest.lookup_weighted(ctx, store, &name).await? {
                                     let path = path.join(&name);
                                     match entry {
@@ -230,6 +297,7 @@ where
                                                 (
                                                     manifest_id,
                                                     selector,
+                                                    exclude_selector,
                                                     path,
                                                     false,
                                                     after.enter_dir(&name),
@@ -1208,6 +1208,7 @@ mod test {
                     service_write_hipster_acl: None,
                     permit_commits_without_parents: false,
                     service_write_restrictions: Default::default(),
+                    default_identity_schemes: Default::default(),
                 },
                 source_control_service_monitoring: Some(SourceControlServiceMonitoring {
                     bookmarks_to_report_age: vec![
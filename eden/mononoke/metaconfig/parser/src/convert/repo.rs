@@ -6,6 +6,7 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -378,12 +379,20 @@ impl Convert for RawSourceControlServiceParams {
             .map(|(name, raw)| Ok((name, raw.convert()?)))
             .collect::<Result<HashMap<_, _>>>()?;
 
+        let default_identity_schemes = self
+            .default_identity_schemes
+            .unwrap_or_default()
+            .into_iter()
+            .map(Convert::convert)
+            .collect::<Result<HashSet<_>>>()?;
+
         Ok(SourceControlServiceParams {
             permit_writes: self.permit_writes,
             permit_service_writes: self.permit_service_writes,
             service_write_hipster_acl: self.service_write_hipster_acl,
             permit_commits_without_parents: self.permit_commits_without_parents,
             service_write_restrictions,
+            default_identity_schemes,
         })
     }
 }
@@ -275,7 +275,7 @@ pub enum ShardedService {
 }
 
 /// Indicates types of commit hashes used in a repo context.
-#[derive(Eq, Clone, Debug, Default, PartialEq)]
+#[derive(Eq, Clone, Debug, Default, Hash, PartialEq)]
 pub enum CommitIdentityScheme {
     /// Hashes are Mercurial hashes.
     #[default]
@@ -1410,6 +1410,11 @@ pub struct SourceControlServiceParams {
 
     /// Whether users can create commits without parents.
     pub permit_commits_without_parents: bool,
+
+    /// Identity schemes to resolve a commit into when a request's
+    /// `identity_schemes` is empty, instead of returning no identities at all.
+    /// Has no effect when the request explicitly names schemes to resolve.
+    pub default_identity_schemes: HashSet<CommitIdentityScheme>,
 }
 
 impl SourceControlServiceParams {
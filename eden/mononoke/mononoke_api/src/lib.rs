@@ -40,9 +40,11 @@ pub use context::SessionContainer;
 
 pub use crate::changeset::ChangesetContext;
 pub use crate::changeset::ChangesetDiffItem;
+pub use crate::changeset::BasenameCaseSensitivity;
 pub use crate::changeset::ChangesetFileOrdering;
 pub use crate::changeset::ChangesetHistoryOptions;
 pub use crate::changeset::Generation;
+pub use crate::changeset::RenameDetectionDiagnostics;
 pub use crate::changeset_path::ChangesetPathContentContext;
 pub use crate::changeset_path::ChangesetPathHistoryOptions;
 pub use crate::changeset_path::PathEntry;
@@ -68,7 +70,9 @@ pub use crate::repo::create_changeset::CreateCopyInfo;
 pub use crate::repo::create_changeset::CreateInfo;
 pub use crate::repo::land_stack::PushrebaseOutcome;
 pub use crate::repo::BookmarkFreshness;
+pub use crate::repo::BookmarkHistoryEntry;
 pub use crate::repo::BookmarkInfo;
+pub use crate::repo::RefEntry;
 pub use crate::repo::Repo;
 pub use crate::repo::RepoContext;
 pub use crate::repo::StoreRequest;
@@ -82,6 +86,7 @@ pub use crate::specifiers::Globalrev;
 pub use crate::specifiers::HgChangesetId;
 pub use crate::specifiers::HgChangesetIdPrefix;
 pub use crate::tree::TreeContext;
+pub use crate::tree::TreeDiffEntry;
 pub use crate::tree::TreeEntry;
 pub use crate::tree::TreeId;
 pub use crate::tree::TreeSummary;
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
@@ -34,6 +35,7 @@ use bonsai_hg_mapping::BonsaiHgMappingRef;
 use bonsai_svnrev_mapping::BonsaiSvnrevMapping;
 use bonsai_svnrev_mapping::BonsaiSvnrevMappingRef;
 use bonsai_tag_mapping::BonsaiTagMapping;
+use bonsai_tag_mapping::BonsaiTagMappingRef;
 use bookmarks::BookmarkCategory;
 use bookmarks::BookmarkKey;
 use bookmarks::BookmarkKind;
@@ -41,8 +43,10 @@ use bookmarks::BookmarkName;
 use bookmarks::BookmarkPagination;
 use bookmarks::BookmarkPrefix;
 use bookmarks::BookmarkUpdateLog;
+use bookmarks::BookmarkUpdateLogEntry;
 use bookmarks::BookmarkUpdateLogArc;
 use bookmarks::BookmarkUpdateLogRef;
+use bookmarks::BookmarkUpdateReason;
 use bookmarks::Bookmarks;
 use bookmarks::BookmarksArc;
 use bookmarks::BookmarksRef;
@@ -84,6 +88,7 @@ use filestore::FetchKey;
 use filestore::FilestoreConfig;
 use filestore::FilestoreConfigRef;
 pub use filestore::StoreRequest;
+use futures::future;
 use futures::stream;
 use futures::stream::Stream;
 use futures::stream::StreamExt;
@@ -761,6 +766,26 @@ pub struct BookmarkInfo {
     pub last_update_timestamp: Timestamp,
 }
 
+/// A single public ref (branch or tag), as returned by `list_all_refs`.
+pub struct RefEntry {
+    pub name: String,
+    pub is_tag: bool,
+    /// Whether this ref is an annotated tag. Always false for branches and
+    /// lightweight tags.
+    pub is_annotated_tag: bool,
+    pub changeset: ChangesetContext,
+}
+
+/// A single entry in a bookmark's move history: the commit the bookmark
+/// pointed to before and after the update, along with why and when the
+/// update happened.
+pub struct BookmarkHistoryEntry {
+    pub old_changeset: Option<ChangesetContext>,
+    pub new_changeset: Option<ChangesetContext>,
+    pub reason: BookmarkUpdateReason,
+    pub timestamp: Timestamp,
+}
+
 /// A context object representing a query to a particular repo.
 impl RepoContext {
     pub async fn new(
@@ -923,6 +948,19 @@ impl RepoContext {
             .await?)
     }
 
+    /// Create a new ephemeral bubble.
+    pub async fn create_bubble(
+        &self,
+        custom_duration: Option<Duration>,
+        labels: Vec<String>,
+    ) -> Result<Bubble, MononokeError> {
+        Ok(self
+            .repo
+            .repo_ephemeral_store()
+            .create_bubble(self.ctx(), custom_duration, labels)
+            .await?)
+    }
+
     // pub(crate) for testing
     pub(crate) async fn changesets(
         &self,
@@ -999,10 +1037,26 @@ impl RepoContext {
                     .await?
             }
             ChangesetSpecifier::GitSha1(git_sha1) => {
-                self.blob_repo()
+                let cs_id = self
+                    .blob_repo()
                     .bonsai_git_mapping()
                     .get_bonsai_from_git_sha1(&self.ctx, git_sha1)
-                    .await?
+                    .await?;
+                // The hash didn't identify a commit directly: it might be the
+                // object id of an annotated tag, which is hashed separately
+                // from the commit it points to. Peel it to the commit it
+                // tags so tag and commit specifiers resolve consistently.
+                match cs_id {
+                    Some(cs_id) => Some(cs_id),
+                    None => self
+                        .inner_repo()
+                        .bonsai_tag_mapping()
+                        .get_entries_by_tag_hashes(vec![git_sha1])
+                        .await?
+                        .into_iter()
+                        .next()
+                        .map(|entry| entry.changeset_id),
+                }
             }
         };
         Ok(id)
@@ -1034,6 +1088,40 @@ impl RepoContext {
         Ok(cs_id.map(|cs_id| ChangesetContext::new(self.clone(), cs_id)))
     }
 
+    /// Resolve multiple bookmarks to changesets in a single call.
+    ///
+    /// Backed by one listing of the warm bookmarks cache rather than a lookup
+    /// per bookmark, so resolving many bookmarks (e.g. to render a branch
+    /// selector) doesn't cost one cache round-trip per name. Bookmarks that
+    /// don't exist are omitted from the result.
+    pub async fn resolve_bookmarks(
+        &self,
+        bookmarks: &[String],
+    ) -> Result<BTreeMap<String, ChangesetContext>, MononokeError> {
+        let all_bookmarks = self
+            .warm_bookmarks_cache()
+            .list(
+                &self.ctx,
+                &BookmarkPrefix::empty(),
+                &BookmarkPagination::FromStart,
+                None,
+            )
+            .await?;
+        let cs_ids_by_name: HashMap<String, ChangesetId> = all_bookmarks
+            .into_iter()
+            .map(|(key, (cs_id, _kind))| (key.to_string(), cs_id))
+            .collect();
+
+        Ok(bookmarks
+            .iter()
+            .filter_map(|name| {
+                cs_ids_by_name
+                    .get(name)
+                    .map(|&cs_id| (name.clone(), ChangesetContext::new(self.clone(), cs_id)))
+            })
+            .collect())
+    }
+
     /// Resolve a changeset id by its prefix
     pub async fn resolve_changeset_id_prefix(
         &self,
@@ -1297,6 +1385,106 @@ impl RepoContext {
         }))
     }
 
+    /// Return the sequence of updates applied to a bookmark, most recent first, as
+    /// recorded in the bookmark update log.
+    ///
+    /// The update log only records where a bookmark moved *to*, not where it moved
+    /// from, so each entry's `old_changeset` is derived from the `new_changeset` of
+    /// the entry immediately after it in the (descending) log order. An entry with
+    /// no following entry (because the log has been trimmed or the bookmark was
+    /// just created) gets `old_changeset: None`.
+    pub async fn bookmark_history(
+        &self,
+        bookmark: impl AsRef<str>,
+        limit: u64,
+        skip: u64,
+    ) -> Result<Vec<BookmarkHistoryEntry>, MononokeError> {
+        let bookmark = BookmarkKey::new(bookmark.as_ref())
+            .map_err(|e| MononokeError::InvalidRequest(e.to_string()))?;
+
+        // Fetch one extra, older entry beyond the requested page so the oldest
+        // entry in the page can derive its `old_changeset` from it.
+        let max_rec = limit.saturating_add(1).min(u32::MAX as u64) as u32;
+        let entries: Vec<_> = self
+            .repo
+            .blob_repo()
+            .bookmark_update_log()
+            .list_bookmark_log_entries(
+                self.ctx.clone(),
+                bookmark,
+                max_rec,
+                Some(skip as u32),
+                Freshness::MaybeStale,
+            )
+            .try_collect()
+            .await?;
+
+        let history = entries
+            .iter()
+            .take(limit as usize)
+            .enumerate()
+            .map(|(index, (_id, new_changeset_id, reason, timestamp))| {
+                let old_changeset_id = entries.get(index + 1).and_then(|(_, cs_id, _, _)| *cs_id);
+                BookmarkHistoryEntry {
+                    old_changeset: old_changeset_id
+                        .map(|cs_id| ChangesetContext::new(self.clone(), cs_id)),
+                    new_changeset: new_changeset_id
+                        .map(|cs_id| ChangesetContext::new(self.clone(), cs_id)),
+                    reason: *reason,
+                    timestamp: *timestamp,
+                }
+            })
+            .collect();
+
+        Ok(history)
+    }
+
+    /// Return the bookmarks that have changed since `since_id`, as recorded in the bookmark
+    /// update log, along with the changeset each now points to (or `None` if it was deleted).
+    ///
+    /// This is intended for mirroring daemons that otherwise have to re-list every bookmark on
+    /// every poll: `since_id` is a bookmark update log entry id, and only entries after it
+    /// are read, so only the bookmarks that actually moved need to be re-fetched. The result
+    /// carries no log entry ids. At most 10,000 log entries are read per call; changes logged
+    /// after those are not included. If a bookmark moved more than once within the entries
+    /// read, only its last position among them is returned.
+    pub async fn bookmarks_changed_since(
+        &self,
+        since_id: u64,
+    ) -> Result<Vec<(String, Option<ChangesetContext>)>, MononokeError> {
+        const MAX_BOOKMARK_LOG_ENTRIES: u64 = 10_000;
+
+        let entries: Vec<BookmarkUpdateLogEntry> = self
+            .repo
+            .blob_repo()
+            .bookmark_update_log()
+            .read_next_bookmark_log_entries(
+                self.ctx.clone(),
+                since_id,
+                MAX_BOOKMARK_LOG_ENTRIES,
+                Freshness::MaybeStale,
+            )
+            .try_collect()
+            .await?;
+
+        // Entries are returned in ascending id order, so the last entry seen for a given
+        // bookmark is its final position as of the end of the queried range.
+        let mut changed = BTreeMap::new();
+        for entry in entries {
+            changed.insert(entry.bookmark_name.into_string(), entry.to_changeset_id);
+        }
+
+        Ok(changed
+            .into_iter()
+            .map(|(name, cs_id)| {
+                (
+                    name,
+                    cs_id.map(|cs_id| ChangesetContext::new(self.clone(), cs_id)),
+                )
+            })
+            .collect())
+    }
+
     /// Get a list of bookmarks.
     pub async fn list_bookmarks(
         &self,
@@ -1304,6 +1492,7 @@ impl RepoContext {
         prefix: Option<&str>,
         after: Option<&str>,
         limit: Option<u64>,
+        categories: &[BookmarkCategory],
     ) -> Result<impl Stream<Item = Result<(String, ChangesetId), MononokeError>> + '_, MononokeError>
     {
         if include_scratch {
@@ -1354,7 +1543,7 @@ impl RepoContext {
                     self.ctx.clone(),
                     BookmarkFreshness::MaybeStale,
                     &prefix,
-                    BookmarkCategory::ALL,
+                    categories,
                     BookmarkKind::ALL,
                     &pagination,
                     limit.unwrap_or(std::u64::MAX),
@@ -1379,12 +1568,119 @@ impl RepoContext {
         } else {
             // Public bookmarks can be fetched from the warm bookmarks cache.
             let cache = self.warm_bookmarks_cache();
-            Ok(
-                stream::iter(cache.list(&self.ctx, &prefix, &pagination, limit).await?)
-                    .map(|(bookmark, (cs_id, _kind))| Ok((bookmark.into_string(), cs_id)))
-                    .boxed(),
-            )
+            Ok(stream::iter(cache.list(&self.ctx, &prefix, &pagination, limit).await?)
+                .filter_map(move |(bookmark, (cs_id, _kind))| {
+                    future::ready(if categories.contains(bookmark.category()) {
+                        Some(Ok((bookmark.into_string(), cs_id)))
+                    } else {
+                        None
+                    })
+                })
+                .boxed())
+        }
+    }
+
+    /// Get a list of all public refs (branches and tags), with their kind
+    /// and, for tags, whether they're annotated. Backed by the same public
+    /// bookmark enumeration (the warm bookmarks cache, restricted to
+    /// publishing bookmarks) that the Git protocol's pack generator uses to
+    /// compute the refs it advertises.
+    pub async fn list_all_refs(
+        &self,
+        prefix: Option<&str>,
+        after: Option<&str>,
+        limit: Option<u64>,
+    ) -> Result<(Vec<RefEntry>, Option<String>), MononokeError> {
+        let prefix = match prefix {
+            Some(prefix) => BookmarkPrefix::new(prefix).map_err(|e| {
+                MononokeError::InvalidRequest(format!("invalid ref prefix '{}': {}", prefix, e))
+            })?,
+            None => BookmarkPrefix::empty(),
+        };
+        let pagination = match after {
+            Some(after) => {
+                let name = BookmarkName::new(after).map_err(|e| {
+                    MononokeError::InvalidRequest(format!("invalid ref name '{}': {}", after, e))
+                })?;
+                BookmarkPagination::After(name)
+            }
+            None => BookmarkPagination::FromStart,
+        };
+        let cache = self.warm_bookmarks_cache();
+        // `cache.list` has no category parameter: it always queries every category
+        // (including `Note`, i.e. git notes) before `limit` is applied. Filtering
+        // Branch/Tag afterwards, as below, means a run of `Note` bookmarks inside a
+        // page can leave us with fewer than `limit` refs even though more
+        // branches/tags exist past the (unfiltered) point the cache truncated at.
+        // Keep re-fetching subsequent raw pages, filtering each, until either we
+        // have `limit` refs or the cache itself runs out, so pagination can't stop
+        // early just because this page happened to be Note-heavy.
+        let mut bookmarks: Vec<_> = Vec::new();
+        let mut next_pagination = pagination;
+        let mut exhausted = true;
+        loop {
+            let page = cache.list(&self.ctx, &prefix, &next_pagination, limit).await?;
+            let last_seen = page.last().map(|(bookmark, _)| bookmark.clone());
+            exhausted = match limit {
+                Some(limit) => (page.len() as u64) < limit,
+                None => true,
+            };
+            bookmarks.extend(page.into_iter().filter(|(bookmark, _)| {
+                matches!(
+                    bookmark.category(),
+                    BookmarkCategory::Branch | BookmarkCategory::Tag
+                )
+            }));
+            let have_enough = matches!(limit, Some(limit) if bookmarks.len() as u64 >= limit);
+            match last_seen {
+                Some(last_seen) if !exhausted && !have_enough => {
+                    next_pagination = BookmarkPagination::After(last_seen.into_name());
+                }
+                _ => break,
+            }
+        }
+        if let Some(limit) = limit {
+            bookmarks.truncate(limit as usize);
         }
+        let continue_after = match limit {
+            Some(limit) if !exhausted && bookmarks.len() as u64 >= limit => {
+                bookmarks.last().map(|(bookmark, _)| bookmark.to_string())
+            }
+            _ => None,
+        };
+        // Only annotated tags have an entry in the bonsai tag mapping, keyed by
+        // the commit they point to; look up just the commits of the tags on this
+        // page instead of scanning every tag in the repo.
+        let tag_cs_ids = bookmarks
+            .iter()
+            .filter(|(bookmark, _)| bookmark.is_tag())
+            .map(|(_, (cs_id, _kind))| *cs_id)
+            .collect::<Vec<_>>();
+        let annotated_tag_names = if tag_cs_ids.is_empty() {
+            HashSet::new()
+        } else {
+            self.inner_repo()
+                .bonsai_tag_mapping()
+                .get_entries_by_changesets(tag_cs_ids)
+                .await?
+                .into_iter()
+                .map(|entry| entry.tag_name)
+                .collect::<HashSet<_>>()
+        };
+        let refs = bookmarks
+            .into_iter()
+            .map(|(bookmark, (cs_id, _kind))| {
+                let is_tag = bookmark.is_tag();
+                let is_annotated_tag = is_tag && annotated_tag_names.contains(&bookmark.to_string());
+                RefEntry {
+                    name: bookmark.into_string(),
+                    is_tag,
+                    is_annotated_tag,
+                    changeset: ChangesetContext::new(self.clone(), cs_id),
+                }
+            })
+            .collect();
+        Ok((refs, continue_after))
     }
 
     /// Get a stack for the list of heads (up to the first public commit).
@@ -135,7 +135,7 @@ impl SparseProfileMonitoring {
                     self.sparse_config.sparse_profiles_location.as_bytes(),
                 )?];
                 let files = changeset
-                    .find_files(Some(prefixes), None, None, ChangesetFileOrdering::Unordered)
+                    .find_files(Some(prefixes), None, None, None, ChangesetFileOrdering::Unordered)
                     .await?;
                 files
                     .try_filter_map(|path| async move {
@@ -161,7 +161,7 @@ impl SparseProfileMonitoring {
                     .collect::<Result<Vec<_>, _>>()
                     .map_err(|error| MononokeError::InvalidRequest(error.to_string()))?;
                 changeset
-                    .find_files(Some(prefixes), None, None, ChangesetFileOrdering::Unordered)
+                    .find_files(Some(prefixes), None, None, None, ChangesetFileOrdering::Unordered)
                     .await?
                     .map(|p| {
                         p.and_then(|path| {
@@ -366,7 +366,7 @@ async fn get_bonsai_size_change(
 ) -> Result<Vec<BonsaiSizeChange>> {
     let diff_items = btreeset! { ChangesetDiffItem::FILES };
     let diff = current
-        .diff_unordered(other, true, None, diff_items)
+        .diff_unordered(other, true, false, None, true, diff_items)
         .await?;
     let res = stream::iter(diff)
         .map(|diff| async move {
@@ -26,6 +26,7 @@ use crate::ChangesetPathDiffContext;
 use crate::CoreContext;
 use crate::HgChangesetId;
 use crate::Mononoke;
+use crate::RenameDetectionDiagnostics;
 
 #[fbinit::test]
 async fn test_diff_with_moves(fb: FacebookInit) -> Result<(), Error> {
@@ -58,7 +59,9 @@ async fn test_diff_with_moves(fb: FacebookInit) -> Result<(), Error> {
         .diff_unordered(
             &repo.changeset(root).await?.context("commit not found")?,
             true, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
         )
         .await?;
@@ -107,7 +110,9 @@ async fn test_diff_with_multiple_copies(fb: FacebookInit) -> Result<(), Error> {
         .diff_unordered(
             &repo.changeset(root).await?.context("commit not found")?,
             true, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
         )
         .await?;
@@ -163,7 +168,9 @@ async fn test_diff_with_multiple_moves(fb: FacebookInit) -> Result<(), Error> {
         .diff_unordered(
             &repo.changeset(root).await?.context("commit not found")?,
             true, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
         )
         .await?;
@@ -194,6 +201,80 @@ async fn test_diff_with_multiple_moves(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_diff_with_rename_diagnostics(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let repo: Repo = test_repo_factory::build_empty(fb).await?;
+    let root = CreateCommitContext::new_root(&ctx, &repo)
+        .add_file("file_to_move", "context1")
+        .commit()
+        .await?;
+
+    // No copy info, so `find_renames_across_tree`'s content-based matching is what
+    // turns this delete/add pair into a move, not the usual copy-info path.
+    let commit_with_move = CreateCommitContext::new(&ctx, &repo, vec![root])
+        .add_file("file_moved", "context1")
+        .delete_file("file_to_move")
+        .commit()
+        .await?;
+
+    let mononoke = Mononoke::new_test(vec![("test".to_string(), repo)]).await?;
+
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists")
+        .build()
+        .await?;
+    let commit_with_move_ctx = repo
+        .changeset(commit_with_move)
+        .await?
+        .ok_or_else(|| anyhow!("commit not found"))?;
+    let root_ctx = repo.changeset(root).await?.context("commit not found")?;
+
+    let (diff, diagnostics) = commit_with_move_ctx
+        .diff_unordered_with_rename_diagnostics(
+            &root_ctx,
+            true, /* include_copies_renames */
+            true, /* find_renames_across_tree */
+            None, /* path_restrictions */
+            true, /* recurse_under_paths */
+            btreeset! {ChangesetDiffItem::FILES},
+        )
+        .await?;
+
+    assert_eq!(diff.len(), 1);
+    match diff.first() {
+        Some(ChangesetPathDiffContext::Moved(to, from)) => {
+            assert_eq!(to.path(), &MPath::try_from("file_moved")?);
+            assert_eq!(from.path(), &MPath::try_from("file_to_move")?);
+        }
+        other => panic!("unexpected diff: {:?}", other),
+    }
+    assert_eq!(diagnostics.delete_candidates_examined, 1);
+    assert_eq!(diagnostics.add_candidates_examined, 1);
+    assert_eq!(diagnostics.renames_detected, 1);
+    assert!(!diagnostics.candidate_cap_reached);
+
+    // With `find_renames_across_tree` off, no pass runs at all, so diagnostics stay
+    // at their all-zero default even though the diff itself now reports a plain
+    // delete and a plain add instead of a move.
+    let (diff, diagnostics) = commit_with_move_ctx
+        .diff_unordered_with_rename_diagnostics(
+            &root_ctx,
+            true,  /* include_copies_renames */
+            false, /* find_renames_across_tree */
+            None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
+            btreeset! {ChangesetDiffItem::FILES},
+        )
+        .await?;
+    assert_eq!(diff.len(), 2);
+    assert_eq!(diagnostics, RenameDetectionDiagnostics::default());
+
+    Ok(())
+}
+
 fn check_root_dir_diff(diff: Option<&ChangesetPathDiffContext>) -> Result<(), Error> {
     match diff {
         Some(ChangesetPathDiffContext::Changed(path1, path2)) if path1.path() == path2.path() => {
@@ -230,7 +311,7 @@ async fn test_diff_with_dirs(fb: FacebookInit) -> Result<(), Error> {
         .expect("other changeset exists");
 
     let diff: Vec<_> = cs
-        .diff_unordered(&other_cs, false, None, btreeset! {ChangesetDiffItem::TREES})
+        .diff_unordered(&other_cs, false, false, None, true, btreeset! {ChangesetDiffItem::TREES})
         .await?;
     assert_eq!(diff.len(), 6);
     check_root_dir_diff(diff.first())?;
@@ -254,7 +335,7 @@ async fn test_diff_with_dirs(fb: FacebookInit) -> Result<(), Error> {
 
     // Added
     let diff: Vec<_> = cs
-        .diff_unordered(&other_cs, false, None, btreeset! {ChangesetDiffItem::TREES})
+        .diff_unordered(&other_cs, false, false, None, true, btreeset! {ChangesetDiffItem::TREES})
         .await?;
     assert_eq!(diff.len(), 5);
     check_root_dir_diff(diff.first())?;
@@ -344,7 +425,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             None,
@@ -358,7 +441,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             Some(8),
@@ -369,7 +454,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered {
                 after: Some(file_list[7].try_into()?),
@@ -382,7 +469,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered {
                 after: Some(file_list[15].try_into()?),
@@ -424,7 +513,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             &commit_ctx,
             true, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             None,
@@ -442,7 +533,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             &commit_ctx,
             true, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES, ChangesetDiffItem::TREES},
             ChangesetFileOrdering::Ordered { after: None },
             None,
@@ -461,7 +554,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             None,  /* path_restrictions */
+            true,  /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::TREES},
             ChangesetFileOrdering::Ordered { after: None },
             None,
@@ -484,7 +579,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             path_restrictions.clone(),
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             Some(3),
@@ -498,7 +595,9 @@ async fn test_ordered_diff(fb: FacebookInit) -> Result<(), Error> {
         .diff(
             root_ctx,
             false, /* include_copies_renames */
+            false, /* find_renames_across_tree */
             path_restrictions,
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered {
                 after: Some(filtered_changed_files_list[2].try_into()?),
@@ -547,6 +646,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             None, /* limit */
@@ -558,6 +658,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             Some(8),
@@ -568,6 +669,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered {
                 after: Some(file_list[7].try_into()?),
@@ -580,6 +682,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered {
                 after: Some(file_list[15].try_into()?),
@@ -598,6 +701,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             path_restrictions.clone(),
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             Some(3),
@@ -610,6 +714,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES, ChangesetDiffItem::TREES},
             ChangesetFileOrdering::Ordered { after: None },
             None, /* limit */
@@ -626,6 +731,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::TREES},
             ChangesetFileOrdering::Ordered { after: None },
             None, /* limit */
@@ -653,6 +759,7 @@ async fn test_ordered_root_diff(fb: FacebookInit) -> Result<(), Error> {
     let diff = commit2_ctx
         .diff_root(
             None, /* path_restrictions */
+            true, /* recurse_under_paths */
             btreeset! {ChangesetDiffItem::FILES},
             ChangesetFileOrdering::Ordered { after: None },
             None,
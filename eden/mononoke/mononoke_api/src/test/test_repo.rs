@@ -272,6 +272,46 @@ async fn commit_is_ancestor_of(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn commit_is_ancestor_of_generation_short_circuit(fb: FacebookInit) -> Result<(), Error> {
+    // `is_ancestor_of` should short-circuit to `false`, without performing a graph
+    // traversal, whenever the candidate ancestor's generation number exceeds the
+    // descendant's generation number, since ancestors can never have a higher
+    // generation than their descendants.
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = Mononoke::new_test(vec![(
+        "test".to_string(),
+        BranchUneven::get_custom_test_repo(fb).await,
+    )])
+    .await?;
+    let repo = mononoke
+        .repo(ctx, "test")
+        .await?
+        .expect("repo exists")
+        .build()
+        .await?;
+    let descendant = repo
+        .changeset(HgChangesetId::from_str(
+            "5d43888a3c972fe68c224f93d41b30e9f888df7c", // branch 1 near top
+        )?)
+        .await?
+        .expect("changeset exists");
+    let unrelated_ancestor_generation = repo
+        .changeset(HgChangesetId::from_str(
+            "1d8a907f7b4bf50c6a09c16361e2205047ecc5e5", // branch 2
+        )?)
+        .await?
+        .expect("changeset exists");
+
+    // Sanity check the premise of the short-circuit: the "descendant" here does
+    // in fact have a higher generation number than the unrelated changeset.
+    assert!(descendant.generation().await? > unrelated_ancestor_generation.generation().await?);
+
+    assert!(!descendant.is_ancestor_of(unrelated_ancestor_generation.id()).await?);
+
+    Ok(())
+}
+
 async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
     let mononoke = Mononoke::new_test(vec![(
@@ -315,6 +355,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
             None,
             None,
             None,
+            None,
             ChangesetFileOrdering::Ordered { after: None },
         )
         .await?
@@ -328,6 +369,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
             None,
             None,
             None,
+            None,
             ChangesetFileOrdering::Ordered {
                 after: Some(MPath::try_from("dir1/subdir1/subsubdir2/file_1")?),
             },
@@ -370,6 +412,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
             ]),
             None,
             None,
+            None,
             ChangesetFileOrdering::Ordered {
                 after: Some(MPath::try_from("")?),
             },
@@ -388,6 +431,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
             ]),
             None,
             None,
+            None,
             ChangesetFileOrdering::Ordered {
                 after: Some(MPath::try_from("dir1/subdir1/subsubdir1/file_1")?),
             },
@@ -415,6 +459,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Basenames ordered
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             Some(vec![String::from("file_1")]),
             None,
@@ -430,6 +475,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Basenames ordered after
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             Some(vec![String::from("file_1")]),
             None,
@@ -469,6 +515,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
                 MPath::try_from("dir2")?,
             ]),
+            None,
             Some(vec![String::from("file_2"), String::from("file_1_in_dir2")]),
             None,
             ChangesetFileOrdering::Ordered {
@@ -492,6 +539,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
                 MPath::try_from("dir2")?,
             ]),
+            None,
             Some(vec![String::from("file_2"), String::from("file_1_in_dir2")]),
             None,
             ChangesetFileOrdering::Ordered {
@@ -507,6 +555,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes
     let mut files: Vec<_> = cs
         .find_files(
+            None,
             None,
             None,
             Some(vec![String::from("_1"), String::from("_2")]),
@@ -527,6 +576,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes, ordered
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             None,
             Some(vec![String::from("_1"), String::from("_2")]),
@@ -548,6 +598,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes, ordered after
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             None,
             Some(vec![String::from("_1"), String::from("_2")]),
@@ -572,6 +623,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
             ]),
             None,
+            None,
             Some(vec![String::from("1"), String::from("2")]),
             ChangesetFileOrdering::Unordered,
         )
@@ -594,6 +646,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
             ]),
             None,
+            None,
             Some(vec![String::from("1"), String::from("2")]),
             ChangesetFileOrdering::Ordered {
                 after: Some(MPath::try_from("")?),
@@ -617,6 +670,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
             ]),
             None,
+            None,
             Some(vec![String::from("1"), String::from("2")]),
             ChangesetFileOrdering::Ordered {
                 after: Some(MPath::try_from("dir1/subdir1/subsubdir1/file_1")?),
@@ -634,6 +688,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes, basenames
     let mut files: Vec<_> = cs
         .find_files(
+            None,
             None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
@@ -657,6 +712,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes, basenames, ordered
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
@@ -695,6 +751,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
     // Suffixes, basenames, ordered after
     let files: Vec<_> = cs
         .find_files(
+            None,
             None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
@@ -727,6 +784,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
                 MPath::try_from("dir2")?,
             ]),
+            None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
             ChangesetFileOrdering::Unordered,
@@ -748,6 +806,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
                 MPath::try_from("dir2")?,
             ]),
+            None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
             ChangesetFileOrdering::Ordered {
@@ -770,6 +829,7 @@ async fn commit_find_files_impl(fb: FacebookInit) -> Result<(), Error> {
                 MPath::try_from("dir1/subdir1/subsubdir2")?,
                 MPath::try_from("dir2")?,
             ]),
+            None,
             Some(vec![String::from("file_1_in_dir2")]),
             Some(vec![String::from("1")]),
             ChangesetFileOrdering::Ordered {
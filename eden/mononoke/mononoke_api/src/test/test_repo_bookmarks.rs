@@ -9,7 +9,9 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use anyhow::Result;
+use bookmarks::BookmarkCategory;
 use bookmarks::BookmarkKey;
+use bookmarks::BookmarkName;
 use bookmarks::BookmarkUpdateReason;
 use bookmarks::BookmarksRef;
 use context::CoreContext;
@@ -123,7 +125,7 @@ async fn list_bookmarks(fb: FacebookInit) -> Result<()> {
     let (repo, changesets) = init_repo(&ctx).await?;
 
     assert_eq!(
-        repo.list_bookmarks(false, None, None, None)
+        repo.list_bookmarks(false, None, None, None, BookmarkCategory::ALL)
             .await?
             .try_collect::<Vec<_>>()
             .await?,
@@ -131,7 +133,7 @@ async fn list_bookmarks(fb: FacebookInit) -> Result<()> {
     );
 
     assert_eq!(
-        repo.list_bookmarks(true, Some("scratch/"), None, Some(3))
+        repo.list_bookmarks(true, Some("scratch/"), None, Some(3), BookmarkCategory::ALL)
             .await?
             .try_collect::<Vec<_>>()
             .await?,
@@ -142,7 +144,7 @@ async fn list_bookmarks(fb: FacebookInit) -> Result<()> {
     );
 
     assert_eq!(
-        repo.list_bookmarks(true, Some("scratch/"), Some("scratch/branch"), Some(3))
+        repo.list_bookmarks(true, Some("scratch/"), Some("scratch/branch"), Some(3), BookmarkCategory::ALL)
             .await?
             .try_collect::<Vec<_>>()
             .await?,
@@ -150,3 +152,50 @@ async fn list_bookmarks(fb: FacebookInit) -> Result<()> {
     );
     Ok(())
 }
+
+#[fbinit::test]
+async fn list_all_refs_skips_notes_across_page_boundary(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (repo, changesets) = init_repo(&ctx).await?;
+
+    // "aab" is a Note (git notes aren't refs and must never be returned by
+    // `list_all_refs`), sorting alphabetically between the two Branch bookmarks
+    // "aaa" and "aac". With `limit: 2`, the first underlying cache page (which has
+    // no category filter) is ["aaa", "aab"], so filtering out the Note after
+    // truncation would leave only "aaa" and wrongly report no more refs, even
+    // though "aac" and "trunk" are still there.
+    let mut txn = repo.blob_repo().bookmarks().create_transaction(ctx.clone());
+    txn.force_set(
+        &BookmarkKey::new("aaa")?,
+        changesets["B"],
+        BookmarkUpdateReason::TestMove,
+    )?;
+    txn.force_set(
+        &BookmarkKey::with_name_and_category(BookmarkName::new("aab")?, BookmarkCategory::Note),
+        changesets["C"],
+        BookmarkUpdateReason::TestMove,
+    )?;
+    txn.force_set(
+        &BookmarkKey::new("aac")?,
+        changesets["D"],
+        BookmarkUpdateReason::TestMove,
+    )?;
+    txn.commit().await?;
+    repo.warm_bookmarks_cache().sync(&ctx).await;
+
+    let (refs, continue_after) = repo.list_all_refs(None, None, Some(2)).await?;
+    assert_eq!(
+        refs.iter().map(|r| r.name.clone()).collect::<Vec<_>>(),
+        vec![String::from("aaa"), String::from("aac")],
+    );
+    assert_eq!(continue_after, Some(String::from("aac")));
+
+    let (refs, continue_after) = repo.list_all_refs(None, continue_after.as_deref(), None).await?;
+    assert_eq!(
+        refs.iter().map(|r| r.name.clone()).collect::<Vec<_>>(),
+        vec![String::from("trunk")],
+    );
+    assert_eq!(continue_after, None);
+
+    Ok(())
+}
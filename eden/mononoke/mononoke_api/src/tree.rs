@@ -11,19 +11,51 @@ use anyhow::Error;
 use blobstore::Loadable;
 use blobstore::LoadableError;
 use cloned::cloned;
+use context::CoreContext;
+use futures::future::try_join;
+use futures::TryStreamExt;
 use futures_lazy_shared::LazyShared;
+use manifest::Diff as ManifestDiff;
+use manifest::Entry as ManifestEntry;
+use manifest::ManifestOps;
 use mononoke_types::fsnode::Fsnode;
+use mononoke_types::fsnode::FsnodeFile;
 // An entry within a tree list (either a file or subdirectory).
 pub use mononoke_types::fsnode::FsnodeEntry as TreeEntry;
 // Summary information about the files in a tree.
 pub use mononoke_types::fsnode::FsnodeSummary as TreeSummary;
 // Trees are identified by their FsnodeId.
 pub use mononoke_types::FsnodeId as TreeId;
+use mononoke_types::MPath;
+use repo_blobstore::RepoBlobstore;
 use repo_blobstore::RepoBlobstoreRef;
 
 use crate::errors::MononokeError;
 use crate::repo::RepoContext;
 
+/// A single difference between two trees, as found by `TreeContext::diff`.
+///
+/// Unlike `ChangesetPathDiffContext`, there is no copy/rename detection here: two
+/// arbitrary trees have no shared history to infer a copy or move from, only their
+/// own content.
+pub enum TreeDiffEntry {
+    AddedFile(MPath, FsnodeFile),
+    RemovedFile(MPath, FsnodeFile),
+    ChangedFile(MPath, FsnodeFile, FsnodeFile),
+    AddedTree(MPath, TreeId, TreeSummary),
+    RemovedTree(MPath, TreeId, TreeSummary),
+    ChangedTree(MPath, TreeId, TreeSummary, TreeId, TreeSummary),
+}
+
+async fn load_tree_summary(
+    ctx: &CoreContext,
+    blobstore: &RepoBlobstore,
+    id: TreeId,
+) -> Result<TreeSummary, MononokeError> {
+    let fsnode = id.load(ctx, blobstore).await.map_err(Error::from)?;
+    Ok(fsnode.summary().clone())
+}
+
 #[derive(Clone)]
 pub struct TreeContext {
     repo: RepoContext,
@@ -117,4 +149,73 @@ impl TreeContext {
             .map(|(elem, entry)| (String::from_utf8_lossy(elem.as_ref()).to_string(), entry));
         Ok(entries)
     }
+
+    /// Returns the differences between this tree and `other`.
+    ///
+    /// `self` is considered the "new" tree (so entries missing there are "Removed")
+    /// `other` is considered the "old" tree (so entries missing there are "Added")
+    pub async fn diff(&self, other: &Self) -> Result<Vec<TreeDiffEntry>, MononokeError> {
+        let ctx = self.repo().ctx().clone();
+        let blobstore = self.repo().blob_repo().repo_blobstore().clone();
+
+        // `ManifestOps::filtered_diff` is backwards: the receiver is the "old" tree
+        // (entries missing there are "Added") and the argument is the "new" tree
+        // (entries missing there are "Removed").
+        let diff = other
+            .id
+            .filtered_diff(
+                ctx.clone(),
+                blobstore.clone(),
+                self.id,
+                blobstore.clone(),
+                Some,
+                |_| true,
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(MononokeError::from)?;
+
+        let mut entries = Vec::with_capacity(diff.len());
+        for diff_entry in diff {
+            let entry = match diff_entry {
+                ManifestDiff::Added(path, ManifestEntry::Leaf(file)) => {
+                    TreeDiffEntry::AddedFile(path, file)
+                }
+                ManifestDiff::Removed(path, ManifestEntry::Leaf(file)) => {
+                    TreeDiffEntry::RemovedFile(path, file)
+                }
+                ManifestDiff::Changed(
+                    path,
+                    ManifestEntry::Leaf(from_file),
+                    ManifestEntry::Leaf(to_file),
+                ) => TreeDiffEntry::ChangedFile(path, to_file, from_file),
+                ManifestDiff::Added(path, ManifestEntry::Tree(id)) => {
+                    let summary = load_tree_summary(&ctx, &blobstore, id).await?;
+                    TreeDiffEntry::AddedTree(path, id, summary)
+                }
+                ManifestDiff::Removed(path, ManifestEntry::Tree(id)) => {
+                    let summary = load_tree_summary(&ctx, &blobstore, id).await?;
+                    TreeDiffEntry::RemovedTree(path, id, summary)
+                }
+                ManifestDiff::Changed(
+                    path,
+                    ManifestEntry::Tree(from_id),
+                    ManifestEntry::Tree(to_id),
+                ) => {
+                    let (from_summary, to_summary) = try_join(
+                        load_tree_summary(&ctx, &blobstore, from_id),
+                        load_tree_summary(&ctx, &blobstore, to_id),
+                    )
+                    .await?;
+                    TreeDiffEntry::ChangedTree(path, to_id, to_summary, from_id, from_summary)
+                }
+                // A leaf changing into a tree (or vice versa) is represented as a
+                // removal plus an addition of the other kind, same as
+                // `ChangesetContext::diff_impl`'s catch-all, so this never happens.
+                ManifestDiff::Changed(..) => continue,
+            };
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
 }
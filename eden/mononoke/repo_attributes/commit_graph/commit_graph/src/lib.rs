@@ -202,6 +202,40 @@ impl CommitGraph {
         Ok(frontier.highest_generation_contains(ancestor, target_gen))
     }
 
+    /// For a batch of candidate changesets, returns which of them are
+    /// ancestors of any of the given heads, reusing a single ancestors
+    /// frontier instead of recomputing it from scratch for each candidate.
+    ///
+    /// Ancestry is inclusive: a changeset is its own ancestor.
+    pub async fn reachable_from_any(
+        &self,
+        ctx: &CoreContext,
+        heads: Vec<ChangesetId>,
+        candidates: Vec<ChangesetId>,
+    ) -> Result<HashMap<ChangesetId, bool>> {
+        let (mut frontier, candidate_edges) = futures::try_join!(
+            self.frontier(ctx, heads),
+            self.storage.fetch_many_edges(ctx, &candidates, Prefetch::None),
+        )?;
+
+        let mut candidates_by_generation = candidates
+            .iter()
+            .map(|cs_id| Ok((candidate_edges
+                .get(cs_id)
+                .ok_or_else(|| anyhow!("changeset {} not found in commit graph", cs_id))?
+                .node
+                .generation, *cs_id)))
+            .collect::<Result<Vec<_>>>()?;
+        candidates_by_generation.sort_by_key(|(generation, _cs_id)| std::cmp::Reverse(*generation));
+
+        let mut reachable = HashMap::with_capacity(candidates.len());
+        for (generation, cs_id) in candidates_by_generation {
+            self.lower_frontier(ctx, &mut frontier, generation).await?;
+            reachable.insert(cs_id, frontier.highest_generation_contains(cs_id, generation));
+        }
+        Ok(reachable)
+    }
+
     /// Returns a stream of all ancestors of any changeset in heads,
     /// excluding any ancestor of any changeset in common, in reverse
     /// topological order.
@@ -30,6 +30,9 @@ pub(super) struct CommandArgs {
     #[clap(long, short)]
     /// Subdir to look at
     prefix: Option<Vec<String>>,
+    #[clap(long)]
+    /// Subdir to exclude from the results, even if it matches --prefix
+    exclude_prefix: Option<Vec<String>>,
     #[clap(long, short)]
     /// Filename to filter on
     filename: Option<Vec<String>>,
@@ -42,6 +45,16 @@ pub(super) struct CommandArgs {
     #[clap(long, default_value_t = 100)]
     /// Maximum number of paths to return
     limit: u64,
+    #[clap(long)]
+    /// Return paths in descending order instead of ascending. Cannot be
+    /// combined with --after.
+    reverse: bool,
+    #[clap(long)]
+    /// Match --filename/--suffix case-insensitively
+    ignore_case: bool,
+    #[clap(long)]
+    /// Also print the type, size and content id of each file
+    with_metadata: bool,
 }
 
 #[derive(Serialize)]
@@ -61,12 +74,53 @@ impl Render for FileListOutput {
     }
 }
 
+#[derive(Serialize)]
+struct FileEntryOutput {
+    path: String,
+    r#type: String,
+    size: i64,
+    id: String,
+}
+
+#[derive(Serialize)]
+struct FileEntryListOutput(Vec<FileEntryOutput>);
+
+impl Render for FileEntryListOutput {
+    type Args = CommandArgs;
+
+    fn render(&self, _args: &Self::Args, w: &mut dyn Write) -> Result<()> {
+        for entry in &self.0 {
+            write!(
+                w,
+                "{:7} {:>10} {} {}\n",
+                entry.r#type, entry.size, entry.id, entry.path
+            )?;
+        }
+        Ok(())
+    }
+    fn render_json(&self, _args: &Self::Args, w: &mut dyn Write) -> Result<()> {
+        Ok(serde_json::to_writer(w, self)?)
+    }
+}
+
+fn entry_type_name(entry_type: thrift::EntryType) -> &'static str {
+    match entry_type {
+        thrift::EntryType::FILE => "file",
+        thrift::EntryType::EXEC => "exec",
+        thrift::EntryType::LINK => "link",
+        thrift::EntryType::TREE => "tree",
+        thrift::EntryType::GIT_SUBMODULE => "submodule",
+        _ => "unknown",
+    }
+}
+
 pub(super) async fn run(app: ScscApp, args: CommandArgs) -> Result<()> {
     let repo = args.repo_args.clone().into_repo_specifier();
     let commit_id = args.commit_id_args.clone().into_commit_id();
     let conn = app.get_connection(Some(&repo.name))?;
     let id = resolve_commit_id(&conn, &repo, &commit_id).await?;
     let prefixes = args.prefix.clone();
+    let exclude_prefixes = args.exclude_prefix.clone();
     let basenames = args.filename.clone();
     let basename_suffixes = args.suffix.clone();
     let after = args.after.clone();
@@ -83,10 +137,31 @@ pub(super) async fn run(app: ScscApp, args: CommandArgs) -> Result<()> {
         basenames,
         basename_suffixes,
         prefixes,
+        exclude_prefixes,
+        reverse: Some(args.reverse),
+        basenames_case_insensitive: Some(args.ignore_case),
+        include_metadata: Some(args.with_metadata),
         ..Default::default()
     };
     let response = conn.commit_find_files(&commit_specifier, &params).await?;
-    app.target
-        .render_one(&args, FileListOutput(response.files))
-        .await
+    if args.with_metadata {
+        let entries = response
+            .file_entries
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| FileEntryOutput {
+                path: entry.path,
+                r#type: entry_type_name(entry.r#type).to_string(),
+                size: entry.file_size,
+                id: faster_hex::hex_string(&entry.id),
+            })
+            .collect();
+        app.target
+            .render_one(&args, FileEntryListOutput(entries))
+            .await
+    } else {
+        app.target
+            .render_one(&args, FileListOutput(response.files))
+            .await
+    }
 }
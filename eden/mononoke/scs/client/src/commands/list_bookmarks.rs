@@ -11,6 +11,7 @@ use std::collections::HashSet;
 use std::io::Write;
 
 use anyhow::Result;
+use clap::ValueEnum;
 use futures::stream;
 use futures::stream::Stream;
 use futures::stream::StreamExt;
@@ -28,6 +29,23 @@ use crate::library::commit_id::render_commit_id;
 use crate::render::Render;
 use crate::ScscApp;
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum BookmarkCategory {
+    Branch,
+    Tag,
+    Note,
+}
+
+impl From<BookmarkCategory> for thrift::BookmarkCategory {
+    fn from(category: BookmarkCategory) -> thrift::BookmarkCategory {
+        match category {
+            BookmarkCategory::Branch => thrift::BookmarkCategory::BRANCH,
+            BookmarkCategory::Tag => thrift::BookmarkCategory::TAG,
+            BookmarkCategory::Note => thrift::BookmarkCategory::NOTE,
+        }
+    }
+}
+
 #[derive(clap::Parser)]
 /// List bookmarks and their current commits
 ///
@@ -55,6 +73,10 @@ pub(super) struct CommandArgs {
     #[clap(long)]
     /// Include scratch bookmarks in results
     include_scratch: bool,
+    #[clap(long)]
+    /// Restrict results to the given bookmark categories (branch, tag, note).
+    /// Only applies when a commit id is given. If omitted, all categories are returned.
+    category: Vec<BookmarkCategory>,
 }
 
 #[derive(Serialize)]
@@ -145,11 +167,13 @@ fn commit_list_descendant_bookmarks(
     prefix: Option<String>,
     include_scratch: bool,
     identity_schemes: BTreeSet<thrift::CommitIdentityScheme>,
+    bookmark_categories: BTreeSet<thrift::BookmarkCategory>,
 ) -> impl Stream<Item = Result<(String, BTreeMap<String, String>)>> {
     stream::try_unfold(Some((after, limit)), move |state| {
         let connection = connection.clone();
         let commit = commit.clone();
         let identity_schemes = identity_schemes.clone();
+        let bookmark_categories = bookmark_categories.clone();
         let prefix = prefix.clone();
         async move {
             if let Some((after, limit)) = state {
@@ -162,6 +186,7 @@ fn commit_list_descendant_bookmarks(
                     limit: source_control::consts::COMMIT_LIST_DESCENDANT_BOOKMARKS_MAX_LIMIT,
                     after,
                     identity_schemes: identity_schemes.clone(),
+                    bookmark_categories: bookmark_categories.clone(),
                     ..Default::default()
                 };
                 let response = connection
@@ -214,6 +239,7 @@ pub(super) async fn run(app: ScscApp, args: CommandArgs) -> Result<()> {
                 prefix.map(String::from),
                 include_scratch,
                 args.scheme_args.clone().into_request_schemes(),
+                args.category.iter().copied().map(Into::into).collect(),
             )
             .left_stream()
         }
@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use lru::LruCache;
+use mononoke_api::ChangesetDiffItem;
+use mononoke_api::ChangesetId;
+use mononoke_types::path::MPath;
+use once_cell::sync::Lazy;
+use stats::prelude::*;
+
+/// Maximum number of distinct `commit_compare` requests' worth of changed-path
+/// lists to keep cached at once. This bounds the cache to a fixed amount of
+/// memory regardless of how many distinct comparisons are requested, at the
+/// cost of evicting colder comparisons first.
+const CAPACITY: usize = 10_000;
+
+define_stats! {
+    prefix = "mononoke.scs_server.commit_compare_cache";
+    hit: timeseries(Rate, Sum),
+    miss: timeseries(Rate, Sum),
+}
+
+/// Every parameter that affects the changed-path list returned by a
+/// `paths_only` `commit_compare` request. Any new parameter that `commit_compare`
+/// learns to honor on that code path must be added here too, or a cached
+/// result could be served for a request whose output it doesn't match.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CompareCacheKey {
+    pub base: ChangesetId,
+    pub other: Option<ChangesetId>,
+    pub diff_items: BTreeSet<ChangesetDiffItem>,
+    pub paths: Option<Vec<MPath>>,
+    pub skip_copies_renames: bool,
+    pub find_renames_across_tree: bool,
+    pub recurse_under_paths: bool,
+}
+
+/// The changed-path list computed for a [`CompareCacheKey`], as returned in
+/// `CommitCompareResponse.changed_paths`.
+type ChangedPaths = Arc<Vec<String>>;
+
+/// Process-wide cache of [`ChangedPaths`], keyed by [`CompareCacheKey`].
+///
+/// A popular PR or bookmark can be diffed by many users in quick succession,
+/// each of which would otherwise independently recompute the same changed-path
+/// list. Sharing one bounded, process-wide cache across requests lets later
+/// callers skip that redundant work.
+static COMPARE_CACHE: Lazy<Mutex<LruCache<CompareCacheKey, ChangedPaths>>> = Lazy::new(|| {
+    Mutex::new(LruCache::new(
+        NonZeroUsize::new(CAPACITY).expect("CAPACITY is non-zero"),
+    ))
+});
+
+/// Return the cached changed-path list for `key`, if present, recording a
+/// cache hit or miss either way.
+pub(crate) fn get(key: &CompareCacheKey) -> Option<ChangedPaths> {
+    let result = COMPARE_CACHE
+        .lock()
+        .expect("COMPARE_CACHE lock poisoned")
+        .get(key)
+        .cloned();
+    if result.is_some() {
+        STATS::hit.add_value(1);
+    } else {
+        STATS::miss.add_value(1);
+    }
+    result
+}
+
+/// Populate the cache with the changed-path list for `key`.
+pub(crate) fn put(key: CompareCacheKey, changed_paths: ChangedPaths) {
+    COMPARE_CACHE
+        .lock()
+        .expect("COMPARE_CACHE lock poisoned")
+        .put(key, changed_paths);
+}
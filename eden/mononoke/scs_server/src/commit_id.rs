@@ -7,23 +7,59 @@
 
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::num::NonZeroU64;
 
 use cloned::cloned;
 use faster_hex::hex_string;
 use futures_util::future;
 use futures_util::FutureExt;
+use metaconfig_types::CommitIdentityScheme as ConfigCommitIdentityScheme;
+use metaconfig_types::RepoConfig;
 use mononoke_api::ChangesetContext;
 use mononoke_api::ChangesetId;
+use mononoke_api::ChangesetSpecifier;
 use mononoke_api::MononokeError;
 use mononoke_api::RepoContext;
 use source_control as thrift;
 
+/// Convert a repo config's identity scheme into its thrift equivalent.
+fn config_identity_scheme_to_thrift(
+    scheme: &ConfigCommitIdentityScheme,
+) -> thrift::CommitIdentityScheme {
+    match scheme {
+        ConfigCommitIdentityScheme::HG => thrift::CommitIdentityScheme::HG,
+        ConfigCommitIdentityScheme::GIT => thrift::CommitIdentityScheme::GIT,
+        ConfigCommitIdentityScheme::BONSAI => thrift::CommitIdentityScheme::BONSAI,
+        ConfigCommitIdentityScheme::UNKNOWN => thrift::CommitIdentityScheme::UNKNOWN,
+    }
+}
+
+/// The identity schemes to resolve when a request's `identity_schemes` is empty: the
+/// per-repo defaults configured in `source_control_service.default_identity_schemes`,
+/// converted to their thrift equivalents. Empty if the repo has no configured default.
+fn default_identity_schemes(config: &RepoConfig) -> BTreeSet<thrift::CommitIdentityScheme> {
+    config
+        .source_control_service
+        .default_identity_schemes
+        .iter()
+        .map(config_identity_scheme_to_thrift)
+        .collect()
+}
+
 /// Generate a mapping for a commit's identity into the requested identity
-/// schemes.
+/// schemes. Falls back to the repo's configured default identity schemes
+/// (see `default_identity_schemes`) when `schemes` is empty.
 pub(crate) async fn map_commit_identity(
     changeset_ctx: &ChangesetContext,
     schemes: &BTreeSet<thrift::CommitIdentityScheme>,
 ) -> Result<BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>, MononokeError> {
+    let default_schemes;
+    let schemes = if schemes.is_empty() {
+        default_schemes = default_identity_schemes(changeset_ctx.repo().config());
+        &default_schemes
+    } else {
+        schemes
+    };
     let mut ids = BTreeMap::new();
     ids.insert(
         thrift::CommitIdentityScheme::BONSAI,
@@ -94,7 +130,8 @@ pub(crate) async fn map_commit_identity(
 }
 
 /// Generate mappings for multiple commits' identities into the requested
-/// identity schemes.
+/// identity schemes. Falls back to the repo's configured default identity
+/// schemes (see `default_identity_schemes`) when `schemes` is empty.
 pub(crate) async fn map_commit_identities(
     repo_ctx: &RepoContext,
     ids: Vec<ChangesetId>,
@@ -103,6 +140,13 @@ pub(crate) async fn map_commit_identities(
     BTreeMap<ChangesetId, BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>>,
     MononokeError,
 > {
+    let default_schemes;
+    let schemes = if schemes.is_empty() {
+        default_schemes = default_identity_schemes(repo_ctx.config());
+        &default_schemes
+    } else {
+        schemes
+    };
     let mut result = BTreeMap::new();
     for id in ids.iter() {
         let mut idmap = BTreeMap::new();
@@ -204,6 +248,27 @@ pub(crate) async fn map_commit_identities(
     Ok(result)
 }
 
+/// Convert a `ChangesetSpecifier` identifying a single scheme's hash (as produced by
+/// prefix resolution) into the `CommitId` of that same scheme.
+pub(crate) fn changeset_specifier_to_commit_id(
+    specifier: &ChangesetSpecifier,
+) -> thrift::CommitId {
+    match specifier {
+        ChangesetSpecifier::Bonsai(cs_id) => thrift::CommitId::bonsai(cs_id.as_ref().into()),
+        ChangesetSpecifier::Hg(hg_cs_id) => thrift::CommitId::hg(hg_cs_id.as_ref().into()),
+        ChangesetSpecifier::GitSha1(git_sha1) => thrift::CommitId::git(git_sha1.as_ref().into()),
+        ChangesetSpecifier::Globalrev(rev) => thrift::CommitId::globalrev(rev.id() as i64),
+        ChangesetSpecifier::Svnrev(rev) => thrift::CommitId::svnrev(rev.id() as i64),
+        ChangesetSpecifier::EphemeralBonsai(cs_id, bubble_id) => {
+            thrift::CommitId::ephemeral_bonsai(thrift::EphemeralBonsai {
+                bonsai_id: cs_id.as_ref().into(),
+                bubble_id: bubble_id.map_or(0, |id| NonZeroU64::from(id).get() as i64),
+                ..Default::default()
+            })
+        }
+    }
+}
+
 /// Trait to extend CommitId with useful functions.
 pub(crate) trait CommitIdExt {
     fn scheme(&self) -> thrift::CommitIdentityScheme;
@@ -220,6 +285,7 @@ impl CommitIdExt for thrift::CommitId {
             thrift::CommitId::git(_) => thrift::CommitIdentityScheme::GIT,
             thrift::CommitId::globalrev(_) => thrift::CommitIdentityScheme::GLOBALREV,
             thrift::CommitId::svnrev(_) => thrift::CommitIdentityScheme::SVNREV,
+            thrift::CommitId::commit_id_prefix(prefix) => prefix.scheme,
             thrift::CommitId::UnknownField(t) => (*t).into(),
         }
     }
@@ -239,6 +305,9 @@ impl CommitIdExt for thrift::CommitId {
             thrift::CommitId::git(id) => hex_string(id),
             thrift::CommitId::globalrev(rev) => rev.to_string(),
             thrift::CommitId::svnrev(rev) => rev.to_string(),
+            thrift::CommitId::commit_id_prefix(prefix) => {
+                format!("{} (scheme={})", prefix.prefix, prefix.scheme)
+            }
             thrift::CommitId::UnknownField(t) => format!("unknown id type ({})", t),
         }
     }
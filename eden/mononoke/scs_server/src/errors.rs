@@ -223,6 +223,7 @@ macro_rules! impl_into_thrift_error {
 impl_into_thrift_error!(service::ListReposExn);
 impl_into_thrift_error!(service::RepoInfoExn);
 impl_into_thrift_error!(service::RepoResolveBookmarkExn);
+impl_into_thrift_error!(service::RepoResolveBookmarksExn);
 impl_into_thrift_error!(service::RepoResolveCommitPrefixExn);
 impl_into_thrift_error!(service::RepoListBookmarksExn);
 impl_into_thrift_error!(service::RepoCreateCommitExn);
@@ -297,6 +298,14 @@ pub(crate) fn internal_error(error: impl ToString) -> thrift::InternalError {
     }
 }
 
+pub(crate) fn request_timed_out(timeout: std::time::Duration) -> thrift::RequestError {
+    thrift::RequestError {
+        kind: thrift::RequestErrorKind::REQUEST_TIMEOUT,
+        reason: format!("request did not complete within {:?}", timeout),
+        ..Default::default()
+    }
+}
+
 pub(crate) fn repo_not_found(repo: String) -> thrift::RequestError {
     thrift::RequestError {
         kind: thrift::RequestErrorKind::REPO_NOT_FOUND,
@@ -200,6 +200,9 @@ impl FromRequest<thrift::CommitId> for ChangesetSpecifier {
                 };
                 Ok(ChangesetSpecifier::EphemeralBonsai(cs_id, bubble_id))
             }
+            thrift::CommitId::commit_id_prefix(_) => Err(errors::invalid_request(
+                "commit id prefixes are only supported by commit_lookup",
+            )),
             thrift::CommitId::UnknownField(_) => Err(errors::invalid_request(format!(
                 "unsupported commit identity scheme ({})",
                 commit.scheme()
@@ -222,52 +225,65 @@ impl FromRequest<thrift::CopyInfo> for CopyInfo {
     }
 }
 
+fn changeset_prefix_specifier_from_scheme_and_prefix(
+    scheme: thrift::CommitIdentityScheme,
+    prefix: &str,
+) -> Result<ChangesetPrefixSpecifier, thrift::RequestError> {
+    match scheme {
+        thrift::CommitIdentityScheme::HG => {
+            let prefix = HgChangesetIdPrefix::from_str(prefix).map_err(|e| {
+                errors::invalid_request(format!(
+                    "invalid commit id prefix (scheme={} {}): {}",
+                    scheme, prefix, e
+                ))
+            })?;
+            Ok(ChangesetPrefixSpecifier::from(prefix))
+        }
+        thrift::CommitIdentityScheme::GIT => {
+            let prefix = GitSha1Prefix::from_str(prefix).map_err(|e| {
+                errors::invalid_request(format!(
+                    "invalid commit id prefix (scheme={} {}): {}",
+                    scheme, prefix, e
+                ))
+            })?;
+            Ok(ChangesetPrefixSpecifier::from(prefix))
+        }
+        thrift::CommitIdentityScheme::BONSAI => {
+            let prefix = ChangesetIdPrefix::from_str(prefix).map_err(|e| {
+                errors::invalid_request(format!(
+                    "invalid commit id prefix (scheme={} {}): {}",
+                    scheme, prefix, e
+                ))
+            })?;
+            Ok(ChangesetPrefixSpecifier::from(prefix))
+        }
+        thrift::CommitIdentityScheme::GLOBALREV => {
+            let rev = prefix.parse().map_err(|e| {
+                errors::invalid_request(format!(
+                    "invalid commit id prefix (scheme={} {}): {}",
+                    scheme, prefix, e
+                ))
+            })?;
+            Ok(ChangesetPrefixSpecifier::from(Globalrev::new(rev)))
+        }
+        _ => Err(errors::invalid_request(format!(
+            "unsupported prefix identity scheme ({})",
+            scheme
+        ))),
+    }
+}
+
 impl FromRequest<thrift::RepoResolveCommitPrefixParams> for ChangesetPrefixSpecifier {
     fn from_request(
         params: &thrift::RepoResolveCommitPrefixParams,
     ) -> Result<Self, thrift::RequestError> {
-        match params.prefix_scheme {
-            thrift::CommitIdentityScheme::HG => {
-                let prefix = HgChangesetIdPrefix::from_str(&params.prefix).map_err(|e| {
-                    errors::invalid_request(format!(
-                        "invalid commit id prefix (scheme={} {}): {}",
-                        params.prefix_scheme, params.prefix, e
-                    ))
-                })?;
-                Ok(ChangesetPrefixSpecifier::from(prefix))
-            }
-            thrift::CommitIdentityScheme::GIT => {
-                let prefix = GitSha1Prefix::from_str(&params.prefix).map_err(|e| {
-                    errors::invalid_request(format!(
-                        "invalid commit id prefix (scheme={} {}): {}",
-                        params.prefix_scheme, params.prefix, e
-                    ))
-                })?;
-                Ok(ChangesetPrefixSpecifier::from(prefix))
-            }
-            thrift::CommitIdentityScheme::BONSAI => {
-                let prefix = ChangesetIdPrefix::from_str(&params.prefix).map_err(|e| {
-                    errors::invalid_request(format!(
-                        "invalid commit id prefix (scheme={} {}): {}",
-                        params.prefix_scheme, params.prefix, e
-                    ))
-                })?;
-                Ok(ChangesetPrefixSpecifier::from(prefix))
-            }
-            thrift::CommitIdentityScheme::GLOBALREV => {
-                let rev = params.prefix.parse().map_err(|e| {
-                    errors::invalid_request(format!(
-                        "invalid commit id prefix (scheme={} {}): {}",
-                        params.prefix_scheme, params.prefix, e
-                    ))
-                })?;
-                Ok(ChangesetPrefixSpecifier::from(Globalrev::new(rev)))
-            }
-            _ => Err(errors::invalid_request(format!(
-                "unsupported prefix identity scheme ({})",
-                params.prefix_scheme
-            ))),
-        }
+        changeset_prefix_specifier_from_scheme_and_prefix(params.prefix_scheme, &params.prefix)
+    }
+}
+
+impl FromRequest<thrift::CommitIdPrefix> for ChangesetPrefixSpecifier {
+    fn from_request(prefix: &thrift::CommitIdPrefix) -> Result<Self, thrift::RequestError> {
+        changeset_prefix_specifier_from_scheme_and_prefix(prefix.scheme, &prefix.prefix)
     }
 }
 
@@ -434,3 +450,60 @@ pub(crate) fn convert_pushvars(
             .collect()
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changeset_prefix_specifier_from_scheme_and_prefix_resolves_known_schemes() {
+        assert!(matches!(
+            changeset_prefix_specifier_from_scheme_and_prefix(
+                thrift::CommitIdentityScheme::HG,
+                "1234567890abcdef1234567890abcdef12345678",
+            ),
+            Ok(ChangesetPrefixSpecifier::Hg(_))
+        ));
+        assert!(matches!(
+            changeset_prefix_specifier_from_scheme_and_prefix(
+                thrift::CommitIdentityScheme::GIT,
+                "1234567890abcdef1234567890abcdef12345678",
+            ),
+            Ok(ChangesetPrefixSpecifier::GitSha1(_))
+        ));
+        assert!(matches!(
+            changeset_prefix_specifier_from_scheme_and_prefix(
+                thrift::CommitIdentityScheme::BONSAI,
+                "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcd",
+            ),
+            Ok(ChangesetPrefixSpecifier::Bonsai(_))
+        ));
+        assert!(matches!(
+            changeset_prefix_specifier_from_scheme_and_prefix(thrift::CommitIdentityScheme::GLOBALREV, "123"),
+            Ok(ChangesetPrefixSpecifier::Globalrev(_))
+        ));
+    }
+
+    #[test]
+    fn changeset_prefix_specifier_from_scheme_and_prefix_rejects_unsupported_scheme() {
+        assert!(changeset_prefix_specifier_from_scheme_and_prefix(
+            thrift::CommitIdentityScheme::SVNREV,
+            "123",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn changeset_prefix_specifier_from_scheme_and_prefix_rejects_malformed_prefix() {
+        assert!(changeset_prefix_specifier_from_scheme_and_prefix(
+            thrift::CommitIdentityScheme::HG,
+            "not-hex",
+        )
+        .is_err());
+        assert!(changeset_prefix_specifier_from_scheme_and_prefix(
+            thrift::CommitIdentityScheme::GLOBALREV,
+            "not-a-number",
+        )
+        .is_err());
+    }
+}
@@ -27,6 +27,8 @@ pub(crate) async fn collect_history(
     after_timestamp: Option<i64>,
     format: thrift::HistoryFormat,
     identity_schemes: &BTreeSet<thrift::CommitIdentityScheme>,
+    fields: &Option<BTreeSet<thrift::CommitInfoField>>,
+    reverse: bool,
 ) -> Result<thrift::History, errors::ServiceError> {
     let history_stream = history_stream
         .map_err(errors::ServiceError::from)
@@ -67,21 +69,37 @@ pub(crate) async fn collect_history(
 
     match format {
         thrift::HistoryFormat::COMMIT_INFO => {
-            let commit_infos: Vec<_> = history
-                .map(|changeset| async {
-                    match changeset {
-                        Ok(cs) => cs.into_response_with(identity_schemes).await,
-                        Err(err) => Err(err),
+            let commit_info_additional = (identity_schemes.clone(), fields.clone());
+            // Identity-scheme lookups (for each changeset and, if requested, its
+            // parents) are batched per chunk instead of one changeset at a time.
+            let mut commit_infos: Vec<_> = history
+                .chunks(100)
+                // TryStreamExt doesn't have the try_chunks method yet so we have to do it by mapping
+                .map(|chunk| chunk.into_iter().collect::<Result<Vec<_>, _>>())
+                .and_then(move |changesets: Vec<ChangesetContext>| {
+                    let commit_info_additional = commit_info_additional.clone();
+                    async move {
+                        Ok(stream::iter(
+                            changesets
+                                .into_response_with(&commit_info_additional)
+                                .await?
+                                .into_iter()
+                                .map(Ok::<_, errors::ServiceError>)
+                                .collect::<Vec<_>>(),
+                        ))
                     }
                 })
-                .buffered(100)
+                .try_flatten()
                 .try_collect()
                 .await?;
+            if reverse {
+                commit_infos.reverse();
+            }
             Ok(thrift::History::commit_infos(commit_infos))
         }
         thrift::HistoryFormat::COMMIT_ID => {
             let identity_schemes = identity_schemes.clone();
-            let commit_ids: Vec<BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>> = history
+            let mut commit_ids: Vec<BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>> = history
                 .chunks(100)
                 // TryStreamExt doesn't have the try_chunks method yet so we have to do it by mapping
                 .map(|chunk| chunk.into_iter().collect::<Result<Vec<_>, _>>())
@@ -101,6 +119,9 @@ pub(crate) async fn collect_history(
                 .try_flatten()
                 .try_collect()
                 .await?;
+            if reverse {
+                commit_ids.reverse();
+            }
             Ok(thrift::History::commit_ids(commit_ids))
         }
         other_format => Err(errors::invalid_request(format!(
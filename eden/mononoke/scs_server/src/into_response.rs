@@ -7,13 +7,17 @@
 
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::collections::HashSet;
 
 use async_trait::async_trait;
+use bookmarks::BookmarkKey;
+use bookmarks::BookmarkUpdateReason;
 use futures::future::try_join_all;
 use futures::try_join;
 use itertools::Itertools;
 use maplit::btreemap;
+use mononoke_api::BookmarkHistoryEntry;
 use mononoke_api::BookmarkInfo;
 use mononoke_api::ChangesetContext;
 use mononoke_api::ChangesetId;
@@ -34,6 +38,7 @@ use mononoke_api::TreeEntry;
 use mononoke_api::TreeId;
 use mononoke_api::TreeSummary;
 use mononoke_api::UnifiedDiff;
+use mononoke_types::fsnode::FsnodeFile;
 use source_control as thrift;
 
 use crate::commit_id::map_commit_identities;
@@ -164,6 +169,18 @@ impl IntoResponse<thrift::FileInfo> for FileMetadata {
     }
 }
 
+impl IntoResponse<thrift::FileInfo> for FsnodeFile {
+    fn into_response(self) -> thrift::FileInfo {
+        thrift::FileInfo {
+            id: self.content_id().as_ref().to_vec(),
+            file_size: self.size() as i64,
+            content_sha1: self.content_sha1().as_ref().to_vec(),
+            content_sha256: self.content_sha256().as_ref().to_vec(),
+            ..Default::default()
+        }
+    }
+}
+
 impl IntoResponse<thrift::TreeInfo> for (TreeId, TreeSummary) {
     fn into_response(self) -> thrift::TreeInfo {
         let (id, summary) = self;
@@ -326,12 +343,19 @@ impl AsyncIntoResponse<thrift::TreePathInfo> for &ChangesetPathContentContext {
 #[async_trait]
 impl AsyncIntoResponseWith<thrift::CommitInfo> for ChangesetContext {
     /// The additional data is the set of commit identity schemes to be
-    /// returned in the response.
-    type Additional = BTreeSet<thrift::CommitIdentityScheme>;
+    /// returned in the response, optionally a field mask restricting
+    /// which of the more expensive fields should be computed (a `None`
+    /// field mask means all fields are wanted), and optionally a bookmark
+    /// to restrict `CommitInfo.children` to.
+    type Additional = (
+        BTreeSet<thrift::CommitIdentityScheme>,
+        Option<BTreeSet<thrift::CommitInfoField>>,
+        Option<BookmarkKey>,
+    );
 
     async fn into_response_with(
         self,
-        identity_schemes: &BTreeSet<thrift::CommitIdentityScheme>,
+        (identity_schemes, fields, children_bookmark): &Self::Additional,
     ) -> Result<thrift::CommitInfo, errors::ServiceError> {
         async fn map_parent_identities(
             changeset: &ChangesetContext,
@@ -352,6 +376,42 @@ impl AsyncIntoResponseWith<thrift::CommitInfo> for ChangesetContext {
                 .collect())
         }
 
+        async fn map_children_identities(
+            changeset: &ChangesetContext,
+            identity_schemes: &BTreeSet<thrift::CommitIdentityScheme>,
+            children_bookmark: Option<&BookmarkKey>,
+        ) -> Result<
+            (Vec<BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>>, bool),
+            MononokeError,
+        > {
+            let (children, limit_reached) = changeset.children(children_bookmark).await?;
+            let child_id_mapping =
+                map_commit_identities(changeset.repo(), children.clone(), identity_schemes)
+                    .await?;
+            let children = children
+                .iter()
+                .map(|child_id| child_id_mapping.get(child_id).cloned().unwrap_or_default())
+                .collect();
+            Ok((children, limit_reached))
+        }
+
+        let wants = |field: thrift::CommitInfoField| commit_info_field_wanted(fields, field);
+        let want_message = wants(thrift::CommitInfoField::MESSAGE);
+        let want_parents = wants(thrift::CommitInfoField::PARENTS);
+        let want_extra = wants(thrift::CommitInfoField::EXTRA);
+        let want_git_extra_headers = wants(thrift::CommitInfoField::GIT_EXTRA_HEADERS);
+        // Unlike the other fields, subtree stats require deriving fsnodes for the whole
+        // tree, so they're only computed when explicitly requested, never by default.
+        let want_subtree_stats = fields
+            .as_ref()
+            .is_some_and(|fields| fields.contains(&thrift::CommitInfoField::SUBTREE_STATS));
+        // Unlike the other fields, children require a commit graph traversal for each
+        // child candidate when restricted to a bookmark, so they're only computed when
+        // explicitly requested, never by default.
+        let want_children = fields
+            .as_ref()
+            .is_some_and(|fields| fields.contains(&thrift::CommitInfoField::CHILDREN));
+
         let (
             ids,
             message,
@@ -362,16 +422,58 @@ impl AsyncIntoResponseWith<thrift::CommitInfo> for ChangesetContext {
             git_extra_headers,
             generation,
             committer_date,
+            subtree_stats,
+            children,
         ) = try_join!(
             map_commit_identity(&self, identity_schemes),
-            self.message(),
+            async {
+                if want_message {
+                    self.message().await
+                } else {
+                    Ok(String::new())
+                }
+            },
             self.author_date(),
             self.author(),
-            map_parent_identities(&self, identity_schemes),
-            self.hg_extras(),
-            self.git_extra_headers(),
+            async {
+                if want_parents {
+                    map_parent_identities(&self, identity_schemes).await
+                } else {
+                    Ok(Vec::new())
+                }
+            },
+            async {
+                if want_extra {
+                    self.hg_extras().await
+                } else {
+                    Ok(Vec::new())
+                }
+            },
+            async {
+                if want_git_extra_headers {
+                    self.git_extra_headers().await
+                } else {
+                    Ok(None)
+                }
+            },
             self.generation(),
             self.committer_date(),
+            async {
+                if want_subtree_stats {
+                    self.subtree_file_stats().await.map(Some)
+                } else {
+                    Ok(None)
+                }
+            },
+            async {
+                if want_children {
+                    map_children_identities(&self, identity_schemes, children_bookmark.as_ref())
+                        .await
+                        .map(Some)
+                } else {
+                    Ok(None)
+                }
+            },
         )?;
         Ok(thrift::CommitInfo {
             ids,
@@ -389,6 +491,10 @@ impl AsyncIntoResponseWith<thrift::CommitInfo> for ChangesetContext {
             }),
             generation: generation.value() as i64,
             committer_date: committer_date.map(|date| date.timestamp()),
+            subtree_file_count: subtree_stats.map(|(count, _)| count as i64),
+            subtree_total_size: subtree_stats.map(|(_, size)| size as i64),
+            children: children.as_ref().map(|(children, _)| children.clone()),
+            children_limit_reached: children.map(|(_, limit_reached)| limit_reached),
             ..Default::default()
         })
     }
@@ -443,11 +549,207 @@ impl AsyncIntoResponseWith<Vec<BTreeMap<thrift::CommitIdentityScheme, thrift::Co
     }
 }
 
+#[async_trait]
+impl AsyncIntoResponseWith<Vec<thrift::CommitInfo>> for Vec<ChangesetContext> {
+    /// The additional data is the set of commit identity schemes to be
+    /// returned in the response, and optionally a field mask restricting
+    /// which of the more expensive fields should be computed. A `None`
+    /// field mask means all fields are wanted.
+    ///
+    /// Unlike converting changesets one at a time via the single-changeset
+    /// `AsyncIntoResponseWith<thrift::CommitInfo>` impl, the identity-scheme
+    /// lookups for every changeset in the list (and, if parents are wanted,
+    /// for all of their parents) are batched into the minimum number of
+    /// mapping queries, instead of querying once per changeset.
+    type Additional = (
+        BTreeSet<thrift::CommitIdentityScheme>,
+        Option<BTreeSet<thrift::CommitInfoField>>,
+    );
+
+    async fn into_response_with(
+        self,
+        (identity_schemes, fields): &Self::Additional,
+    ) -> Result<Vec<thrift::CommitInfo>, errors::ServiceError> {
+        let repo = match self.first() {
+            Some(changeset) => changeset.repo().clone(),
+            None => return Ok(Vec::new()),
+        };
+
+        let wants = |field: thrift::CommitInfoField| commit_info_field_wanted(fields, field);
+        let want_message = wants(thrift::CommitInfoField::MESSAGE);
+        let want_parents = wants(thrift::CommitInfoField::PARENTS);
+        let want_extra = wants(thrift::CommitInfoField::EXTRA);
+        let want_git_extra_headers = wants(thrift::CommitInfoField::GIT_EXTRA_HEADERS);
+        // Unlike the other fields, subtree stats require deriving fsnodes for the whole
+        // tree, so they're only computed when explicitly requested, never by default.
+        let want_subtree_stats = fields
+            .as_ref()
+            .is_some_and(|fields| fields.contains(&thrift::CommitInfoField::SUBTREE_STATS));
+
+        let parents_by_id: HashMap<ChangesetId, Vec<ChangesetId>> = if want_parents {
+            try_join_all(
+                self.iter()
+                    .map(|cs| async move { Ok::<_, MononokeError>((cs.id(), cs.parents().await?)) }),
+            )
+            .await?
+            .into_iter()
+            .collect()
+        } else {
+            HashMap::new()
+        };
+
+        // Batch the identity-scheme lookups for every changeset in this page, and
+        // for all of their parents, into the minimum number of mapping queries,
+        // grouped by repo.
+        let ids_by_repo = self
+            .iter()
+            .map(|cs| (cs.repo().clone(), cs.id()))
+            .chain(
+                parents_by_id
+                    .values()
+                    .flatten()
+                    .map(|parent_id| (repo.clone(), *parent_id)),
+            )
+            .into_group_map();
+        let id_maps = try_join_all(ids_by_repo.into_iter().map(|(repo, ids)| async move {
+            map_commit_identities(&repo, ids, identity_schemes).await
+        }))
+        .await?
+        .into_iter()
+        .fold(HashMap::new(), |mut acc, map| {
+            acc.extend(map);
+            acc
+        });
+
+        let get_ids = |id: ChangesetId| -> BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId> {
+            id_maps.get(&id).cloned().unwrap_or_default()
+        };
+
+        try_join_all(self.into_iter().map(|changeset| {
+            let get_ids = &get_ids;
+            let parents_by_id = &parents_by_id;
+            async move {
+                let ids = get_ids(changeset.id());
+                let parents = if want_parents {
+                    parents_by_id
+                        .get(&changeset.id())
+                        .cloned()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(get_ids)
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let (
+                    message,
+                    date,
+                    author,
+                    hg_extra,
+                    git_extra_headers,
+                    generation,
+                    committer_date,
+                    subtree_stats,
+                ) = try_join!(
+                    async {
+                        if want_message {
+                            changeset.message().await
+                        } else {
+                            Ok(String::new())
+                        }
+                    },
+                    changeset.author_date(),
+                    changeset.author(),
+                    async {
+                        if want_extra {
+                            changeset.hg_extras().await
+                        } else {
+                            Ok(Vec::new())
+                        }
+                    },
+                    async {
+                        if want_git_extra_headers {
+                            changeset.git_extra_headers().await
+                        } else {
+                            Ok(None)
+                        }
+                    },
+                    changeset.generation(),
+                    changeset.committer_date(),
+                    async {
+                        if want_subtree_stats {
+                            changeset.subtree_file_stats().await.map(Some)
+                        } else {
+                            Ok(None)
+                        }
+                    },
+                )?;
+                Ok::<_, errors::ServiceError>(thrift::CommitInfo {
+                    ids,
+                    message,
+                    date: date.timestamp(),
+                    tz: date.offset().local_minus_utc(),
+                    author,
+                    parents,
+                    extra: hg_extra.into_iter().collect(),
+                    git_extra_headers: git_extra_headers.map(|headers| {
+                        headers
+                            .into_iter()
+                            .map(|(k, v)| (thrift::small_binary(k), v))
+                            .collect()
+                    }),
+                    generation: generation.value() as i64,
+                    committer_date: committer_date.map(|date| date.timestamp()),
+                    subtree_file_count: subtree_stats.map(|(count, _)| count as i64),
+                    subtree_total_size: subtree_stats.map(|(_, size)| size as i64),
+                    ..Default::default()
+                })
+            }
+        }))
+        .await
+    }
+}
+
 fn to_i64(val: usize) -> Result<i64, errors::ServiceError> {
     val.try_into()
         .map_err(|_| errors::internal_error("usize too big for i64").into())
 }
 
+/// Decide whether `field` is wanted in a `thrift::CommitInfo` response. A `None` field
+/// mask means "all fields" (the behavior before the mask was introduced); otherwise only
+/// fields the caller explicitly listed are wanted.
+fn commit_info_field_wanted(
+    fields: &Option<BTreeSet<thrift::CommitInfoField>>,
+    field: thrift::CommitInfoField,
+) -> bool {
+    fields.as_ref().map_or(true, |fields| fields.contains(&field))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::btreeset;
+
+    #[test]
+    fn commit_info_field_wanted_with_no_mask_wants_everything() {
+        assert!(commit_info_field_wanted(&None, thrift::CommitInfoField::MESSAGE));
+        assert!(commit_info_field_wanted(&None, thrift::CommitInfoField::PARENTS));
+    }
+
+    #[test]
+    fn commit_info_field_wanted_with_mask_only_wants_listed_fields() {
+        let fields = Some(btreeset! { thrift::CommitInfoField::MESSAGE });
+        assert!(commit_info_field_wanted(&fields, thrift::CommitInfoField::MESSAGE));
+        assert!(!commit_info_field_wanted(&fields, thrift::CommitInfoField::PARENTS));
+    }
+
+    #[test]
+    fn commit_info_field_wanted_with_empty_mask_wants_nothing() {
+        let fields = Some(BTreeSet::new());
+        assert!(!commit_info_field_wanted(&fields, thrift::CommitInfoField::MESSAGE));
+    }
+}
+
 #[async_trait]
 impl AsyncIntoResponseWith<thrift::PushrebaseOutcome> for PushrebaseOutcome {
     /// The additional data is the repo context, the set of commit identity
@@ -545,3 +847,46 @@ impl AsyncIntoResponseWith<thrift::BookmarkInfo> for BookmarkInfo {
         })
     }
 }
+
+impl From<BookmarkUpdateReason> for thrift::BookmarkUpdateReason {
+    fn from(reason: BookmarkUpdateReason) -> Self {
+        match reason {
+            BookmarkUpdateReason::Pushrebase => thrift::BookmarkUpdateReason::PUSHREBASE,
+            BookmarkUpdateReason::Push => thrift::BookmarkUpdateReason::PUSH,
+            BookmarkUpdateReason::Blobimport => thrift::BookmarkUpdateReason::BLOBIMPORT,
+            BookmarkUpdateReason::ManualMove => thrift::BookmarkUpdateReason::MANUAL_MOVE,
+            BookmarkUpdateReason::TestMove => thrift::BookmarkUpdateReason::TEST_MOVE,
+            BookmarkUpdateReason::Backsyncer => thrift::BookmarkUpdateReason::BACKSYNCER,
+            BookmarkUpdateReason::XRepoSync => thrift::BookmarkUpdateReason::XREPO_SYNC,
+            BookmarkUpdateReason::ApiRequest => thrift::BookmarkUpdateReason::API_REQUEST,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncIntoResponseWith<thrift::BookmarkHistoryEntry> for BookmarkHistoryEntry {
+    /// The additional data is the set of commit identity schemes to be
+    /// returned in the response.
+    type Additional = BTreeSet<thrift::CommitIdentityScheme>;
+
+    async fn into_response_with(
+        self,
+        identity_schemes: &BTreeSet<thrift::CommitIdentityScheme>,
+    ) -> Result<thrift::BookmarkHistoryEntry, errors::ServiceError> {
+        let old_ids = match &self.old_changeset {
+            Some(changeset) => Some(map_commit_identity(changeset, identity_schemes).await?),
+            None => None,
+        };
+        let new_ids = match &self.new_changeset {
+            Some(changeset) => Some(map_commit_identity(changeset, identity_schemes).await?),
+            None => None,
+        };
+        Ok(thrift::BookmarkHistoryEntry {
+            old_ids,
+            new_ids,
+            reason: self.reason.into(),
+            timestamp_ns: self.timestamp.timestamp_nanos(),
+            ..Default::default()
+        })
+    }
+}
@@ -12,6 +12,7 @@ use std::io::Write;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Error;
@@ -53,6 +54,7 @@ use srserver::ThriftServer;
 use srserver::ThriftServerBuilder;
 use tokio::task;
 
+mod commit_compare_cache;
 mod commit_id;
 mod errors;
 mod facebook;
@@ -87,6 +89,10 @@ struct ScsServerArgs {
     /// Path for file in which to write the bound tcp address in rust std::net::SocketAddr format
     #[clap(long)]
     bound_address_file: Option<String>,
+    /// Timeout (in seconds) after which long-running commit methods (e.g. commit_compare,
+    /// commit_find_files, commit_history) are aborted and an error is returned to the client
+    #[clap(long, default_value_t = 300)]
+    long_request_timeout_secs: u64,
     #[clap(flatten)]
     sharded_executor_args: ShardedExecutorArgs,
 }
@@ -241,6 +247,7 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
         args.scribe_logging_args.get_scribe(fb)?,
         security_checker,
         &app.repo_configs().common,
+        Duration::from_secs(args.long_request_timeout_secs),
     );
     let service = {
         move |proto| {
@@ -8,20 +8,32 @@
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
+use bookmarks::BookmarkCategory;
+use bookmarks::BookmarkKey;
 use bytes::Bytes;
+use chrono::Local;
 use context::CoreContext;
+use futures::future;
+use futures::future::try_join_all;
 use futures::stream;
+use futures::stream::BoxStream;
 use futures::stream::FuturesOrdered;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use futures::try_join;
+use globset::Glob;
+use globset::GlobMatcher;
 use hooks::HookExecution;
 use hooks::HookOutcome;
-use itertools::Either;
 use itertools::Itertools;
 use maplit::btreeset;
+use mononoke_api::BasenameCaseSensitivity;
 use mononoke_api::CandidateSelectionHintArgs;
 use mononoke_api::ChangesetContext;
 use mononoke_api::ChangesetDiffItem;
@@ -30,8 +42,12 @@ use mononoke_api::ChangesetHistoryOptions;
 use mononoke_api::ChangesetId;
 use mononoke_api::ChangesetPathContentContext;
 use mononoke_api::ChangesetPathDiffContext;
+use mononoke_api::ChangesetPrefixSpecifier;
 use mononoke_api::ChangesetSpecifier;
+use mononoke_api::ChangesetSpecifierPrefixResolution;
 use mononoke_api::CopyInfo;
+use mononoke_api::CreateInfo;
+use mononoke_api::FileType;
 use mononoke_api::MetadataDiff;
 use mononoke_api::MononokeError;
 use mononoke_api::RepoContext;
@@ -41,6 +57,9 @@ use mononoke_api::XRepoLookupSyncBehaviour;
 use mononoke_types::path::MPath;
 use source_control as thrift;
 
+use crate::commit_compare_cache;
+use crate::commit_compare_cache::CompareCacheKey;
+use crate::commit_id::changeset_specifier_to_commit_id;
 use crate::commit_id::map_commit_identities;
 use crate::commit_id::map_commit_identity;
 use crate::errors;
@@ -60,6 +79,7 @@ const CONCURRENCY_LIMIT: usize = 100;
 enum CommitComparePath {
     File(thrift::CommitCompareFile),
     Tree(thrift::CommitCompareTree),
+    GitSubmodule(thrift::CommitCompareGitSubmodule),
 }
 
 impl CommitComparePath {
@@ -85,22 +105,97 @@ impl CommitComparePath {
                 .ok_or_else(|| {
                     errors::internal_error("programming error, tree entry has no tree").into()
                 }),
+
+            CommitComparePath::GitSubmodule(submodule) => submodule
+                .base_submodule
+                .as_ref()
+                .or(submodule.other_submodule.as_ref())
+                .map(|submodule| submodule.path.as_str())
+                .ok_or_else(|| {
+                    errors::internal_error("programming error, submodule entry has no submodule")
+                        .into()
+                }),
         }
     }
 
+    /// Helper for `from_path_diff`: fetch the target Git commit hash that a
+    /// submodule (gitlink) path points to, if the path is present.
+    async fn git_submodule_path_info(
+        path: Option<&ChangesetPathContentContext>,
+    ) -> Result<Option<thrift::GitSubmodulePathInfo>, errors::ServiceError> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let file = path
+            .file()
+            .await?
+            .ok_or_else(|| errors::internal_error("submodule path has no file content"))?;
+        let commit_hash_bytes = file.content_concat().await?;
+        let target_commit_hash = mononoke_types::hash::GitSha1::from_bytes(commit_hash_bytes)
+            .map_err(|e| {
+                errors::internal_error(format!(
+                    "invalid commit hash for submodule at {}: {}",
+                    path.path(),
+                    e
+                ))
+            })?
+            .to_string();
+        Ok(Some(thrift::GitSubmodulePathInfo {
+            path: path.path().to_string(),
+            target_commit_hash,
+            ..Default::default()
+        }))
+    }
+
     async fn from_path_diff(
         path_diff: ChangesetPathDiffContext,
+        inline_diffs: Option<Arc<InlineDiffsState>>,
     ) -> Result<Self, errors::ServiceError> {
         if path_diff.path().is_file().await? {
+            let file_type = path_diff.path().file_type().await?;
+            if file_type == Some(FileType::GitSubmodule) {
+                let (base_submodule, other_submodule) = try_join!(
+                    Self::git_submodule_path_info(path_diff.base()),
+                    Self::git_submodule_path_info(path_diff.other())
+                )?;
+                let copy_info = path_diff.copy_info().into_response();
+                return Ok(CommitComparePath::GitSubmodule(
+                    thrift::CommitCompareGitSubmodule {
+                        base_submodule,
+                        other_submodule,
+                        copy_info,
+                        ..Default::default()
+                    },
+                ));
+            }
             let (base_file, other_file) = try_join!(
                 path_diff.base().into_response(),
                 path_diff.other().into_response()
             )?;
             let copy_info = path_diff.copy_info().into_response();
+            // A mode-only change (e.g. a file becoming executable) has unchanged
+            // content, so there is no content diff to render for it.
+            let is_mode_only_change = match (&base_file, &other_file) {
+                (Some(base_file), Some(other_file)) => {
+                    base_file.r#type != other_file.r#type
+                        && base_file.info.id == other_file.info.id
+                }
+                _ => false,
+            };
+            let (diff, diff_omitted) = match inline_diffs {
+                Some(inline_diffs) if !is_mode_only_change => {
+                    inline_diffs.diff_for(path_diff).await?
+                }
+                _ => (None, None),
+            };
             Ok(CommitComparePath::File(thrift::CommitCompareFile {
                 base_file,
                 other_file,
                 copy_info,
+                diff,
+                diff_omitted,
+                is_mode_only_change,
                 ..Default::default()
             }))
         } else {
@@ -117,6 +212,28 @@ impl CommitComparePath {
     }
 }
 
+/// Helper for commit_compare to split the combined list of file/tree/submodule
+/// comparisons into the three buckets that `CommitCompareResponse` expects.
+fn partition_compare_paths(
+    diff: Vec<CommitComparePath>,
+) -> (
+    Vec<thrift::CommitCompareFile>,
+    Vec<thrift::CommitCompareTree>,
+    Vec<thrift::CommitCompareGitSubmodule>,
+) {
+    let mut diff_files = Vec::new();
+    let mut diff_trees = Vec::new();
+    let mut diff_git_submodules = Vec::new();
+    for entry in diff {
+        match entry {
+            CommitComparePath::File(entry) => diff_files.push(entry),
+            CommitComparePath::Tree(entry) => diff_trees.push(entry),
+            CommitComparePath::GitSubmodule(entry) => diff_git_submodules.push(entry),
+        }
+    }
+    (diff_files, diff_trees, diff_git_submodules)
+}
+
 /// Helper for commit_compare to add mutable rename information if appropriate
 async fn add_mutable_renames(
     base_changeset: &mut ChangesetContext,
@@ -137,6 +254,90 @@ async fn add_mutable_renames(
     Ok(())
 }
 
+/// Helper for commit_compare to parse the optional `path_glob` restriction
+fn parse_path_glob(
+    params: &thrift::CommitCompareParams,
+) -> Result<Option<GlobMatcher>, errors::ServiceError> {
+    params
+        .path_glob
+        .as_deref()
+        .map(|pattern| {
+            Glob::new(pattern)
+                .map(|glob| glob.compile_matcher())
+                .map_err(|error| {
+                    errors::invalid_request(format!("invalid path_glob '{}': {}", pattern, error))
+                        .into()
+                })
+        })
+        .transpose()
+}
+
+/// Helper for commit_compare to restrict a diff result to paths matching the
+/// `path_glob` restriction, if one was requested
+fn filter_by_path_glob(
+    diff: Vec<ChangesetPathDiffContext>,
+    path_glob: Option<&GlobMatcher>,
+) -> Vec<ChangesetPathDiffContext> {
+    match path_glob {
+        None => diff,
+        Some(path_glob) => diff
+            .into_iter()
+            .filter(|path_diff| path_glob.is_match(path_diff.path().path().to_string()))
+            .collect(),
+    }
+}
+
+/// Helper for commit_compare to parse the optional `file_attribute_filter`
+/// restriction. `CONTENT_TYPE` is rejected: this repo has no content-type
+/// sniffing, only a binary/text/non-UTF8 classification, so there's nothing
+/// to filter on yet.
+fn parse_file_attribute_filter(
+    params: &thrift::CommitCompareParams,
+) -> Result<Option<thrift::FileAttributeFilter>, errors::ServiceError> {
+    match params.file_attribute_filter {
+        Some(thrift::FileAttributeFilter::CONTENT_TYPE) => Err(errors::invalid_request(
+            "file_attribute_filter CONTENT_TYPE is not yet supported",
+        )
+        .into()),
+        filter => Ok(filter),
+    }
+}
+
+/// Helper for commit_compare to restrict `diff_files` entries to those
+/// matching the `file_attribute_filter` restriction, if one was requested.
+/// Trees and git submodules are never filtered.
+fn filter_by_file_attribute(
+    diff: Vec<CommitComparePath>,
+    file_attribute_filter: Option<thrift::FileAttributeFilter>,
+) -> Vec<CommitComparePath> {
+    let file_attribute_filter = match file_attribute_filter {
+        Some(file_attribute_filter) => file_attribute_filter,
+        None => return diff,
+    };
+    diff.into_iter()
+        .filter(|entry| match entry {
+            CommitComparePath::File(file) => {
+                let entry_type = file
+                    .base_file
+                    .as_ref()
+                    .or(file.other_file.as_ref())
+                    .map(|file| file.r#type);
+                matches!(
+                    (file_attribute_filter, entry_type),
+                    (
+                        thrift::FileAttributeFilter::EXECUTABLE,
+                        Some(thrift::EntryType::EXEC)
+                    ) | (
+                        thrift::FileAttributeFilter::SYMLINK,
+                        Some(thrift::EntryType::LINK)
+                    )
+                )
+            }
+            _ => true,
+        })
+        .collect()
+}
+
 struct CommitFileDiffsItem {
     path_diff_context: ChangesetPathDiffContext,
     placeholder: bool,
@@ -181,6 +382,7 @@ impl CommitFileDiffsItem {
         match format {
             thrift::DiffFormat::RAW_DIFF => self.raw_diff(context_lines).await,
             thrift::DiffFormat::METADATA_DIFF => self.metadata_diff().await,
+            thrift::DiffFormat::GIT_DIFF => self.git_diff(context_lines).await,
             unknown => {
                 Err(errors::invalid_request(format!("invalid diff format: {:?}", unknown)).into())
             }
@@ -207,42 +409,342 @@ impl CommitFileDiffsItem {
         let metadata_diff = self.path_diff_context.metadata_diff().await?;
         Ok(CommitFileDiffsResponseElement::MetadataDiff { metadata_diff })
     }
+
+    /// Builds the `diff --git`, mode, and rename/copy header lines for the
+    /// git-patch format, and prepends them to the `--- a/...` / `+++ b/...`
+    /// unified diff body. This doesn't include an `index <old>..<new> <mode>`
+    /// line, since Mononoke doesn't cheaply expose the Git blob hash of file
+    /// content at this layer; `git apply` (without `--index`) doesn't need it.
+    async fn git_diff(
+        &self,
+        context_lines: usize,
+    ) -> Result<CommitFileDiffsResponseElement, errors::ServiceError> {
+        let mode = if self.placeholder {
+            UnifiedDiffMode::OmitContent
+        } else {
+            UnifiedDiffMode::Inline
+        };
+        let header = self.git_diff_header().await?;
+        let diff = self
+            .path_diff_context
+            .unified_diff(context_lines, mode)
+            .await?;
+        let mut raw_diff = header.into_bytes();
+        raw_diff.extend_from_slice(&diff.raw_diff);
+        Ok(CommitFileDiffsResponseElement::GitDiff {
+            diff: UnifiedDiff {
+                raw_diff,
+                is_binary: diff.is_binary,
+            },
+        })
+    }
+
+    /// Whether either side of this diff is a symlink. Callers use this to
+    /// decide whether to render the diff body as a plain old-target/new-target
+    /// text change rather than as a regular file edit, since a symlink's
+    /// content is just the link target string, not file data.
+    async fn is_symlink_change(&self) -> Result<bool, errors::ServiceError> {
+        let (old_type, new_type) = try_join!(
+            async {
+                match self.path_diff_context.other() {
+                    Some(path) => path.file_type().await,
+                    None => Ok(None),
+                }
+            },
+            async {
+                match self.path_diff_context.base() {
+                    Some(path) => path.file_type().await,
+                    None => Ok(None),
+                }
+            }
+        )?;
+        Ok(old_type == Some(FileType::Symlink) || new_type == Some(FileType::Symlink))
+    }
+
+    async fn git_diff_header(&self) -> Result<String, errors::ServiceError> {
+        let base = self.path_diff_context.base();
+        let other = self.path_diff_context.other();
+        let base_path = base.map(|p| p.path().to_string());
+        let other_path = other.map(|p| p.path().to_string());
+        // Git shows both sides of the `diff --git` line using whichever path is
+        // available, falling back to the other side's path for pure adds/removes.
+        let a_path = other_path.as_deref().or(base_path.as_deref()).ok_or_else(|| {
+            errors::internal_error("diff has neither a base nor an other path")
+        })?;
+        let b_path = base_path.as_deref().or(other_path.as_deref()).unwrap_or(a_path);
+        let mut header = format!("diff --git a/{} b/{}\n", a_path, b_path);
+
+        match self.path_diff_context.copy_info() {
+            CopyInfo::None => {}
+            CopyInfo::Copy => {
+                header.push_str(&format!("copy from {}\n", a_path));
+                header.push_str(&format!("copy to {}\n", b_path));
+            }
+            CopyInfo::Move => {
+                header.push_str(&format!("rename from {}\n", a_path));
+                header.push_str(&format!("rename to {}\n", b_path));
+            }
+        }
+
+        let (old_type, new_type) = try_join!(
+            async {
+                match other {
+                    Some(path) => path.file_type().await,
+                    None => Ok(None),
+                }
+            },
+            async {
+                match base {
+                    Some(path) => path.file_type().await,
+                    None => Ok(None),
+                }
+            }
+        )?;
+        match (other_path.is_some(), base_path.is_some()) {
+            (false, true) => {
+                if let Some(file_type) = new_type {
+                    header.push_str(&format!("new file mode {}\n", git_file_mode(file_type)));
+                }
+            }
+            (true, false) => {
+                if let Some(file_type) = old_type {
+                    header.push_str(&format!("deleted file mode {}\n", git_file_mode(file_type)));
+                }
+            }
+            (true, true) if old_type != new_type => {
+                if let Some(file_type) = old_type {
+                    header.push_str(&format!("old mode {}\n", git_file_mode(file_type)));
+                }
+                if let Some(file_type) = new_type {
+                    header.push_str(&format!("new mode {}\n", git_file_mode(file_type)));
+                }
+            }
+            _ => {}
+        }
+        Ok(header)
+    }
+}
+
+/// The Git file mode string for a given file type, as used in patch headers.
+fn git_file_mode(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::Regular => "100644",
+        FileType::Executable => "100755",
+        FileType::Symlink => "120000",
+        FileType::GitSubmodule => "160000",
+    }
 }
 
 enum CommitFileDiffsResponseElement {
     RawDiff { diff: UnifiedDiff },
     MetadataDiff { metadata_diff: MetadataDiff },
+    GitDiff { diff: UnifiedDiff },
 }
 
 impl CommitFileDiffsResponseElement {
     fn size(&self) -> usize {
         match self {
-            Self::RawDiff { diff } => diff.raw_diff.len(),
+            Self::RawDiff { diff } | Self::GitDiff { diff } => diff.raw_diff.len(),
             Self::MetadataDiff { .. } => 1,
         }
     }
 
+    /// Truncate this diff's raw content down to `limit` bytes, if it exceeds it.
+    /// Returns whether truncation occurred. `MetadataDiff` is never truncated,
+    /// since it's a count of entries rather than a rendered diff body.
+    fn truncate(&mut self, limit: usize) -> bool {
+        match self {
+            Self::RawDiff { diff } | Self::GitDiff { diff } => {
+                if diff.raw_diff.len() > limit {
+                    diff.raw_diff.truncate(limit);
+                    true
+                } else {
+                    false
+                }
+            }
+            Self::MetadataDiff { .. } => false,
+        }
+    }
+
+    fn into_diff(self) -> thrift::Diff {
+        match self {
+            Self::RawDiff { diff } => diff.into_response(),
+            Self::MetadataDiff { metadata_diff } => metadata_diff.into_response(),
+            Self::GitDiff { diff } => thrift::Diff::git_diff(thrift::GitDiff {
+                raw_diff: Some(diff.raw_diff),
+                is_binary: diff.is_binary,
+                ..Default::default()
+            }),
+        }
+    }
+
     fn into_response_for_item(
         self,
         item: &CommitFileDiffsItem,
+        is_symlink_change: bool,
+        truncated: bool,
     ) -> thrift::CommitFileDiffsResponseElement {
-        match self {
-            Self::RawDiff { diff } => thrift::CommitFileDiffsResponseElement {
-                base_path: item.path_diff_context.base().map(|p| p.path().to_string()),
-                other_path: item.path_diff_context.other().map(|p| p.path().to_string()),
-                diff: diff.into_response(),
-                ..Default::default()
-            },
-            Self::MetadataDiff { metadata_diff } => thrift::CommitFileDiffsResponseElement {
-                base_path: item.path_diff_context.base().map(|p| p.path().to_string()),
-                other_path: item.path_diff_context.other().map(|p| p.path().to_string()),
-                diff: metadata_diff.into_response(),
-                ..Default::default()
-            },
+        let copy_info = item.path_diff_context.copy_info();
+        let copy_from_path = match copy_info {
+            CopyInfo::None => None,
+            CopyInfo::Copy | CopyInfo::Move => {
+                item.path_diff_context.other().map(|p| p.path().to_string())
+            }
+        };
+        thrift::CommitFileDiffsResponseElement {
+            base_path: item.path_diff_context.base().map(|p| p.path().to_string()),
+            other_path: item.path_diff_context.other().map(|p| p.path().to_string()),
+            diff: self.into_diff(),
+            copy_from_path,
+            copy_info: copy_info.into_response(),
+            is_symlink_change,
+            truncated,
+            ..Default::default()
         }
     }
 }
 
+/// Per-request state for `commit_compare`'s `inline_diffs` option, shared across the
+/// (possibly concurrent) `CommitComparePath::from_path_diff` calls that populate it.
+struct InlineDiffsState {
+    format: thrift::DiffFormat,
+    context_lines: usize,
+    diff_size_limit: usize,
+    size_so_far: AtomicUsize,
+}
+
+impl InlineDiffsState {
+    fn new(
+        params: &thrift::CommitCompareInlineDiffsParams,
+    ) -> Result<Self, errors::ServiceError> {
+        let diff_size_limit = params
+            .diff_size_limit
+            .map(|limit| check_range_and_convert("diff_size_limit", limit, 0..))
+            .transpose()?
+            .unwrap_or(thrift::consts::COMMIT_FILE_DIFFS_SIZE_LIMIT as usize);
+        Ok(Self {
+            format: params.format,
+            context_lines: check_range_and_convert("context", params.context, 0..)?,
+            diff_size_limit,
+            size_so_far: AtomicUsize::new(0),
+        })
+    }
+
+    /// Compute the inline diff for a file, or report that it was omitted because the
+    /// size budget was already exhausted by earlier files in the response.
+    async fn diff_for(
+        &self,
+        path_diff_context: ChangesetPathDiffContext,
+    ) -> Result<(Option<thrift::Diff>, Option<bool>), errors::ServiceError> {
+        if self.size_so_far.load(Ordering::Relaxed) >= self.diff_size_limit {
+            return Ok((None, Some(true)));
+        }
+        let item = CommitFileDiffsItem {
+            path_diff_context,
+            placeholder: false,
+        };
+        let element = item.response_element(self.format, self.context_lines).await?;
+        self.size_so_far.fetch_add(element.size(), Ordering::Relaxed);
+        Ok((Some(element.into_diff()), None))
+    }
+}
+
+/// Compute the set of file paths modified by `changeset` relative to its first parent, for
+/// `commit_find_files`'s `changed_only` filter. For a merge commit, this only reflects the
+/// diff against the first parent, not any other parent. For a commit with no parents, every
+/// path in the commit is returned, by diffing against the empty tree.
+async fn find_files_changed_only_paths(
+    repo: &RepoContext,
+    changeset: &ChangesetContext,
+) -> Result<HashSet<MPath>, errors::ServiceError> {
+    let parents = changeset.parents().await?;
+    let diff = match parents.first() {
+        Some(parent_id) => {
+            let parent = repo
+                .changeset(ChangesetSpecifier::Bonsai(*parent_id))
+                .await?
+                .ok_or_else(|| errors::internal_error("parent changeset is missing"))?;
+            changeset
+                .diff_unordered(
+                    &parent,
+                    false,
+                    false,
+                    None,
+                    true,
+                    btreeset! { ChangesetDiffItem::FILES },
+                )
+                .await?
+        }
+        None => {
+            changeset
+                .diff_root_unordered(None, true, btreeset! { ChangesetDiffItem::FILES })
+                .await?
+        }
+    };
+    Ok(diff
+        .into_iter()
+        .map(|path_diff| path_diff.path().path().clone())
+        .collect())
+}
+
+/// Apply `commit_find_files`'s forward-pagination truncation to a page of results
+/// collected with one extra lookahead item (i.e. `limit + 1` items were requested from
+/// the stream): decide whether more results exist beyond `limit`, and if so, the path to
+/// resume from on the next call. `path_of` extracts the path from an item since the
+/// metadata and plain-path variants of `commit_find_files` collect different item types.
+///
+/// `limit` may be 0, in which case no items are returned and `continue_after` is `None`
+/// (there's no "last returned item" to resume after).
+fn truncate_find_files_page<T>(
+    mut entries: Vec<T>,
+    limit: usize,
+    path_of: impl Fn(&T) -> String,
+) -> (bool, Option<String>, Vec<T>) {
+    let has_more = entries.len() > limit;
+    let continue_after = has_more
+        .then(|| limit.checked_sub(1))
+        .flatten()
+        .and_then(|last_index| entries.get(last_index))
+        .map(path_of);
+    entries.truncate(limit);
+    (has_more, continue_after, entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn truncate_find_files_page_with_limit_zero_does_not_panic() {
+        let (has_more, continue_after, entries) =
+            truncate_find_files_page(vec!["a".to_string()], 0, |s| s.clone());
+        assert!(has_more);
+        assert_eq!(continue_after, None);
+        assert_eq!(entries, Vec::<String>::new());
+    }
+
+    #[test]
+    fn truncate_find_files_page_with_more_results() {
+        let (has_more, continue_after, entries) = truncate_find_files_page(
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            2,
+            |s| s.clone(),
+        );
+        assert!(has_more);
+        assert_eq!(continue_after, Some("b".to_string()));
+        assert_eq!(entries, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn truncate_find_files_page_with_no_more_results() {
+        let (has_more, continue_after, entries) =
+            truncate_find_files_page(vec!["a".to_string(), "b".to_string()], 2, |s| s.clone());
+        assert!(!has_more);
+        assert_eq!(continue_after, None);
+        assert_eq!(entries, vec!["a".to_string(), "b".to_string()]);
+    }
+}
+
 impl SourceControlServiceImpl {
     /// Returns the lowest common ancestor of two commits.
     ///
@@ -277,15 +779,39 @@ impl SourceControlServiceImpl {
         params: thrift::CommitLookupParams,
     ) -> Result<thrift::CommitLookupResponse, errors::ServiceError> {
         let repo = self.repo(ctx, &commit.repo).await?;
+        if let thrift::CommitId::commit_id_prefix(ref prefix) = commit.id {
+            return Self::commit_lookup_prefix(&repo, prefix, &params).await;
+        }
         match repo
             .changeset(ChangesetSpecifier::from_request(&commit.id)?)
             .await?
         {
             Some(cs) => {
                 let ids = map_commit_identity(&cs, &params.identity_schemes).await?;
+                // A `git` commit id that doesn't match `cs`'s own Git hash was
+                // resolved by peeling an annotated tag, since that's the only
+                // way a `GitSha1` specifier can land on a different commit.
+                let tag_id = if params.identify_tag {
+                    match &commit.id {
+                        thrift::CommitId::git(requested_git_id) => {
+                            match cs.git_sha1().await? {
+                                Some(resolved_git_id)
+                                    if resolved_git_id.as_ref() != requested_git_id.as_slice() =>
+                                {
+                                    Some(thrift::CommitId::git(requested_git_id.clone()))
+                                }
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
                 Ok(thrift::CommitLookupResponse {
                     exists: true,
                     ids: Some(ids),
+                    tag_id,
                     ..Default::default()
                 })
             }
@@ -297,6 +823,49 @@ impl SourceControlServiceImpl {
         }
     }
 
+    /// Resolve a `CommitIdPrefix` for `commit_lookup`, returning the unique match, an
+    /// `ambiguous_ids` resolution listing up to `COMMIT_LOOKUP_AMBIGUOUS_ID_LIMIT`
+    /// candidates, or a not-found response.
+    async fn commit_lookup_prefix(
+        repo: &RepoContext,
+        prefix: &thrift::CommitIdPrefix,
+        params: &thrift::CommitLookupParams,
+    ) -> Result<thrift::CommitLookupResponse, errors::ServiceError> {
+        use ChangesetSpecifierPrefixResolution::*;
+
+        let prefix = ChangesetPrefixSpecifier::from_request(prefix)?;
+        match repo.resolve_changeset_id_prefix(prefix).await? {
+            Single(cs_id) => match repo.changeset(cs_id).await? {
+                None => Err(errors::internal_error(
+                    "unexpected failure to resolve an existing commit",
+                )
+                .into()),
+                Some(cs) => {
+                    let ids = map_commit_identity(&cs, &params.identity_schemes).await?;
+                    Ok(thrift::CommitLookupResponse {
+                        exists: true,
+                        ids: Some(ids),
+                        ..Default::default()
+                    })
+                }
+            },
+            NoMatch => Ok(thrift::CommitLookupResponse {
+                exists: false,
+                ..Default::default()
+            }),
+            Multiple(ids) | TooMany(ids) => Ok(thrift::CommitLookupResponse {
+                exists: false,
+                ambiguous_ids: Some(
+                    ids.iter()
+                        .take(thrift::consts::COMMIT_LOOKUP_AMBIGUOUS_ID_LIMIT as usize)
+                        .map(changeset_specifier_to_commit_id)
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+        }
+    }
+
     /// Get diff.
     pub(crate) async fn commit_file_diffs(
         &self,
@@ -309,18 +878,41 @@ impl SourceControlServiceImpl {
             Err(errors::diff_input_too_many_paths(params.paths.len()))?;
         }
 
-        // Resolve the CommitSpecfier into ChangesetContext
-        let (base_commit, other_commit) = match params.other_commit_id {
-            Some(other_commit_id) => {
-                let (_repo, base_commit, other_commit) = self
-                    .repo_changeset_pair(ctx, &commit, &other_commit_id)
+        // WORD and CHAR granularity aren't implemented by any diff format yet, since
+        // the diff engine backing `unified_diff` only produces line-level hunks.
+        match params.granularity.unwrap_or(thrift::DiffGranularity::LINE) {
+            thrift::DiffGranularity::LINE => {}
+            granularity => Err(errors::invalid_request(format!(
+                "diff granularity {:?} is not yet supported",
+                granularity
+            )))?,
+        }
+
+        // Resolve the CommitSpecfier into ChangesetContext. `diff_against_empty_tree` takes
+        // priority over `other_commit_id`: it diffs every path against the empty tree (so every
+        // path appears as a full addition), mirroring the `diff_root` behaviour `commit_compare`
+        // gets from leaving `other_commit_id` unset there.
+        let diff_against_empty_tree = params.diff_against_empty_tree.unwrap_or(false);
+        let (base_commit, other_commit) = match &params.relative_other_commit {
+            Some(relative_other_commit) if !diff_against_empty_tree => {
+                let (repo, base_commit) = self.repo_changeset(ctx, &commit).await?;
+                let other_commit = self
+                    .resolve_relative_other_commit(&repo, &base_commit, relative_other_commit)
                     .await?;
                 (base_commit, Some(other_commit))
             }
-            None => {
-                let (_repo, base_commit) = self.repo_changeset(ctx, &commit).await?;
-                (base_commit, None)
-            }
+            _ => match params.other_commit_id {
+                Some(other_commit_id) if !diff_against_empty_tree => {
+                    let (_repo, base_commit, other_commit) = self
+                        .repo_changeset_pair(ctx, &commit, &other_commit_id)
+                        .await?;
+                    (base_commit, Some(other_commit))
+                }
+                _ => {
+                    let (_repo, base_commit) = self.repo_changeset(ctx, &commit).await?;
+                    (base_commit, None)
+                }
+            },
         };
 
         // Resolve the paths into ChangesetPathContentContext
@@ -454,17 +1046,29 @@ impl SourceControlServiceImpl {
             .diff_size_limit
             .map(|limit| check_range_and_convert("diff_size_limit", limit, 0..))
             .transpose()?;
+        let per_file_diff_size_limit: Option<usize> = params
+            .per_file_diff_size_limit
+            .map(|limit| check_range_and_convert("per_file_diff_size_limit", limit, 0..))
+            .transpose()?;
         let mut size_so_far = 0usize;
         let mut stopped_at_pair = None;
 
         let path_diffs = stream::iter(items)
             .map(|item| async move {
-                let element = item.response_element(params.format, context).await?;
-                Ok::<_, errors::ServiceError>((item, element))
+                let (mut element, is_symlink_change) = try_join!(
+                    item.response_element(params.format, context),
+                    item.is_symlink_change()
+                )?;
+                // Truncate before the total `diff_size_limit` accounting below, so a
+                // file capped here also counts towards that limit at its truncated
+                // size, not its original one.
+                let truncated = per_file_diff_size_limit
+                    .is_some_and(|limit| element.truncate(limit));
+                Ok::<_, errors::ServiceError>((item, element, is_symlink_change, truncated))
             })
             .boxed() // Prevents compiler error
             .buffered(20)
-            .try_take_while(|(item, element)| {
+            .try_take_while(|(item, element, _is_symlink_change, _truncated)| {
                 let mut limit_reached = false;
                 if let Some(diff_size_limit) = diff_size_limit {
                     size_so_far = size_so_far.saturating_add(element.size());
@@ -475,7 +1079,9 @@ impl SourceControlServiceImpl {
                 }
                 async move { Ok(!limit_reached) }
             })
-            .map_ok(|(item, element)| element.into_response_for_item(&item))
+            .map_ok(|(item, element, is_symlink_change, truncated)| {
+                element.into_response_for_item(&item, is_symlink_change, truncated)
+            })
             .try_collect()
             .await?;
 
@@ -494,7 +1100,14 @@ impl SourceControlServiceImpl {
         params: thrift::CommitInfoParams,
     ) -> Result<thrift::CommitInfo, errors::ServiceError> {
         let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
-        changeset.into_response_with(&params.identity_schemes).await
+        let children_bookmark = params
+            .children_bookmark
+            .as_deref()
+            .map(BookmarkKey::from_request)
+            .transpose()?;
+        changeset
+            .into_response_with(&(params.identity_schemes, params.fields, children_bookmark))
+            .await
     }
 
     /// Returns `true` if this commit is an ancestor of `other_commit`.
@@ -511,6 +1124,57 @@ impl SourceControlServiceImpl {
         Ok(is_ancestor_of)
     }
 
+    /// Resolve a `CommitFileDiffsRelativeOtherCommit` into the changeset it refers to,
+    /// relative to `base_commit`. `merge_base_with` takes priority over
+    /// `first_parent_ancestor_offset` if both are set.
+    async fn resolve_relative_other_commit(
+        &self,
+        repo: &RepoContext,
+        base_commit: &ChangesetContext,
+        relative_other_commit: &thrift::CommitFileDiffsRelativeOtherCommit,
+    ) -> Result<ChangesetContext, errors::ServiceError> {
+        if let Some(merge_base_with) = &relative_other_commit.merge_base_with {
+            let merge_base_with = ChangesetSpecifier::from_request(merge_base_with)?;
+            let merge_base_with = repo
+                .changeset(merge_base_with)
+                .await?
+                .ok_or_else(|| errors::commit_not_found(merge_base_with.to_string()))?;
+            return base_commit
+                .common_base_with(merge_base_with.id())
+                .await?
+                .ok_or_else(|| errors::internal_error("no common ancestor exists").into());
+        }
+
+        let offset = relative_other_commit
+            .first_parent_ancestor_offset
+            .ok_or_else(|| {
+                errors::invalid_request(
+                    "relative_other_commit must set either first_parent_ancestor_offset or merge_base_with",
+                )
+            })?;
+        if offset <= 0 {
+            return Err(
+                errors::invalid_request("first_parent_ancestor_offset must be positive").into(),
+            );
+        }
+
+        let mut ancestor = base_commit.clone();
+        for _ in 0..offset {
+            let parent_id = ancestor.parents().await?.first().copied().ok_or_else(|| {
+                errors::invalid_request(format!(
+                    "commit {} has fewer than {} first-parent ancestors",
+                    base_commit.id(),
+                    offset
+                ))
+            })?;
+            ancestor = repo
+                .changeset(ChangesetSpecifier::Bonsai(parent_id))
+                .await?
+                .ok_or_else(|| errors::internal_error("parent changeset is missing"))?;
+        }
+        Ok(ancestor)
+    }
+
     /// Given a base changeset, find the "other" changeset from parent information
     /// including mutable history if appropriate
     ///
@@ -561,7 +1225,7 @@ impl SourceControlServiceImpl {
         commit: thrift::CommitSpecifier,
         params: thrift::CommitCompareParams,
     ) -> Result<thrift::CommitCompareResponse, errors::ServiceError> {
-        let (base_changeset, other_changeset) = match &params.other_commit_id {
+        let (mut base_changeset, other_changeset) = match &params.other_commit_id {
             Some(id) => {
                 let (_repo, mut base_changeset, other_changeset) =
                     self.repo_changeset_pair(ctx, &commit, id).await?;
@@ -578,6 +1242,24 @@ impl SourceControlServiceImpl {
             }
         };
 
+        if params.use_merge_base.unwrap_or(false) {
+            // Three-dot semantics: diff the merge base against `other_commit_id`, not
+            // `commit` itself, so the response only shows what changed on
+            // `other_commit_id`'s branch since it diverged from `commit`.
+            let other = other_changeset.as_ref().ok_or_else(|| {
+                MononokeError::InvalidRequest("use_merge_base requires other_commit_id to be set".to_string())
+            })?;
+            base_changeset = base_changeset
+                .common_base_with(other.id())
+                .await?
+                .ok_or_else(|| {
+                    MononokeError::InvalidRequest(
+                        "commit and other_commit_id have no common ancestor".to_string(),
+                    )
+                })?;
+            add_mutable_renames(&mut base_changeset, &params).await?;
+        }
+
         let mut last_path = None;
         let mut diff_items: BTreeSet<_> = params
             .compare_items
@@ -585,6 +1267,9 @@ impl SourceControlServiceImpl {
             .filter_map(|item| match item {
                 thrift::CommitCompareItem::FILES => Some(ChangesetDiffItem::FILES),
                 thrift::CommitCompareItem::TREES => Some(ChangesetDiffItem::TREES),
+                thrift::CommitCompareItem::GIT_SUBMODULES => {
+                    Some(ChangesetDiffItem::GIT_SUBMODULES)
+                }
                 _ => None,
             })
             .collect();
@@ -603,35 +1288,215 @@ impl SourceControlServiceImpl {
                     .map_err(|error| MononokeError::InvalidRequest(error.to_string()))?,
             ),
         };
-        let (diff_files, diff_trees) = match params.ordered_params {
+
+        let path_glob = parse_path_glob(&params)?;
+        let file_attribute_filter = parse_file_attribute_filter(&params)?;
+        let recurse_under_paths = params.recurse_under_paths.unwrap_or(true);
+
+        if params.top_level_dirs_only.unwrap_or(false) {
+            let other_changeset = other_changeset.ok_or_else(|| {
+                MononokeError::InvalidRequest(
+                    "top_level_dirs_only requires other_commit_id to be set".to_string(),
+                )
+            })?;
+            let changed_top_level_paths = base_changeset
+                .changed_top_level_paths(&other_changeset)
+                .await?;
+            let other_commit_ids =
+                Some(map_commit_identity(&other_changeset, &params.identity_schemes).await?);
+            return Ok(thrift::CommitCompareResponse {
+                other_commit_ids,
+                changed_top_level_paths: Some(changed_top_level_paths),
+                ..Default::default()
+            });
+        }
+
+        if params.paths_only.unwrap_or(false) {
+            // Deliberately excludes `path_glob`: it's applied below as a plain string
+            // filter over the cached (or freshly computed) unfiltered changed-path
+            // list, so that requests differing only by glob can share a cache entry.
+            let bypass_cache = params.bypass_compare_cache.unwrap_or(false);
+            let cache_key = CompareCacheKey {
+                base: base_changeset.id(),
+                other: other_changeset.as_ref().map(|c| c.id()),
+                diff_items: diff_items.clone(),
+                paths: paths.clone(),
+                skip_copies_renames: params.skip_copies_renames,
+                find_renames_across_tree: params.find_renames_across_tree.unwrap_or(false),
+                recurse_under_paths,
+            };
+            let all_changed_paths = match (!bypass_cache)
+                .then(|| commit_compare_cache::get(&cache_key))
+                .flatten()
+            {
+                Some(cached) => cached,
+                None => {
+                    let diff = match other_changeset {
+                        Some(ref other_changeset) => {
+                            base_changeset
+                                .diff_unordered(
+                                    other_changeset,
+                                    !params.skip_copies_renames,
+                                    params.find_renames_across_tree.unwrap_or(false),
+                                    paths,
+                                    recurse_under_paths,
+                                    diff_items,
+                                )
+                                .await?
+                        }
+                        None => {
+                            base_changeset
+                                .diff_root_unordered(paths, recurse_under_paths, diff_items)
+                                .await?
+                        }
+                    };
+                    let all_changed_paths = Arc::new(
+                        diff.into_iter()
+                            .map(|path_diff| path_diff.path().path().to_string())
+                            .collect::<Vec<_>>(),
+                    );
+                    if !bypass_cache {
+                        commit_compare_cache::put(cache_key, Arc::clone(&all_changed_paths));
+                    }
+                    all_changed_paths
+                }
+            };
+            let changed_paths = match path_glob.as_ref() {
+                None => (*all_changed_paths).clone(),
+                Some(path_glob) => all_changed_paths
+                    .iter()
+                    .filter(|path| path_glob.is_match(path.as_str()))
+                    .cloned()
+                    .collect(),
+            };
+            let other_commit_ids = match other_changeset {
+                None => None,
+                Some(other_changeset) => {
+                    Some(map_commit_identity(&other_changeset, &params.identity_schemes).await?)
+                }
+            };
+            return Ok(thrift::CommitCompareResponse {
+                other_commit_ids,
+                changed_paths: Some(changed_paths),
+                ..Default::default()
+            });
+        }
+
+        if params.renames_only.unwrap_or(false) {
+            let diff = match other_changeset {
+                Some(ref other_changeset) => {
+                    base_changeset
+                        .diff_unordered(
+                            other_changeset,
+                            !params.skip_copies_renames,
+                            params.find_renames_across_tree.unwrap_or(false),
+                            paths,
+                            recurse_under_paths,
+                            diff_items,
+                        )
+                        .await?
+                }
+                None => {
+                    base_changeset
+                        .diff_root_unordered(paths, recurse_under_paths, diff_items)
+                        .await?
+                }
+            };
+            let renamed_paths = filter_by_path_glob(diff, path_glob.as_ref())
+                .into_iter()
+                .filter(|path_diff| {
+                    matches!(path_diff.copy_info(), CopyInfo::Copy | CopyInfo::Move)
+                })
+                .map(|path_diff| {
+                    let base_path = path_diff
+                        .base()
+                        .ok_or_else(|| {
+                            errors::internal_error("copied/moved entry has no base path")
+                        })?
+                        .path()
+                        .to_string();
+                    let other_path = path_diff
+                        .other()
+                        .ok_or_else(|| {
+                            errors::internal_error("copied/moved entry has no other path")
+                        })?
+                        .path()
+                        .to_string();
+                    Ok(thrift::CommitCompareRenamedPath {
+                        base_path,
+                        other_path,
+                        copy_info: path_diff.copy_info().into_response(),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Result<Vec<_>, errors::ServiceError>>()?;
+            let other_commit_ids = match other_changeset {
+                None => None,
+                Some(other_changeset) => {
+                    Some(map_commit_identity(&other_changeset, &params.identity_schemes).await?)
+                }
+            };
+            return Ok(thrift::CommitCompareResponse {
+                other_commit_ids,
+                renamed_paths: Some(renamed_paths),
+                ..Default::default()
+            });
+        }
+
+        let inline_diffs = params
+            .inline_diffs
+            .as_ref()
+            .map(InlineDiffsState::new)
+            .transpose()?
+            .map(Arc::new);
+
+        let mut rename_detection_diagnostics = None;
+        let ((diff_files, diff_trees, diff_git_submodules), total_count) = match params.ordered_params {
             None => {
+                let want_rename_diagnostics = params.with_rename_detection_diagnostics.unwrap_or(false)
+                    && params.find_renames_across_tree.unwrap_or(false);
                 let diff = match other_changeset {
+                    Some(ref other_changeset) if want_rename_diagnostics => {
+                        let (diff, diagnostics) = base_changeset
+                            .diff_unordered_with_rename_diagnostics(
+                                other_changeset,
+                                !params.skip_copies_renames,
+                                true,
+                                paths,
+                                recurse_under_paths,
+                                diff_items,
+                            )
+                            .await?;
+                        rename_detection_diagnostics = Some(diagnostics);
+                        diff
+                    }
                     Some(ref other_changeset) => {
                         base_changeset
                             .diff_unordered(
                                 other_changeset,
                                 !params.skip_copies_renames,
+                                params.find_renames_across_tree.unwrap_or(false),
                                 paths,
+                                recurse_under_paths,
                                 diff_items,
                             )
                             .await?
                     }
                     None => {
                         base_changeset
-                            .diff_root_unordered(paths, diff_items)
+                            .diff_root_unordered(paths, recurse_under_paths, diff_items)
                             .await?
                     }
                 };
-                stream::iter(diff)
-                    .map(CommitComparePath::from_path_diff)
+                let diff = stream::iter(filter_by_path_glob(diff, path_glob.as_ref()))
+                    .map(|path_diff| {
+                        CommitComparePath::from_path_diff(path_diff, inline_diffs.clone())
+                    })
                     .buffer_unordered(CONCURRENCY_LIMIT)
                     .try_collect::<Vec<_>>()
-                    .await?
-                    .into_iter()
-                    .partition_map(|diff| match diff {
-                        CommitComparePath::File(entry) => Either::Left(entry),
-                        CommitComparePath::Tree(entry) => Either::Right(entry),
-                    })
+                    .await?;
+                let diff = filter_by_file_attribute(diff, file_attribute_filter);
+                (partition_compare_paths(diff), None)
             }
             Some(ordered_params) => {
                 let limit: usize = check_range_and_convert(
@@ -650,13 +1515,43 @@ impl SourceControlServiceImpl {
                         })
                     })
                     .transpose()?;
+                let total_count = if ordered_params.with_total_count.unwrap_or(false) {
+                    let diff = match other_changeset {
+                        Some(ref other_changeset) => {
+                            base_changeset
+                                .diff_unordered(
+                                    other_changeset,
+                                    !params.skip_copies_renames,
+                                    params.find_renames_across_tree.unwrap_or(false),
+                                    paths.clone(),
+                                    recurse_under_paths,
+                                    diff_items.clone(),
+                                )
+                                .await?
+                        }
+                        None => {
+                            base_changeset
+                                .diff_root_unordered(
+                                    paths.clone(),
+                                    recurse_under_paths,
+                                    diff_items.clone(),
+                                )
+                                .await?
+                        }
+                    };
+                    Some(filter_by_path_glob(diff, path_glob.as_ref()).len() as i64)
+                } else {
+                    None
+                };
                 let diff = match other_changeset {
                     Some(ref other_changeset) => {
                         base_changeset
                             .diff(
                                 other_changeset,
                                 !params.skip_copies_renames,
+                                params.find_renames_across_tree.unwrap_or(false),
                                 paths,
+                                recurse_under_paths,
                                 diff_items,
                                 ChangesetFileOrdering::Ordered { after },
                                 Some(limit),
@@ -667,6 +1562,7 @@ impl SourceControlServiceImpl {
                         base_changeset
                             .diff_root(
                                 paths,
+                                recurse_under_paths,
                                 diff_items,
                                 ChangesetFileOrdering::Ordered { after },
                                 Some(limit),
@@ -676,7 +1572,9 @@ impl SourceControlServiceImpl {
                 };
                 let diff_items = diff
                     .into_iter()
-                    .map(CommitComparePath::from_path_diff)
+                    .map(|path_diff| {
+                        CommitComparePath::from_path_diff(path_diff, inline_diffs.clone())
+                    })
                     .collect::<FuturesOrdered<_>>()
                     .try_collect::<Vec<_>>()
                     .await?;
@@ -685,10 +1583,25 @@ impl SourceControlServiceImpl {
                         last_path = Some(item.path()?.to_string());
                     }
                 }
-                diff_items.into_iter().partition_map(|diff| match diff {
-                    CommitComparePath::File(entry) => Either::Left(entry),
-                    CommitComparePath::Tree(entry) => Either::Right(entry),
-                })
+                // The glob restriction (if any) is applied after `last_path` is derived from
+                // the unfiltered page, so the continuation token keeps advancing over the
+                // underlying diff even when a page contains no glob-matching entries.
+                let diff_items = match &path_glob {
+                    None => diff_items,
+                    Some(path_glob) => {
+                        let mut filtered = Vec::with_capacity(diff_items.len());
+                        for diff in diff_items {
+                            if path_glob.is_match(diff.path()?) {
+                                filtered.push(diff);
+                            }
+                        }
+                        filtered
+                    }
+                };
+                // Likewise applied after `last_path` and the glob restriction, so it only
+                // trims the page that's actually returned to the caller.
+                let diff_items = filter_by_file_attribute(diff_items, file_attribute_filter);
+                (partition_compare_paths(diff_items), total_count)
             }
         };
 
@@ -698,11 +1611,130 @@ impl SourceControlServiceImpl {
                 Some(map_commit_identity(&other_changeset, &params.identity_schemes).await?)
             }
         };
+        let rename_detection_diagnostics =
+            rename_detection_diagnostics.map(|diagnostics| {
+                thrift::CommitCompareRenameDetectionDiagnostics {
+                    delete_candidates_examined: diagnostics.delete_candidates_examined as i64,
+                    add_candidates_examined: diagnostics.add_candidates_examined as i64,
+                    renames_detected: diagnostics.renames_detected as i64,
+                    candidate_cap_reached: diagnostics.candidate_cap_reached,
+                    ..Default::default()
+                }
+            });
         Ok(thrift::CommitCompareResponse {
             diff_files,
             diff_trees,
             other_commit_ids,
             last_path,
+            diff_git_submodules: Some(diff_git_submodules),
+            total_count,
+            rename_detection_diagnostics,
+            ..Default::default()
+        })
+    }
+
+    /// How long the ephemeral bubble backing a `commit_compare_overlay` commit is
+    /// kept around for. The bubble and the commit inside it are only ever read
+    /// back within this same request, so this just needs to outlive that.
+    const COMMIT_COMPARE_OVERLAY_BUBBLE_LIFETIME: Duration = Duration::from_secs(300);
+
+    /// Like `commit_compare`, but diffs the commit against an in-memory overlay
+    /// of path content changes instead of another commit. The overlay is
+    /// materialized as a throwaway commit in a short-lived ephemeral bubble,
+    /// which becomes the "base" side of the diff, with the requested commit as
+    /// the "other" side, mirroring how `commit_compare` diffs a commit against
+    /// its parent by default.
+    pub(crate) async fn commit_compare_overlay(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitCompareOverlayParams,
+    ) -> Result<thrift::CommitCompareResponse, errors::ServiceError> {
+        let (repo, base_changeset) = self.repo_changeset(ctx, &commit).await?;
+
+        let overlay_changes =
+            SourceControlServiceImpl::convert_create_commit_changes(&repo, params.overlay)
+                .await?;
+        let bubble = repo
+            .create_bubble(Some(Self::COMMIT_COMPARE_OVERLAY_BUBBLE_LIFETIME), Vec::new())
+            .await?;
+        let now = Local::now();
+        let info = CreateInfo {
+            author: "svcscm".to_string(),
+            author_date: now.with_timezone(now.offset()),
+            committer: None,
+            committer_date: None,
+            message: "commit_compare_overlay".to_string(),
+            extra: BTreeMap::new(),
+            git_extra_headers: None,
+        };
+        let overlay_changeset = repo
+            .create_changeset(
+                vec![base_changeset.id()],
+                info,
+                overlay_changes,
+                Some(&bubble),
+            )
+            .await?;
+
+        let mut diff_items: BTreeSet<_> = params
+            .compare_items
+            .into_iter()
+            .filter_map(|item| match item {
+                thrift::CommitCompareItem::FILES => Some(ChangesetDiffItem::FILES),
+                thrift::CommitCompareItem::TREES => Some(ChangesetDiffItem::TREES),
+                thrift::CommitCompareItem::GIT_SUBMODULES => {
+                    Some(ChangesetDiffItem::GIT_SUBMODULES)
+                }
+                _ => None,
+            })
+            .collect();
+        if diff_items.is_empty() {
+            diff_items = btreeset! { ChangesetDiffItem::FILES };
+        }
+
+        let paths: Option<Vec<MPath>> = match params.paths {
+            None => None,
+            Some(paths) => Some(
+                paths
+                    .iter()
+                    .map(MPath::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| MononokeError::InvalidRequest(error.to_string()))?,
+            ),
+        };
+        let path_glob = params
+            .path_glob
+            .as_deref()
+            .map(|pattern| {
+                Glob::new(pattern)
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|error| {
+                        errors::invalid_request(format!(
+                            "invalid path_glob '{}': {}",
+                            pattern, error
+                        ))
+                    })
+            })
+            .transpose()?;
+
+        let diff = overlay_changeset
+            .diff_unordered(&base_changeset, true, false, paths, true, diff_items)
+            .await?;
+        let diff = stream::iter(filter_by_path_glob(diff, path_glob.as_ref()))
+            .map(|path_diff| CommitComparePath::from_path_diff(path_diff, None))
+            .buffer_unordered(CONCURRENCY_LIMIT)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let (diff_files, diff_trees, diff_git_submodules) = partition_compare_paths(diff);
+
+        let other_commit_ids =
+            Some(map_commit_identity(&base_changeset, &params.identity_schemes).await?);
+        Ok(thrift::CommitCompareResponse {
+            diff_files,
+            diff_trees,
+            other_commit_ids,
+            diff_git_submodules: Some(diff_git_submodules),
             ..Default::default()
         })
     }
@@ -714,7 +1746,12 @@ impl SourceControlServiceImpl {
         commit: thrift::CommitSpecifier,
         params: thrift::CommitFindFilesParams,
     ) -> Result<thrift::CommitFindFilesResponse, errors::ServiceError> {
-        let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let (repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let changed_only_paths = if params.changed_only.unwrap_or(false) {
+            Some(find_files_changed_only_paths(&repo, &changeset).await?)
+        } else {
+            None
+        };
         let limit: usize = check_range_and_convert(
             "limit",
             params.limit,
@@ -733,6 +1770,29 @@ impl SourceControlServiceImpl {
             ),
             None => None,
         };
+        let exclude_prefixes: Option<Vec<_>> = match params.exclude_prefixes {
+            Some(exclude_prefixes) => Some(
+                exclude_prefixes
+                    .into_iter()
+                    .map(|prefix| {
+                        MPath::try_from(&prefix).map_err(|e| {
+                            errors::invalid_request(format!(
+                                "invalid exclude_prefix '{}': {}",
+                                prefix, e
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        let reverse = params.reverse.unwrap_or(false);
+        if reverse && params.after.is_some() {
+            return Err(errors::invalid_request(
+                "'reverse' cannot be combined with 'after', pagination is only supported in ascending order",
+            )
+            .into());
+        }
         let ordering = match &params.after {
             Some(after) => {
                 let after = Some(MPath::try_from(after).map_err(|e| {
@@ -743,24 +1803,206 @@ impl SourceControlServiceImpl {
             None => ChangesetFileOrdering::Unordered,
         };
 
-        let files = changeset
-            .find_files(
+        if params.include_metadata.unwrap_or(false) {
+            let entries_stream = changeset
+                .find_files_with_metadata(
+                    prefixes,
+                    exclude_prefixes,
+                    params.basenames,
+                    params.basename_suffixes,
+                    ordering,
+                )
+                .await?
+                .try_filter(move |(path, _)| {
+                    let keep = changed_only_paths
+                        .as_ref()
+                        .map_or(true, |paths| paths.contains(path));
+                    future::ready(keep)
+                })
+                .map_ok(|(path, fsnode_file)| thrift::CommitFindFilesEntry {
+                    path: path.to_string(),
+                    r#type: (*fsnode_file.file_type()).into_response(),
+                    file_size: fsnode_file.size() as i64,
+                    id: fsnode_file.content_id().as_ref().to_vec(),
+                    ..Default::default()
+                });
+
+            let (has_more, continue_after, file_entries) = if reverse {
+                let mut entries: Vec<thrift::CommitFindFilesEntry> =
+                    entries_stream.try_collect().await?;
+                entries.sort_unstable_by(|a, b| b.path.cmp(&a.path));
+                let has_more = entries.len() > limit;
+                entries.truncate(limit);
+                (has_more, None, entries)
+            } else {
+                let entries: Vec<thrift::CommitFindFilesEntry> =
+                    entries_stream.take(limit + 1).try_collect().await?;
+                truncate_find_files_page(entries, limit, |entry| entry.path.clone())
+            };
+            return Ok(thrift::CommitFindFilesResponse {
+                files: Vec::new(),
+                file_entries: Some(file_entries),
+                has_more: Some(has_more),
+                continue_after,
+                ..Default::default()
+            });
+        }
+
+        let case_sensitivity = if params.basenames_case_insensitive.unwrap_or(false) {
+            BasenameCaseSensitivity::Insensitive
+        } else {
+            BasenameCaseSensitivity::Sensitive
+        };
+        let files_stream = changeset
+            .find_files_with_case_sensitivity(
                 prefixes,
+                exclude_prefixes,
                 params.basenames,
                 params.basename_suffixes,
                 ordering,
+                case_sensitivity,
             )
             .await?
-            .take(limit)
-            .map_ok(|path| path.to_string())
-            .try_collect()
-            .await?;
+            .try_filter(move |path| {
+                let keep = changed_only_paths
+                    .as_ref()
+                    .map_or(true, |paths| paths.contains(path));
+                future::ready(keep)
+            })
+            .map_ok(|path| path.to_string());
+
+        let (has_more, continue_after, files) = if reverse {
+            let mut files: Vec<String> = files_stream.try_collect().await?;
+            files.sort_unstable_by(|a, b| b.cmp(a));
+            let has_more = files.len() > limit;
+            files.truncate(limit);
+            (has_more, None, files)
+        } else {
+            let files: Vec<String> = files_stream.take(limit + 1).try_collect().await?;
+            truncate_find_files_page(files, limit, |path| path.clone())
+        };
         Ok(thrift::CommitFindFilesResponse {
             files,
+            has_more: Some(has_more),
+            continue_after,
             ..Default::default()
         })
     }
 
+    /// Like `commit_find_files`, but returns paths as a stream that yields each match as it's
+    /// discovered, instead of buffering the whole (up to `limit`) page before replying, so a
+    /// UI can start rendering results for commits with huge matching file sets before the
+    /// whole page is ready. Ordering and `limit` semantics match `commit_find_files`.
+    /// `reverse` and `include_metadata` aren't supported, since both require seeing every
+    /// match before the first one can be returned.
+    pub(crate) async fn commit_find_files_stream(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitFindFilesParams,
+    ) -> Result<
+        (
+            thrift::CommitFindFilesResponse,
+            BoxStream<'static, Result<String, errors::ServiceError>>,
+        ),
+        errors::ServiceError,
+    > {
+        if params.reverse.unwrap_or(false) {
+            return Err(errors::invalid_request(
+                "'reverse' is not supported by the streaming variant of commit_find_files",
+            )
+            .into());
+        }
+        if params.include_metadata.unwrap_or(false) {
+            return Err(errors::invalid_request(
+                "'include_metadata' is not supported by the streaming variant of commit_find_files",
+            )
+            .into());
+        }
+
+        let (repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let changed_only_paths = if params.changed_only.unwrap_or(false) {
+            Some(find_files_changed_only_paths(&repo, &changeset).await?)
+        } else {
+            None
+        };
+        let limit: usize = check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=source_control::COMMIT_FIND_FILES_MAX_LIMIT,
+        )?;
+        let prefixes: Option<Vec<_>> = match params.prefixes {
+            Some(prefixes) => Some(
+                prefixes
+                    .into_iter()
+                    .map(|prefix| {
+                        MPath::try_from(&prefix).map_err(|e| {
+                            errors::invalid_request(format!("invalid prefix '{}': {}", prefix, e))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        let exclude_prefixes: Option<Vec<_>> = match params.exclude_prefixes {
+            Some(exclude_prefixes) => Some(
+                exclude_prefixes
+                    .into_iter()
+                    .map(|prefix| {
+                        MPath::try_from(&prefix).map_err(|e| {
+                            errors::invalid_request(format!(
+                                "invalid exclude_prefix '{}': {}",
+                                prefix, e
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        let ordering = match &params.after {
+            Some(after) => {
+                let after = Some(MPath::try_from(after).map_err(|e| {
+                    errors::invalid_request(format!("invalid continuation path '{}': {}", after, e))
+                })?);
+                ChangesetFileOrdering::Ordered { after }
+            }
+            None => ChangesetFileOrdering::Unordered,
+        };
+        let case_sensitivity = if params.basenames_case_insensitive.unwrap_or(false) {
+            BasenameCaseSensitivity::Insensitive
+        } else {
+            BasenameCaseSensitivity::Sensitive
+        };
+
+        let files_stream = changeset
+            .find_files_with_case_sensitivity(
+                prefixes,
+                exclude_prefixes,
+                params.basenames,
+                params.basename_suffixes,
+                ordering,
+                case_sensitivity,
+            )
+            .await?
+            .try_filter(move |path| {
+                let keep = changed_only_paths
+                    .as_ref()
+                    .map_or(true, |paths| paths.contains(path));
+                future::ready(keep)
+            })
+            .take(limit)
+            .map_ok(|path| path.to_string())
+            .map_err(errors::ServiceError::from)
+            .boxed();
+
+        let response = thrift::CommitFindFilesResponse {
+            files: Vec::new(),
+            ..Default::default()
+        };
+        Ok((response, files_stream))
+    }
+
     /// Returns the history of a commit
     pub(crate) async fn commit_history(
         &self,
@@ -825,6 +2067,29 @@ impl SourceControlServiceImpl {
                 exclude_changeset_and_ancestors,
             })
             .await?;
+        // `extra` is excluded by default here even though an unset `fields` mask
+        // otherwise means "all fields", since history listings can be much
+        // longer than a single `commit_info` call and extras are rarely needed
+        // for timeline rendering. `include_extras`/`fields = {EXTRA}` opt back in.
+        let fields = if params.include_extras {
+            params.fields.clone().map(|mut fields| {
+                fields.insert(thrift::CommitInfoField::EXTRA);
+                fields
+            })
+        } else {
+            match &params.fields {
+                Some(fields) => Some(fields.clone()),
+                None => Some(
+                    [
+                        thrift::CommitInfoField::MESSAGE,
+                        thrift::CommitInfoField::PARENTS,
+                        thrift::CommitInfoField::GIT_EXTRA_HEADERS,
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+            }
+        };
         let history = collect_history(
             history_stream,
             skip,
@@ -833,6 +2098,8 @@ impl SourceControlServiceImpl {
             after_timestamp,
             params.format,
             &params.identity_schemes,
+            &fields,
+            params.reverse,
         )
         .await?;
 
@@ -861,6 +2128,23 @@ impl SourceControlServiceImpl {
         } else {
             None
         };
+        let categories = if params.bookmark_categories.is_empty() {
+            BookmarkCategory::ALL.to_vec()
+        } else {
+            params
+                .bookmark_categories
+                .iter()
+                .map(|category| match category {
+                    thrift::BookmarkCategory::BRANCH => Ok(BookmarkCategory::Branch),
+                    thrift::BookmarkCategory::TAG => Ok(BookmarkCategory::Tag),
+                    thrift::BookmarkCategory::NOTE => Ok(BookmarkCategory::Note),
+                    other => Err(MononokeError::InvalidRequest(format!(
+                        "invalid bookmark category {:?}",
+                        other
+                    ))),
+                })
+                .collect::<Result<Vec<_>, MononokeError>>()?
+        };
         let (repo, changeset) = self.repo_changeset(ctx, &commit).await?;
         let bookmarks = repo
             .list_bookmarks(
@@ -868,6 +2152,7 @@ impl SourceControlServiceImpl {
                 prefix.as_deref(),
                 params.after.as_deref(),
                 limit,
+                &categories,
             )
             .await?
             .try_collect::<Vec<_>>()
@@ -913,8 +2198,51 @@ impl SourceControlServiceImpl {
             })
             .await?;
 
-        let ids = bookmarks.iter().map(|(_name, cs_id)| *cs_id).collect();
-        let id_mapping = map_commit_identities(&repo, ids, &params.identity_schemes).await?;
+        let ids: Vec<ChangesetId> = bookmarks.iter().map(|(_name, cs_id)| *cs_id).collect();
+        let id_mapping = map_commit_identities(&repo, ids.clone(), &params.identity_schemes).await?;
+
+        let commit_infos = if params.include_commit_info.unwrap_or(false) {
+            // Batch the `CommitInfo` lookups for every distinct commit pointed
+            // at by the returned bookmarks, rather than fetching one commit at
+            // a time per bookmark.
+            let distinct_ids: Vec<ChangesetId> = ids.iter().copied().unique().collect();
+            let changesets: Vec<ChangesetContext> = try_join_all(
+                distinct_ids
+                    .iter()
+                    .map(|cs_id| repo.changeset(ChangesetSpecifier::Bonsai(*cs_id))),
+            )
+            .await?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| errors::internal_error("bookmarked commit is missing").into())?;
+
+            let commit_info_additional = (
+                params.identity_schemes.clone(),
+                Some(btreeset! {thrift::CommitInfoField::MESSAGE}),
+            );
+            let commit_infos_by_id: HashMap<ChangesetId, thrift::CommitInfo> = distinct_ids
+                .into_iter()
+                .zip(
+                    changesets
+                        .into_response_with(&commit_info_additional)
+                        .await?,
+                )
+                .collect();
+
+            Some(
+                bookmarks
+                    .iter()
+                    .map(|(name, cs_id)| {
+                        (
+                            name.clone(),
+                            commit_infos_by_id.get(cs_id).cloned().unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
 
         let bookmarks = bookmarks
             .into_iter()
@@ -924,6 +2252,7 @@ impl SourceControlServiceImpl {
         Ok(thrift::CommitListDescendantBookmarksResponse {
             bookmarks,
             continue_after,
+            commit_infos,
             ..Default::default()
         })
     }
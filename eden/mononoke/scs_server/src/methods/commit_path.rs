@@ -15,13 +15,18 @@ use bytes::Bytes;
 use context::CoreContext;
 use dedupmap::DedupMap;
 use futures::future;
+use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use futures::try_join;
 use maplit::btreeset;
+use mononoke_api::ChangesetContext;
 use mononoke_api::ChangesetPathHistoryOptions;
+use mononoke_api::ChangesetSpecifier;
 use mononoke_api::MononokeError;
 use mononoke_api::PathEntry;
+use mononoke_api::RepoContext;
 use mononoke_types::path::MPath;
+use regex::Regex;
 use source_control as thrift;
 
 use crate::commit_id::map_commit_identities;
@@ -32,6 +37,7 @@ use crate::from_request::validate_timestamp;
 use crate::history::collect_history;
 use crate::into_response::IntoResponse;
 use crate::source_control_impl::SourceControlServiceImpl;
+use crate::specifiers::SpecifierExt;
 
 const BLAME_TITLE_MAX_LENGTH: usize = 128;
 
@@ -109,6 +115,83 @@ impl SourceControlServiceImpl {
         Ok(response)
     }
 
+    /// Get the content of the file at a path in a commit, optionally restricted to a
+    /// byte range. Reuses the same `ChangesetContext` path resolution the diff code
+    /// uses, so callers don't need to abuse `commit_file_diffs` against an empty base
+    /// just to fetch a file's bytes.
+    pub(crate) async fn commit_path_content(
+        &self,
+        ctx: CoreContext,
+        commit_path: thrift::CommitPathSpecifier,
+        params: thrift::CommitPathContentParams,
+    ) -> Result<thrift::CommitPathContentResponse, errors::ServiceError> {
+        let (_repo, changeset) = self.repo_changeset(ctx, &commit_path.commit).await?;
+        let path = changeset.path_with_content(&commit_path.path).await?;
+        let file = match path.entry().await? {
+            PathEntry::File(file, _file_type) => file,
+            PathEntry::NotPresent | PathEntry::Tree(_) => {
+                return Err(errors::file_not_found(commit_path.description()).into());
+            }
+        };
+        let offset: u64 = check_range_and_convert("offset", params.offset.unwrap_or(0), 0..)?;
+        let size: u64 = check_range_and_convert(
+            "size",
+            params
+                .size
+                .unwrap_or(thrift::consts::COMMIT_PATH_CONTENT_SIZE_LIMIT),
+            0..=thrift::consts::COMMIT_PATH_CONTENT_SIZE_LIMIT,
+        )?;
+        let metadata = file.metadata().await?;
+        let data = file.content_range_concat(offset, size).await?;
+        Ok(thrift::CommitPathContentResponse {
+            offset: offset as i64,
+            file_size: metadata.total_size as i64,
+            data: Vec::from(data.as_ref()),
+            ..Default::default()
+        })
+    }
+
+    /// Determine whether multiple paths exist and what type they are, resolved in a
+    /// single batched manifest walk.
+    pub(crate) async fn commit_multiple_path_exists(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitMultiplePathExistsParams,
+    ) -> Result<thrift::CommitMultiplePathExistsResponse, errors::ServiceError> {
+        let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let mut paths = vec![];
+        for path in params.paths {
+            let strpath = path.as_str();
+            let mpath = MPath::try_from(strpath)
+                .map_err(|error| MononokeError::InvalidRequest(error.to_string()))?;
+            paths.push(mpath);
+        }
+
+        let path_exists = changeset
+            .paths_with_content(paths.into_iter())
+            .await?
+            .map_ok(|context| async move {
+                let context_path = context.path().to_string();
+                let exists_elem = thrift::CommitPathExistsResponse {
+                    exists: context.exists().await?,
+                    file_exists: context.is_file().await?,
+                    tree_exists: context.is_tree().await?,
+                    ..Default::default()
+                };
+                Result::<_, errors::ServiceError>::Ok((context_path, exists_elem))
+            })
+            .map_err(errors::ServiceError::from)
+            .try_buffer_unordered(100)
+            .try_collect::<BTreeMap<_, _>>()
+            .await?;
+
+        Ok(thrift::CommitMultiplePathExistsResponse {
+            path_exists,
+            ..Default::default()
+        })
+    }
+
     pub(crate) async fn commit_multiple_path_info(
         &self,
         ctx: CoreContext,
@@ -485,6 +568,29 @@ impl SourceControlServiceImpl {
             .into());
         }
 
+        let content_grep = params
+            .content_grep
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| errors::invalid_request(format!("invalid content_grep regex: {e}")))?;
+
+        let max_commits_examined: Option<usize> = match &content_grep {
+            Some(_) => {
+                let max_commits_examined = params.max_commits_examined.ok_or_else(|| {
+                    errors::invalid_request(
+                        "max_commits_examined is required when content_grep is set",
+                    )
+                })?;
+                Some(check_range_and_convert(
+                    "max_commits_examined",
+                    max_commits_examined,
+                    1..,
+                )?)
+            }
+            None => None,
+        };
+
         let history_stream = path
             .history(ChangesetPathHistoryOptions {
                 until_timestamp: after_timestamp.clone(),
@@ -494,6 +600,29 @@ impl SourceControlServiceImpl {
                 follow_mutable_file_history: params.follow_mutable_file_history.unwrap_or(false),
             })
             .await?;
+
+        let history_stream = match content_grep {
+            Some(regex) => {
+                let repo = repo.clone();
+                let grep_path = commit_path.path.clone();
+                history_stream
+                    .take(max_commits_examined.expect("validated above"))
+                    .try_filter_map(move |changeset| {
+                        let repo = repo.clone();
+                        let grep_path = grep_path.clone();
+                        let regex = regex.clone();
+                        async move {
+                            let matches =
+                                changeset_content_grep_matches(&repo, &changeset, &grep_path, &regex)
+                                    .await?;
+                            Ok::<_, MononokeError>(matches.then_some(changeset))
+                        }
+                    })
+                    .left_stream()
+            }
+            None => history_stream.right_stream(),
+        };
+
         let history = collect_history(
             history_stream,
             skip,
@@ -502,6 +631,8 @@ impl SourceControlServiceImpl {
             after_timestamp,
             params.format,
             &params.identity_schemes,
+            &None,
+            false,
         )
         .await?;
 
@@ -642,3 +773,43 @@ impl SourceControlServiceImpl {
         })
     }
 }
+
+/// For `commit_path_history`'s `content_grep`: does `changeset`'s version of `path` add
+/// or remove a line matching `regex`, compared to `path`'s content in `changeset`'s first
+/// parent? A changeset with no first parent (a root commit) or where `path` doesn't exist
+/// on one or both sides is compared against an empty file on the missing side, same as a
+/// normal added/removed-file diff.
+async fn changeset_content_grep_matches(
+    repo: &RepoContext,
+    changeset: &ChangesetContext,
+    path: &thrift::Path,
+    regex: &Regex,
+) -> Result<bool, MononokeError> {
+    let new_content = changeset.path_with_content(path).await?.file_content().await?;
+
+    let parent_id = changeset.parents().await?.first().copied();
+    let old_content = match parent_id {
+        Some(parent_id) => {
+            let parent = repo
+                .changeset(ChangesetSpecifier::Bonsai(parent_id))
+                .await?
+                .ok_or_else(|| MononokeError::from(anyhow::anyhow!("parent changeset is missing")))?;
+            parent.path_with_content(path).await?.file_content().await?
+        }
+        None => None,
+    };
+
+    let lines_of = |content: &Option<Bytes>| -> HashSet<Vec<u8>> {
+        content
+            .as_ref()
+            .map(|bytes| bytes.split(|&b| b == b'\n').map(|line| line.to_vec()).collect())
+            .unwrap_or_default()
+    };
+    let new_lines = lines_of(&new_content);
+    let old_lines = lines_of(&old_content);
+
+    let line_matches = |line: &[u8]| regex.is_match(&String::from_utf8_lossy(line));
+
+    Ok(new_lines.difference(&old_lines).any(|line| line_matches(line))
+        || old_lines.difference(&new_lines).any(|line| line_matches(line)))
+}
@@ -7,6 +7,7 @@
 
 use std::collections::BTreeMap;
 
+use bookmarks::BookmarkCategory;
 use bookmarks::BookmarkKey;
 use bytes::Bytes;
 use context::CoreContext;
@@ -48,11 +49,15 @@ use crate::errors::ServiceErrorResultExt;
 use crate::from_request::check_range_and_convert;
 use crate::from_request::convert_pushvars;
 use crate::from_request::FromRequest;
+use crate::history::collect_history;
 use crate::into_response::AsyncIntoResponseWith;
 use crate::source_control_impl::SourceControlServiceImpl;
 
 mod land_stack;
 
+/// Bound on how many commits `repo_commits_info` resolves concurrently.
+const COMMITS_INFO_CONCURRENCY_LIMIT: usize = 100;
+
 impl SourceControlServiceImpl {
     /// Detailed repo info.
     ///
@@ -119,6 +124,33 @@ impl SourceControlServiceImpl {
         }
     }
 
+    /// Resolve multiple bookmarks to changesets in a single call.
+    ///
+    /// Returns the IDs of each bookmark's changeset in the requested identity
+    /// schemes. Backed by a single batched bookmark listing rather than a
+    /// lookup per bookmark. Bookmarks that don't exist are omitted from the
+    /// result.
+    pub(crate) async fn repo_resolve_bookmarks(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoResolveBookmarksParams,
+    ) -> Result<thrift::RepoResolveBookmarksResponse, errors::ServiceError> {
+        let repo = self.repo(ctx, &repo).await?;
+        let resolved = repo.resolve_bookmarks(&params.bookmark_names).await?;
+        let ids = try_join_all(resolved.into_iter().map(|(name, cs)| async move {
+            let ids = map_commit_identity(&cs, &params.identity_schemes).await?;
+            Ok::<_, errors::ServiceError>((name, ids))
+        }))
+        .await?
+        .into_iter()
+        .collect();
+        Ok(thrift::RepoResolveBookmarksResponse {
+            ids,
+            ..Default::default()
+        })
+    }
+
     /// Resolve a prefix and its identity scheme to a changeset.
     ///
     /// Returns the IDs of the changeset in the requested identity schemes.
@@ -241,6 +273,7 @@ impl SourceControlServiceImpl {
                 prefix.as_deref(),
                 params.after.as_deref(),
                 limit,
+                BookmarkCategory::ALL,
             )
             .await?
             .try_collect::<Vec<_>>()
@@ -267,7 +300,88 @@ impl SourceControlServiceImpl {
         })
     }
 
-    async fn convert_create_commit_parents(
+    /// List all public refs (branches and tags), with their kind and target commit.
+    pub(crate) async fn repo_list_all_refs(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoListAllRefsParams,
+    ) -> Result<thrift::RepoListAllRefsResponse, errors::ServiceError> {
+        let limit = match check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=source_control::REPO_LIST_ALL_REFS_MAX_LIMIT,
+        )? {
+            0 => None,
+            limit => Some(limit),
+        };
+        let prefix = if !params.ref_prefix.is_empty() {
+            Some(params.ref_prefix)
+        } else {
+            None
+        };
+        let repo = self.repo(ctx, &repo).await?;
+        let (refs, continue_after) = repo
+            .list_all_refs(prefix.as_deref(), params.after.as_deref(), limit)
+            .await?;
+        let ids = refs.iter().map(|r| r.changeset.id()).collect();
+        let id_mapping = map_commit_identities(&repo, ids, &params.identity_schemes).await?;
+        let refs = refs
+            .into_iter()
+            .map(|r| {
+                let target = id_mapping.get(&r.changeset.id()).cloned().unwrap_or_default();
+                let peeled_target = r.is_annotated_tag.then(|| target.clone());
+                thrift::Ref {
+                    name: r.name,
+                    kind: if r.is_tag {
+                        thrift::RefKind::TAG
+                    } else {
+                        thrift::RefKind::BRANCH
+                    },
+                    is_annotated_tag: r.is_annotated_tag,
+                    target,
+                    peeled_target,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        Ok(thrift::RepoListAllRefsResponse {
+            refs,
+            continue_after,
+            ..Default::default()
+        })
+    }
+
+    /// Get the sequence of commits a bookmark has pointed to over time.
+    pub(crate) async fn repo_bookmark_history(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoBookmarkHistoryParams,
+    ) -> Result<thrift::RepoBookmarkHistoryResponse, errors::ServiceError> {
+        let limit: u64 = check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=source_control::REPO_BOOKMARK_HISTORY_MAX_LIMIT,
+        )?;
+        let skip: u64 = check_range_and_convert("skip", params.skip, 0..)?;
+        let repo = self.repo(ctx, &repo).await?;
+        let entries = repo
+            .bookmark_history(params.bookmark_name, limit, skip)
+            .await?;
+        let history = try_join_all(
+            entries
+                .into_iter()
+                .map(|entry| entry.into_response_with(&params.identity_schemes)),
+        )
+        .await?;
+        Ok(thrift::RepoBookmarkHistoryResponse {
+            history,
+            ..Default::default()
+        })
+    }
+
+    pub(crate) async fn convert_create_commit_parents(
         repo: &RepoContext,
         parents: &[thrift::CommitId],
     ) -> Result<Vec<ChangesetId>, errors::ServiceError> {
@@ -296,7 +410,7 @@ impl SourceControlServiceImpl {
         Ok(parents)
     }
 
-    async fn convert_create_commit_change(
+    pub(crate) async fn convert_create_commit_change(
         repo: &RepoContext,
         change: thrift::RepoCreateCommitParamsChange,
     ) -> Result<CreateChange, errors::ServiceError> {
@@ -397,7 +511,7 @@ impl SourceControlServiceImpl {
         Ok(change)
     }
 
-    async fn convert_create_commit_changes(
+    pub(crate) async fn convert_create_commit_changes(
         repo: &RepoContext,
         changes: BTreeMap<String, thrift::RepoCreateCommitParamsChange>,
     ) -> Result<BTreeMap<MPath, CreateChange>, errors::ServiceError> {
@@ -603,17 +717,10 @@ impl SourceControlServiceImpl {
             leftover_heads.into_iter().collect::<Option<Vec<_>>>(),
         ) {
             (Some(draft_commits), Some(public_parents), Some(leftover_heads)) => {
+                let commit_info_additional = (params.identity_schemes.clone(), None);
                 let (mut draft_commits, public_parents, leftover_heads) = try_join!(
-                    try_join_all(
-                        draft_commits
-                            .into_iter()
-                            .map(|cs| cs.into_response_with(&params.identity_schemes)),
-                    ),
-                    try_join_all(
-                        public_parents
-                            .into_iter()
-                            .map(|cs| cs.into_response_with(&params.identity_schemes)),
-                    ),
+                    draft_commits.into_response_with(&commit_info_additional),
+                    public_parents.into_response_with(&commit_info_additional),
                     leftover_heads.into_response_with(&params.identity_schemes),
                 )?;
 
@@ -635,6 +742,93 @@ impl SourceControlServiceImpl {
         }
     }
 
+    /// List the changesets in the difference of ancestors of `heads` minus
+    /// ancestors of `bases`, the multi-head, multi-base generalization of
+    /// `commit_history`'s `descendants_of`/`exclude_changeset_and_ancestors`.
+    pub(crate) async fn repo_commits_in_range(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoCommitsInRangeParams,
+    ) -> Result<thrift::RepoCommitsInRangeResponse, errors::ServiceError> {
+        let repo = self.repo(ctx, &repo).await?;
+
+        let limit: usize = check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=thrift::consts::REPO_COMMITS_IN_RANGE_MAX_LIMIT,
+        )?;
+        let skip: usize = check_range_and_convert("skip", params.skip, 0..)?;
+
+        let (heads, bases) = try_join!(
+            try_join_all(params.heads.iter().map(|id| self.changeset_id(&repo, id))),
+            try_join_all(params.bases.iter().map(|id| self.changeset_id(&repo, id))),
+        )?;
+
+        let history_stream = repo.difference_of_unions_of_ancestors(heads, bases).await?;
+
+        let history = collect_history(
+            history_stream,
+            skip,
+            limit,
+            None,
+            None,
+            params.format,
+            &params.identity_schemes,
+            &params.fields,
+            false,
+        )
+        .await?;
+
+        Ok(thrift::RepoCommitsInRangeResponse {
+            history,
+            ..Default::default()
+        })
+    }
+
+    /// Batched form of `commit_info`: resolve every id in `params.commit_ids`
+    /// concurrently (bounded by `COMMITS_INFO_CONCURRENCY_LIMIT`), then map
+    /// the resolved changesets to `CommitInfo` with a single batched
+    /// identity-scheme lookup instead of one per commit.
+    pub(crate) async fn repo_commits_info(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoCommitsInfoParams,
+    ) -> Result<thrift::RepoCommitsInfoResponse, errors::ServiceError> {
+        let repo = self.repo(ctx, &repo).await?;
+
+        let specifiers = params
+            .commit_ids
+            .iter()
+            .map(ChangesetSpecifier::from_request)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let changesets = stream::iter(
+            specifiers
+                .into_iter()
+                .map(|specifier| repo.changeset(specifier)),
+        )
+        .buffered(COMMITS_INFO_CONCURRENCY_LIMIT)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+        let (found_ids, found_changesets): (Vec<_>, Vec<_>) =
+            std::iter::zip(params.commit_ids, changesets)
+                .filter_map(|(id, changeset)| changeset.map(|changeset| (id, changeset)))
+                .unzip();
+
+        let commit_info_additional = (params.identity_schemes.clone(), params.fields.clone());
+        let commit_infos = found_changesets
+            .into_response_with(&commit_info_additional)
+            .await?;
+
+        Ok(thrift::RepoCommitsInfoResponse {
+            commit_infos: std::iter::zip(found_ids, commit_infos).collect(),
+            ..Default::default()
+        })
+    }
+
     pub(crate) async fn repo_create_bookmark(
         &self,
         ctx: CoreContext,
@@ -5,13 +5,80 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::BTreeSet;
+
 use context::CoreContext;
+use maplit::btreeset;
+use mononoke_api::TreeDiffEntry;
 use source_control as thrift;
 
 use crate::errors;
 use crate::from_request::check_range_and_convert;
 use crate::into_response::IntoResponse;
 use crate::source_control_impl::SourceControlServiceImpl;
+use crate::specifiers::SpecifierExt;
+
+/// Resolve the requested `compare_items` into the set of items to actually compare in a
+/// tree diff: only `FILES` and `TREES` are meaningful here (a tree diff has no commits to
+/// attach git submodule changes to), so anything else is dropped, and `FILES` is used as
+/// the default when nothing recognized remains. Mirrors `commit_compare`'s resolution of
+/// `compare_items` in `commit.rs`.
+fn resolve_tree_compare_items(
+    compare_items: Vec<thrift::CommitCompareItem>,
+) -> BTreeSet<thrift::CommitCompareItem> {
+    let mut compare_items: BTreeSet<_> = compare_items
+        .into_iter()
+        .filter_map(|item| match item {
+            thrift::CommitCompareItem::FILES => Some(thrift::CommitCompareItem::FILES),
+            thrift::CommitCompareItem::TREES => Some(thrift::CommitCompareItem::TREES),
+            _ => None,
+        })
+        .collect();
+    if compare_items.is_empty() {
+        compare_items = btreeset! { thrift::CommitCompareItem::FILES };
+    }
+    compare_items
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_tree_compare_items_keeps_both_files_and_trees() {
+        assert_eq!(
+            resolve_tree_compare_items(vec![
+                thrift::CommitCompareItem::FILES,
+                thrift::CommitCompareItem::TREES,
+            ]),
+            btreeset! { thrift::CommitCompareItem::FILES, thrift::CommitCompareItem::TREES },
+        );
+    }
+
+    #[test]
+    fn resolve_tree_compare_items_trees_only_stays_trees_only() {
+        assert_eq!(
+            resolve_tree_compare_items(vec![thrift::CommitCompareItem::TREES]),
+            btreeset! { thrift::CommitCompareItem::TREES },
+        );
+    }
+
+    #[test]
+    fn resolve_tree_compare_items_defaults_to_files_when_empty() {
+        assert_eq!(
+            resolve_tree_compare_items(vec![]),
+            btreeset! { thrift::CommitCompareItem::FILES },
+        );
+    }
+
+    #[test]
+    fn resolve_tree_compare_items_defaults_to_files_for_unrecognized_values() {
+        assert_eq!(
+            resolve_tree_compare_items(vec![thrift::CommitCompareItem::GIT_SUBMODULES]),
+            btreeset! { thrift::CommitCompareItem::FILES },
+        );
+    }
+}
 
 impl SourceControlServiceImpl {
     /// Determine whether a tree exists.
@@ -63,4 +130,134 @@ impl SourceControlServiceImpl {
             })
         }
     }
+
+    /// Compare two arbitrary trees directly, without synthesizing a commit
+    /// for either side.
+    pub(crate) async fn tree_compare(
+        &self,
+        ctx: CoreContext,
+        tree: thrift::TreeSpecifier,
+        params: thrift::TreeCompareParams,
+    ) -> Result<thrift::TreeCompareResponse, errors::ServiceError> {
+        let (_repo, base_tree) = self.repo_tree(ctx.clone(), &tree).await?;
+        let base_tree = base_tree.ok_or_else(|| errors::tree_not_found(tree.description()))?;
+        let (_other_repo, other_tree) = self.repo_tree(ctx, &params.other_tree).await?;
+        let other_tree = other_tree
+            .ok_or_else(|| errors::tree_not_found(params.other_tree.description()))?;
+
+        let compare_items = resolve_tree_compare_items(params.compare_items);
+
+        let diff = base_tree.diff(&other_tree).await?;
+
+        let mut diff_files = Vec::new();
+        let mut diff_trees = Vec::new();
+        for entry in diff {
+            match entry {
+                TreeDiffEntry::AddedFile(path, file)
+                    if compare_items.contains(&thrift::CommitCompareItem::FILES) =>
+                {
+                    diff_files.push(thrift::CommitCompareFile {
+                        base_file: Some(thrift::FilePathInfo {
+                            path: path.to_string(),
+                            r#type: (*file.file_type()).into_response(),
+                            info: file.into_response(),
+                            ..Default::default()
+                        }),
+                        other_file: None,
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                TreeDiffEntry::RemovedFile(path, file)
+                    if compare_items.contains(&thrift::CommitCompareItem::FILES) =>
+                {
+                    diff_files.push(thrift::CommitCompareFile {
+                        base_file: None,
+                        other_file: Some(thrift::FilePathInfo {
+                            path: path.to_string(),
+                            r#type: (*file.file_type()).into_response(),
+                            info: file.into_response(),
+                            ..Default::default()
+                        }),
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                TreeDiffEntry::ChangedFile(path, base_file, other_file)
+                    if compare_items.contains(&thrift::CommitCompareItem::FILES) =>
+                {
+                    diff_files.push(thrift::CommitCompareFile {
+                        base_file: Some(thrift::FilePathInfo {
+                            path: path.to_string(),
+                            r#type: (*base_file.file_type()).into_response(),
+                            info: base_file.into_response(),
+                            ..Default::default()
+                        }),
+                        other_file: Some(thrift::FilePathInfo {
+                            path: path.to_string(),
+                            r#type: (*other_file.file_type()).into_response(),
+                            info: other_file.into_response(),
+                            ..Default::default()
+                        }),
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                TreeDiffEntry::AddedTree(path, id, summary)
+                    if compare_items.contains(&thrift::CommitCompareItem::TREES) =>
+                {
+                    diff_trees.push(thrift::CommitCompareTree {
+                        base_tree: Some(thrift::TreePathInfo {
+                            path: path.to_string(),
+                            info: (id, summary).into_response(),
+                            ..Default::default()
+                        }),
+                        other_tree: None,
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                TreeDiffEntry::RemovedTree(path, id, summary)
+                    if compare_items.contains(&thrift::CommitCompareItem::TREES) =>
+                {
+                    diff_trees.push(thrift::CommitCompareTree {
+                        base_tree: None,
+                        other_tree: Some(thrift::TreePathInfo {
+                            path: path.to_string(),
+                            info: (id, summary).into_response(),
+                            ..Default::default()
+                        }),
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                TreeDiffEntry::ChangedTree(path, base_id, base_summary, other_id, other_summary)
+                    if compare_items.contains(&thrift::CommitCompareItem::TREES) =>
+                {
+                    diff_trees.push(thrift::CommitCompareTree {
+                        base_tree: Some(thrift::TreePathInfo {
+                            path: path.to_string(),
+                            info: (base_id, base_summary).into_response(),
+                            ..Default::default()
+                        }),
+                        other_tree: Some(thrift::TreePathInfo {
+                            path: path.to_string(),
+                            info: (other_id, other_summary).into_response(),
+                            ..Default::default()
+                        }),
+                        copy_info: thrift::CopyInfo::NONE,
+                        ..Default::default()
+                    });
+                }
+                // Filtered out by `compare_items`.
+                _ => {}
+            }
+        }
+
+        Ok(thrift::TreeCompareResponse {
+            diff_files,
+            diff_trees,
+            ..Default::default()
+        })
+    }
 }
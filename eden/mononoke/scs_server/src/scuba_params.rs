@@ -180,6 +180,17 @@ impl AddScubaParams for thrift::RepoListBookmarksParams {
     }
 }
 
+impl AddScubaParams for thrift::RepoListAllRefsParams {
+    fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
+        scuba.add("param_ref_prefix", self.ref_prefix.as_str());
+        scuba.add("param_limit", self.limit);
+        if let Some(after) = &self.after {
+            scuba.add("param_after", after.as_str());
+        }
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
 impl AddScubaParams for thrift::RepoResolveBookmarkParams {
     fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
         scuba.add("bookmark_name", self.bookmark_name.as_str());
@@ -218,6 +229,46 @@ impl AddScubaParams for thrift::RepoStackInfoParams {
     }
 }
 
+impl AddScubaParams for thrift::RepoCommitsInRangeParams {
+    fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
+        scuba.add(
+            "param_first_n_heads",
+            self.heads
+                .iter()
+                .take(COMMIT_LIMIT)
+                .map(CommitIdExt::to_string)
+                .collect::<ScubaValue>(),
+        );
+        scuba.add(
+            "param_first_n_bases",
+            self.bases
+                .iter()
+                .take(COMMIT_LIMIT)
+                .map(CommitIdExt::to_string)
+                .collect::<ScubaValue>(),
+        );
+        scuba.add("param_format", self.format.to_string());
+        scuba.add("param_skip", self.skip);
+        scuba.add("param_limit", self.limit);
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
+impl AddScubaParams for thrift::RepoCommitsInfoParams {
+    fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
+        scuba.add(
+            "param_first_n_commits",
+            self.commit_ids
+                .iter()
+                .take(COMMIT_LIMIT)
+                .map(CommitIdExt::to_string)
+                .collect::<ScubaValue>(),
+        );
+        scuba.add("param_commit_count", self.commit_ids.len());
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
 impl AddScubaParams for thrift::RepoPrepareCommitsParams {
     fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
         scuba.add(
@@ -261,6 +312,16 @@ impl AddScubaParams for thrift::CommitCompareParams {
     }
 }
 
+impl AddScubaParams for thrift::CommitCompareOverlayParams {
+    fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
+        scuba.add("param_overlay_paths_count", self.overlay.len());
+        if let Some(paths) = &self.paths {
+            scuba.add("param_paths", paths.iter().collect::<ScubaValue>());
+        }
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
 impl AddScubaParams for thrift::CommitFileDiffsParams {
     fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
         scuba.add(
@@ -284,6 +345,12 @@ impl AddScubaParams for thrift::CommitFindFilesParams {
         if let Some(prefixes) = &self.prefixes {
             scuba.add("param_prefixes", prefixes.iter().collect::<ScubaValue>());
         }
+        if let Some(exclude_prefixes) = &self.exclude_prefixes {
+            scuba.add(
+                "param_exclude_prefixes",
+                exclude_prefixes.iter().collect::<ScubaValue>(),
+            );
+        }
         if let Some(after) = &self.after {
             scuba.add("param_after", after.as_str());
         }
@@ -411,6 +478,17 @@ impl AddScubaParams for thrift::CommitPathExistsParams {}
 
 impl AddScubaParams for thrift::CommitPathInfoParams {}
 
+impl AddScubaParams for thrift::CommitPathContentParams {
+    fn add_scuba_params(&self, scuba: &mut MononokeScubaSampleBuilder) {
+        if let Some(offset) = self.offset {
+            scuba.add("param_offset", offset);
+        }
+        if let Some(size) = self.size {
+            scuba.add("param_size", size);
+        }
+    }
+}
+
 impl AddScubaParams for thrift::RepoInfoParams {}
 
 impl AddScubaParams for thrift::CommitMultiplePathInfoParams {
@@ -10,6 +10,7 @@ use std::future::Future;
 use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clientinfo::ClientEntryPoint;
 use clientinfo::ClientInfo;
@@ -103,6 +104,7 @@ pub(crate) struct SourceControlServiceImpl {
     pub(crate) identity: Identity,
     pub(crate) scribe: Scribe,
     identity_proxy_checker: Arc<ConnectionSecurityChecker>,
+    long_request_timeout: Duration,
 }
 
 pub(crate) struct SourceControlServiceThriftImpl(SourceControlServiceImpl);
@@ -117,6 +119,7 @@ impl SourceControlServiceImpl {
         scribe: Scribe,
         identity_proxy_checker: ConnectionSecurityChecker,
         common_config: &CommonConfig,
+        long_request_timeout: Duration,
     ) -> Self {
         scuba_builder.add_common_server_data();
 
@@ -132,6 +135,7 @@ impl SourceControlServiceImpl {
             ),
             scribe,
             identity_proxy_checker: Arc::new(identity_proxy_checker),
+            long_request_timeout,
         }
     }
 
@@ -630,6 +634,25 @@ fn log_cancelled(ctx: &CoreContext, stats: &FutureStats) {
     scuba.log_with_msg("Request cancelled", None);
 }
 
+// Commit methods whose cost scales with the size of the commit range or
+// directory tree being examined, and which are therefore bounded by
+// `SourceControlServiceImpl::long_request_timeout` rather than being allowed
+// to run indefinitely.
+const LONG_RUNNING_COMMIT_METHODS: &[&str] = &[
+    "commit_compare",
+    "commit_compare_overlay",
+    "commit_find_files",
+    "commit_history",
+    "commit_path_blame",
+    "commit_path_history",
+    "commit_sparse_profile_delta",
+    "commit_sparse_profile_size",
+];
+
+fn is_long_running_commit_method(method: &str) -> bool {
+    LONG_RUNNING_COMMIT_METHODS.contains(&method)
+}
+
 // Define a macro to construct a CoreContext based on the thrift parameters.
 macro_rules! create_ctx {
     ( $service_impl:expr, $method_name:ident, $req_ctxt:ident, $params_name:ident ) => {
@@ -667,11 +690,24 @@ macro_rules! impl_thrift_methods {
                     let ctx = create_ctx!(self.0, $method_name, req_ctxt, $( $param_name ),*).await?;
                     ctx.scuba().clone().log_with_msg("Request start", None);
                     STATS::total_request_start.add_value(1);
-                    let (stats, res) = (self.0)
-                        .$method_name(ctx.clone(), $( $param_name ),* )
+                    let inner = (self.0).$method_name(ctx.clone(), $( $param_name ),* );
+                    let (stats, res) = if is_long_running_commit_method(stringify!($method_name)) {
+                        let timeout = self.0.long_request_timeout;
+                        async move {
+                            match tokio::time::timeout(timeout, inner).await {
+                                Ok(res) => res,
+                                Err(_) => Err(errors::request_timed_out(timeout).into()),
+                            }
+                        }
                         .timed()
                         .on_cancel_with_data(|stats| log_cancelled(&ctx, &stats))
-                        .await;
+                        .await
+                    } else {
+                        inner
+                            .timed()
+                            .on_cancel_with_data(|stats| log_cancelled(&ctx, &stats))
+                            .await
+                    };
                     log_result(ctx, &stats, &res);
                     let method = stringify!($method_name).to_string();
                     STATS::method_completion_time_ms.add_value(stats.completion_time.as_millis_unchecked() as i64, (method,));
@@ -701,6 +737,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::RepoResolveBookmarkParams,
         ) -> Result<thrift::RepoResolveBookmarkResponse, service::RepoResolveBookmarkExn>;
 
+        async fn repo_resolve_bookmarks(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoResolveBookmarksParams,
+        ) -> Result<thrift::RepoResolveBookmarksResponse, service::RepoResolveBookmarksExn>;
+
         async fn repo_resolve_commit_prefix(
             repo: thrift::RepoSpecifier,
             params: thrift::RepoResolveCommitPrefixParams,
@@ -711,6 +752,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::RepoListBookmarksParams,
         ) -> Result<thrift::RepoListBookmarksResponse, service::RepoListBookmarksExn>;
 
+        async fn repo_list_all_refs(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoListAllRefsParams,
+        ) -> Result<thrift::RepoListAllRefsResponse, service::RepoListAllRefsExn>;
+
         async fn commit_common_base_with(
             commit: thrift::CommitSpecifier,
             params: thrift::CommitCommonBaseWithParams,
@@ -746,6 +792,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::CommitCompareParams,
         ) -> Result<thrift::CommitCompareResponse, service::CommitCompareExn>;
 
+        async fn commit_compare_overlay(
+            commit: thrift::CommitSpecifier,
+            params: thrift::CommitCompareOverlayParams,
+        ) -> Result<thrift::CommitCompareResponse, service::CommitCompareOverlayExn>;
+
         async fn commit_find_files(
             commit: thrift::CommitSpecifier,
             params: thrift::CommitFindFilesParams,
@@ -776,6 +827,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::CommitPathExistsParams,
         ) -> Result<thrift::CommitPathExistsResponse, service::CommitPathExistsExn>;
 
+        async fn commit_multiple_path_exists(
+            commit: thrift::CommitSpecifier,
+            params: thrift::CommitMultiplePathExistsParams,
+        ) -> Result<thrift::CommitMultiplePathExistsResponse, service::CommitMultiplePathExistsExn>;
+
         async fn commit_path_info(
             commit_path: thrift::CommitPathSpecifier,
             params: thrift::CommitPathInfoParams,
@@ -786,6 +842,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::CommitMultiplePathInfoParams,
         ) -> Result<thrift::CommitMultiplePathInfoResponse, service::CommitMultiplePathInfoExn>;
 
+        async fn commit_path_content(
+            commit_path: thrift::CommitPathSpecifier,
+            params: thrift::CommitPathContentParams,
+        ) -> Result<thrift::CommitPathContentResponse, service::CommitPathContentExn>;
+
         async fn commit_path_blame(
             commit_path: thrift::CommitPathSpecifier,
             params: thrift::CommitPathBlameParams,
@@ -861,11 +922,26 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::RepoBookmarkInfoParams,
         ) -> Result<thrift::RepoBookmarkInfoResponse, service::RepoBookmarkInfoExn>;
 
+        async fn repo_bookmark_history(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoBookmarkHistoryParams,
+        ) -> Result<thrift::RepoBookmarkHistoryResponse, service::RepoBookmarkHistoryExn>;
+
         async fn repo_stack_info(
             repo: thrift::RepoSpecifier,
             params: thrift::RepoStackInfoParams,
         ) -> Result<thrift::RepoStackInfoResponse, service::RepoStackInfoExn>;
 
+        async fn repo_commits_in_range(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoCommitsInRangeParams,
+        ) -> Result<thrift::RepoCommitsInRangeResponse, service::RepoCommitsInRangeExn>;
+
+        async fn repo_commits_info(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoCommitsInfoParams,
+        ) -> Result<thrift::RepoCommitsInfoResponse, service::RepoCommitsInfoExn>;
+
         async fn repo_create_bookmark(
             repo: thrift::RepoSpecifier,
             params: thrift::RepoCreateBookmarkParams,
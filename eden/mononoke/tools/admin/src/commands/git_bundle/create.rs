@@ -29,6 +29,7 @@ use packfile::bundle::BundleWriter;
 use packfile::pack::DeltaForm;
 use packfile::types::PackfileItem;
 use protocol::generator::generate_pack_item_stream;
+use protocol::types::DeltaBasePreference;
 use protocol::types::DeltaInclusion;
 use protocol::types::PackItemStreamRequest;
 use protocol::types::PackfileItemInclusion;
@@ -96,6 +97,11 @@ pub struct FromRepoArgs {
     /// Should the packfile items for base objects be generated on demand or fetched from store
     #[clap(long, default_value_t, value_enum)]
     packfile_item_inclusion: PackfileItemInclusion,
+    /// Don't include the HEAD symref in the generated bundle. Useful when the bundle is only
+    /// meant to be fetched into an existing repo (where ls-refs is used separately to resolve
+    /// HEAD) and the caller doesn't want the extra ref written into the bundle
+    #[clap(long)]
+    exclude_head_symref: bool,
 }
 
 /// Args for creating a Git bundle from an on-disk Git repo
@@ -152,6 +158,7 @@ pub async fn create_from_mononoke_repo(
         DeltaInclusion::Include {
             form,
             inclusion_threshold: 0.90,
+            base_preference: DeltaBasePreference::SmallestSize,
         }
     };
     // If references are specified without values, just take the ref names
@@ -164,8 +171,13 @@ pub async fn create_from_mononoke_repo(
         // Otherwise include all the refs known by the server
         RequestedRefs::all()
     };
+    let requested_symrefs = if create_args.exclude_head_symref {
+        RequestedSymrefs::ExcludeAll
+    } else {
+        RequestedSymrefs::IncludeHead(SymrefFormat::NameOnly)
+    };
     let request = PackItemStreamRequest::new(
-        RequestedSymrefs::IncludeHead(SymrefFormat::NameOnly),
+        requested_symrefs,
         requested_refs,
         create_args.have_heads.clone(),
         delta_inclusion,
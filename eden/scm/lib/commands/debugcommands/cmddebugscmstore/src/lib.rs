@@ -6,50 +6,206 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use async_runtime::block_on;
 use async_runtime::stream_to_iter as block_on_stream;
 use clidispatch::abort;
 use clidispatch::abort_if;
 use clidispatch::errors;
+use clidispatch::io::IOOutput;
 use clidispatch::ReqCtx;
 use cmdutil::define_flags;
 use cmdutil::Config;
 use cmdutil::Error;
 use cmdutil::Result;
 use cmdutil::IO;
+use dag::ops::IdConvert;
+use dag::Vertex;
+use edenapi_types::Blake3;
+use edenapi_types::Sha256;
 use manifest::FileMetadata;
 use manifest::FsNodeMetadata;
 use manifest::Manifest;
+use manifest_tree::Flag;
+use pathmatcher::TreeMatcher;
 use repo::repo::Repo;
 use revisionstore::scmstore::file_to_async_key_stream;
 use revisionstore::scmstore::FileAttributes;
 use revisionstore::scmstore::FileStoreBuilder;
+use revisionstore::scmstore::StoreFile;
+use revisionstore::scmstore::tree::types::StoreTree;
 use revisionstore::scmstore::TreeStoreBuilder;
+use revisionstore::HgIdHistoryStore;
+use revisionstore::MetadataStoreBuilder;
 use types::fetch_mode::FetchMode;
 use types::Key;
 use types::RepoPathBuf;
 
 define_flags! {
     pub struct DebugScmStoreOpts {
-        /// Fetch mode (file or tree)
+        /// Fetch mode (file, tree, history, or both)
         mode: String,
 
         /// Input file containing keys to fetch (hgid,path separated by newlines)
         requests_file: Option<String>,
 
+        /// When a --requests-file line fails to parse, log it (with its line number and
+        /// content) and skip it instead of aborting the whole run. Useful when the requests
+        /// file was generated programmatically and a single bad row shouldn't be fatal.
+        skip_bad_lines: bool,
+
         /// Only check for the entity locally, don't make a remote request
         local: bool,
 
+        /// Only fetch from remote stores, skipping the local cache
+        remote: bool,
+
+        /// Fetch each key from both the local cache and the remote store, and
+        /// report any key where the content differs between the two sources
+        /// (or is present in only one). Useful for catching cache corruption.
+        /// Only supported in 'file' mode, and cannot be combined with --local
+        /// or --remote.
+        compare_sources: bool,
+
         /// Only fetch AUX data (don't request file content).
         aux_only: bool,
 
+        /// Benchmark pure aux-data fetch throughput over the given key set,
+        /// splitting it across --bench-concurrency concurrent `fetch` calls and
+        /// reporting aux entries/sec and aux bytes/sec at the end. Unlike the
+        /// normal 'file' mode fetch, this never falls back to a content-only or
+        /// combined content+aux fetch for keys that come back missing, and skips
+        /// the --local aux-only diagnostic hints, so the reported numbers reflect
+        /// a single clean aux-only fetch pass. Requires --aux-only and 'file' mode.
+        aux_bench: bool,
+
+        /// Number of concurrent `fetch` calls to split the key set across when
+        /// using --aux-bench. Ignored without --aux-bench.
+        bench_concurrency: i64 = 1,
+
+        /// Verify the fetched file's content hash matches the given value, e.g.
+        /// "sha256:abcd..." or "blake3:abcd...". Only valid when fetching a single file.
+        content_hash: Option<String>,
+
         /// Revision for positional file paths.
         #[short('r')]
         #[argtype("REV")]
         rev: Option<String>,
 
+        /// Print the resolved keys (one `hgid,path` pair per line, matching the
+        /// --requests-file format) and exit without fetching anything. Useful for
+        /// confirming that --rev plus positional paths resolved to the keys you
+        /// expected, or for capturing them into a requests file for repeated runs.
+        dump_keys: bool,
+
+        /// Instead of positional file paths, walk this directory's subtree in the
+        /// manifest at --rev and enqueue every file found under it.
+        paths_from_manifest: Option<String>,
+
+        /// Maximum number of files to enqueue when using --paths-from-manifest.
+        max_files: i64 = 100_000,
+
+        /// Flush stdout every N fetched items. Lower this for large fetches so that
+        /// streaming consumers (e.g. `debugscmstore | head`) see output promptly. 0
+        /// disables periodic flushing (only flush once at the end). Ignored when
+        /// --sort is given, since sorted output can only be written once fetching
+        /// is complete.
+        flush_every: i64 = 200,
+
+        /// Sort the success and failure output deterministically by path then hgid
+        /// before printing, instead of printing in (nondeterministic) completion
+        /// order. This makes output from two runs over the same requests file
+        /// byte-identical, which is useful for diffing and golden tests.
+        sort: bool,
+
+        /// Abort as soon as a key fails with a non-retryable error, instead of
+        /// attempting every key and reporting all failures at the end. Useful for
+        /// fast feedback in CI. The default is to collect everything.
+        fail_fast: bool,
+
+        /// Number of times to retry a fetch that failed with what looks like a
+        /// transient network error, not counting the initial attempt. 0 disables
+        /// retrying. Ignored with --local, since a local-only fetch can't hit a
+        /// transient network error.
+        retries: i64 = 3,
+
+        /// Backoff, in milliseconds, before the first retry. Doubles after each
+        /// subsequent retry. Ignored without retries (see --retries).
+        retry_backoff: i64 = 200,
+
+        /// Suffix passed to `TreeStoreBuilder` when fetching trees, overriding the
+        /// default of "manifests". Used in 'tree' and 'both' modes. May be repeated to fetch
+        /// each key under every given suffix and report which ones had it, e.g. to
+        /// diagnose suffix-routing bugs between manifests and other tree namespaces.
+        tree_suffix: Vec<String>,
+
+        /// After fetching a tree, also enqueue and fetch its child trees (i.e. the
+        /// subdirectories it contains), down to --max-depth. This walks the manifest the
+        /// way a real checkout would, exercising the tree store's handling of a whole
+        /// subtree rather than just the keys given on the command line, and surfacing
+        /// missing intermediate trees that a single-level fetch wouldn't reveal. Only
+        /// supported in 'tree' mode with a single --tree-suffix.
+        recursive: bool,
+
+        /// Maximum number of additional levels of child trees to fetch when
+        /// --recursive is given. 0 means only fetch the given keys, same as without
+        /// --recursive. Ignored without --recursive.
+        max_depth: i64 = 100,
+
+        /// Print a structured description of the store layers and settings that
+        /// `FileStoreBuilder`/`TreeStoreBuilder` resolved from the repo config (cache
+        /// paths, edenapi usage, aux-data settings, etc.), then exit without fetching.
+        /// If keys are also given (via positional paths, --rev, or --requests-file),
+        /// the explanation is printed first and the fetch proceeds normally afterwards.
+        /// Only supported in 'file' and 'tree' modes.
+        explain: bool,
+
+        /// Emit the end-of-run aux-data completeness summary as JSON instead of
+        /// human-readable text. Only affects the summary printed after the
+        /// per-key success/failure lines; those lines are always text. Only
+        /// meaningful in 'file' mode.
+        json: bool,
+
+        /// Accumulate the content length of each successfully fetched file/tree
+        /// and print a throughput summary (total bytes, elapsed time, effective
+        /// MB/s) at the end. Only supported in 'file', 'tree', and 'both' modes.
+        stats: bool,
+
+        /// Only valid with 'mode both'. By default, the end-of-run summary for
+        /// 'both' mode breaks the found/missing tally down by type (files vs.
+        /// trees). Pass this to collapse it into one combined found/missing
+        /// tally plus combined byte total instead, so monitoring has a single
+        /// scalar to alert on. The detailed per-type breakdown remains
+        /// available by just omitting this flag.
+        merge_summary: bool,
+
+        /// Write each successfully fetched file's raw content to DIR/<hgid>, in
+        /// addition to the usual debug-formatted summary line. The directory is
+        /// created if it doesn't exist. Only supported in 'file' mode.
+        output_dir: Option<String>,
+
+        /// Write the end-of-run counters (requested, found, missing, errors by
+        /// category, bytes, and per-key fetch latency percentiles) in Prometheus
+        /// text exposition format to PATH, or to stdout if PATH is "-". Emitted in
+        /// addition to (not instead of) the normal per-key and --stats output.
+        /// Turns on the same counting as --stats and has the same restrictions; the
+        /// --stats summary line itself is only printed when --stats is also given.
+        /// Only supported in 'file', 'tree', and 'both' modes.
+        metrics: Option<String>,
+
+        /// Overwrite a file under --output-dir if one already exists at that
+        /// path. Without this, an existing file is left untouched and a line
+        /// noting the skip is printed instead. Only valid with --output-dir.
+        force: bool,
+
         #[args]
         args: Vec<String>,
     }
@@ -59,94 +215,567 @@ define_flags! {
 enum FetchType {
     File,
     Tree,
+    History,
+    /// Fetch the same key set as both files and trees, sequentially, and combine
+    /// their `FetchStats` into one found/missing/bytes tally. Useful for exercising
+    /// a workload that mixes both entity types without having to run the command twice.
+    Both,
+}
+
+/// An expected content hash, as parsed from the `--content-hash` flag, used to verify
+/// that a fetched file's content matches what the caller expects.
+enum ExpectedContentHash {
+    Sha256(Sha256),
+    Blake3(Blake3),
+}
+
+impl ExpectedContentHash {
+    fn parse(value: &str) -> Result<Self> {
+        let (algo, hex) = value
+            .split_once(':')
+            .ok_or_else(|| errors::Abort("content hash must be in the form 'algo:hex'".into()))?;
+        match algo {
+            "sha256" => Ok(Self::Sha256(Sha256::from_str(hex)?)),
+            "blake3" => Ok(Self::Blake3(Blake3::from_str(hex)?)),
+            algo => abort!("unknown content hash algorithm {algo}, expected 'sha256' or 'blake3'"),
+        }
+    }
+
+    /// Check the expected hash against the aux data of a fetched file, returning an
+    /// error describing the mismatch (or the missing aux data) if verification fails.
+    fn verify(&self, file: &StoreFile) -> Result<()> {
+        let aux_data = file.aux_data()?;
+        match self {
+            Self::Sha256(expected) if *expected == aux_data.sha256 => Ok(()),
+            Self::Sha256(expected) => {
+                abort!("content hash mismatch: expected sha256:{expected}, got sha256:{}", aux_data.sha256)
+            }
+            Self::Blake3(expected) => match aux_data.seeded_blake3 {
+                Some(actual) if actual == *expected => Ok(()),
+                Some(actual) => {
+                    abort!("content hash mismatch: expected blake3:{expected}, got blake3:{actual}")
+                }
+                None => abort!("file has no blake3 hash recorded"),
+            },
+        }
+    }
 }
 
 pub fn run(ctx: ReqCtx<DebugScmStoreOpts>, repo: &mut Repo) -> Result<u8> {
     let mode = match ctx.opts.mode.as_ref() {
         "file" => FetchType::File,
         "tree" => FetchType::Tree,
-        _ => return Err(errors::Abort("'mode' must be one of 'file' or 'tree'".into()).into()),
+        "history" => FetchType::History,
+        "both" => FetchType::Both,
+        _ => {
+            return Err(errors::Abort(
+                "'mode' must be one of 'file', 'tree', 'history', or 'both'".into(),
+            )
+            .into())
+        }
     };
 
+    // --explain doesn't need any keys to fetch if it's the only thing being asked for;
+    // it's still an error to combine --explain with both --rev and --requests-file.
+    let explain_only =
+        ctx.opts.explain && ctx.opts.requests_file.is_none() && ctx.opts.rev.is_none();
+
     abort_if!(
-        ctx.opts.requests_file.is_some() == ctx.opts.rev.is_some(),
+        !explain_only && ctx.opts.requests_file.is_some() == ctx.opts.rev.is_some(),
         "must specify exactly one of --rev or --path"
     );
 
     abort_if!(
-        ctx.opts.rev.is_some() && mode == FetchType::Tree,
-        "--rev doesn't support trees yet",
+        ctx.opts.explain && mode != FetchType::File && mode != FetchType::Tree,
+        "--explain is only supported in 'file' and 'tree' modes"
+    );
+
+    abort_if!(
+        ctx.opts.rev.is_some() && mode != FetchType::File,
+        "--rev only supports 'file' mode for now (resolving a --rev path that turns out to be \
+         a directory would suggest 'mode tree', but tree-by-rev resolution isn't supported yet)",
+    );
+
+    abort_if!(
+        ctx.opts.local && ctx.opts.remote,
+        "must specify at most one of --local or --remote"
+    );
+
+    abort_if!(
+        ctx.opts.compare_sources && (ctx.opts.local || ctx.opts.remote),
+        "--compare-sources cannot be combined with --local or --remote"
+    );
+
+    abort_if!(
+        ctx.opts.compare_sources && mode != FetchType::File,
+        "--compare-sources only supports 'file' mode"
+    );
+
+    abort_if!(
+        ctx.opts.content_hash.is_some() && mode != FetchType::File,
+        "--content-hash only supports 'file' mode"
+    );
+
+    abort_if!(
+        ctx.opts.json && mode != FetchType::File,
+        "--json only supports 'file' mode"
+    );
+
+    abort_if!(
+        ctx.opts.paths_from_manifest.is_some() && ctx.opts.rev.is_none(),
+        "--paths-from-manifest requires --rev"
+    );
+
+    abort_if!(
+        ctx.opts.paths_from_manifest.is_some() && !ctx.opts.args.is_empty(),
+        "--paths-from-manifest cannot be combined with positional paths"
+    );
+
+    // --metrics implies --stats (it reports the same counters, just in a different
+    // format), so anywhere --stats is required or restricted, --metrics is too.
+    let want_stats = ctx.opts.stats || ctx.opts.metrics.is_some();
+
+    abort_if!(
+        want_stats && (ctx.opts.compare_sources || mode == FetchType::History),
+        "--stats/--metrics only support 'file', 'tree', and 'both' modes"
+    );
+
+    abort_if!(
+        ctx.opts.merge_summary && mode != FetchType::Both,
+        "--merge-summary only supports 'both' mode"
+    );
+
+    abort_if!(
+        want_stats && ctx.opts.tree_suffix.len() > 1,
+        "--stats/--metrics do not support multiple --tree-suffix values"
+    );
+
+    abort_if!(
+        ctx.opts.output_dir.is_some() && mode != FetchType::File,
+        "--output-dir only supports 'file' mode"
+    );
+
+    abort_if!(
+        ctx.opts.force && ctx.opts.output_dir.is_none(),
+        "--force only applies with --output-dir"
+    );
+
+    abort_if!(
+        ctx.opts.recursive && mode != FetchType::Tree,
+        "--recursive only supports 'tree' mode"
+    );
+
+    abort_if!(
+        ctx.opts.recursive && ctx.opts.tree_suffix.len() > 1,
+        "--recursive does not support multiple --tree-suffix values"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && mode != FetchType::File,
+        "--aux-bench only supports 'file' mode"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && !ctx.opts.aux_only,
+        "--aux-bench requires --aux-only"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && ctx.opts.compare_sources,
+        "--aux-bench cannot be combined with --compare-sources"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && ctx.opts.content_hash.is_some(),
+        "--aux-bench cannot be combined with --content-hash"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && ctx.opts.output_dir.is_some(),
+        "--aux-bench cannot be combined with --output-dir"
+    );
+
+    abort_if!(
+        ctx.opts.bench_concurrency < 1,
+        "--bench-concurrency must be at least 1"
+    );
+
+    abort_if!(
+        ctx.opts.aux_bench && want_stats,
+        "--aux-bench always prints its own throughput summary; --stats/--metrics are not \
+         supported with it"
     );
 
-    let keys: Vec<Key> = if let Some(path) = ctx.opts.requests_file {
-        block_on_stream(block_on(file_to_async_key_stream(path.into()))?).collect()
+    let content_hash = ctx
+        .opts
+        .content_hash
+        .as_deref()
+        .map(ExpectedContentHash::parse)
+        .transpose()?;
+
+    let keys: Vec<Key> = if explain_only {
+        Vec::new()
+    } else if let Some(path) = ctx.opts.requests_file {
+        block_on_stream(block_on(file_to_async_key_stream(
+            path.into(),
+            ctx.opts.skip_bad_lines,
+        ))?)
+        .collect::<Result<Vec<_>>>()?
     } else {
         let wc = repo.working_copy()?;
         let commit = repo.resolve_commit(Some(&wc.treestate().lock()), &ctx.opts.rev.unwrap())?;
         let manifest = repo.tree_resolver()?.get(&commit)?;
-        ctx.opts
-            .args
-            .into_iter()
-            .map(|path| {
-                let path = RepoPathBuf::from_string(path)?;
-                match manifest.get(&path)? {
-                    None => abort!("path {path} not in manifest"),
-                    Some(FsNodeMetadata::Directory(_)) => abort!("path {path} is a directory"),
-                    Some(FsNodeMetadata::File(FileMetadata { hgid, .. })) => {
-                        Ok(Key::new(path, hgid))
-                    }
+        if let Some(dir) = ctx.opts.paths_from_manifest {
+            let matcher = TreeMatcher::from_rules([format!("{dir}/**")].iter(), true)?;
+            let max_files = ctx.opts.max_files.max(0) as usize;
+            let mut keys = Vec::new();
+            for file in manifest.files(matcher) {
+                let file = file?;
+                if keys.len() >= max_files {
+                    abort!(
+                        "--paths-from-manifest matched more than --max-files ({max_files}) files under {dir}"
+                    );
                 }
-            })
-            .collect::<Result<_>>()?
+                keys.push(Key::new(file.path, file.meta.hgid));
+            }
+            keys
+        } else {
+            ctx.opts
+                .args
+                .into_iter()
+                .map(|path| {
+                    let path = RepoPathBuf::from_string(path)?;
+                    match manifest.get(&path)? {
+                        None => abort!("path {path} not in manifest"),
+                        Some(FsNodeMetadata::Directory(hgid)) => match hgid {
+                            Some(hgid) => abort!(
+                                "path {path} is a directory (hgid {hgid}); 'mode tree' will be \
+                                 able to fetch it once tree-by-rev resolution is supported"
+                            ),
+                            None => abort!(
+                                "path {path} is a directory with no assigned hgid; 'mode tree' \
+                                 will be able to fetch it once tree-by-rev resolution is supported"
+                            ),
+                        },
+                        Some(FsNodeMetadata::File(FileMetadata { hgid, .. })) => {
+                            Ok(Key::new(path, hgid))
+                        }
+                    }
+                })
+                .collect::<Result<_>>()?
+        }
     };
 
+    if ctx.opts.dump_keys {
+        let mut output = ctx.core.io.output();
+        for key in &keys {
+            write!(output, "{},{}\n", key.hgid, key.path)?;
+        }
+        output.flush()?;
+        return Ok(0);
+    }
+
+    abort_if!(
+        content_hash.is_some() && keys.len() != 1,
+        "--content-hash requires fetching exactly one key"
+    );
+
     let config = repo.config();
 
-    match mode {
-        FetchType::File => fetch_files(
+    let fetch_mode = if ctx.opts.local {
+        FetchMode::LocalOnly
+    } else if ctx.opts.remote {
+        FetchMode::RemoteOnly
+    } else {
+        FetchMode::AllowRemote
+    };
+
+    let flush_every = ctx.opts.flush_every.max(0) as usize;
+    let sort = ctx.opts.sort;
+    let fail_fast = ctx.opts.fail_fast;
+    let retries = ctx.opts.retries.max(0) as u32;
+    let retry_backoff = Duration::from_millis(ctx.opts.retry_backoff.max(0) as u64);
+    let tree_suffixes = if ctx.opts.tree_suffix.is_empty() {
+        vec!["manifests".to_string()]
+    } else {
+        ctx.opts.tree_suffix.clone()
+    };
+
+    if ctx.opts.explain {
+        let explanation = match mode {
+            FetchType::File => FileStoreBuilder::new(config).describe()?,
+            FetchType::Tree if tree_suffixes.len() == 1 => TreeStoreBuilder::new(config)
+                .suffix(&tree_suffixes[0])
+                .describe()?,
+            FetchType::Tree => tree_suffixes
+                .iter()
+                .map(|suffix| {
+                    let describe = TreeStoreBuilder::new(config).suffix(suffix).describe()?;
+                    Ok(format!("--- suffix: {suffix} ---\n{describe}"))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join("\n"),
+            FetchType::History | FetchType::Both => unreachable!("rejected by abort_if! above"),
+        };
+        write!(ctx.core.io.output(), "{explanation}")?;
+        if explain_only {
+            ctx.core.io.output().flush()?;
+            return Ok(0);
+        }
+    }
+
+    let stats_start = Instant::now();
+    let result = if ctx.opts.compare_sources {
+        compare_sources(&ctx.core.io, config, keys, flush_every, sort)
+            .map(|()| FetchStats::default())
+    } else if ctx.opts.aux_bench {
+        fetch_aux_bench(
             &ctx.core.io,
             config,
             keys,
-            ctx.opts.local,
-            ctx.opts.aux_only,
-        )?,
-        FetchType::Tree => fetch_trees(&ctx.core.io, config, keys, ctx.opts.local)?,
+            fetch_mode,
+            ctx.opts.bench_concurrency.max(1) as usize,
+        )
+    } else {
+        match mode {
+            FetchType::File => fetch_files(
+                &ctx.core.io,
+                config,
+                keys,
+                fetch_mode,
+                ctx.opts.aux_only,
+                content_hash,
+                flush_every,
+                sort,
+                fail_fast,
+                retries,
+                retry_backoff,
+                ctx.opts.json,
+                want_stats,
+                ctx.opts.output_dir.map(PathBuf::from),
+                ctx.opts.force,
+            ),
+            FetchType::Tree => fetch_trees(
+                &ctx.core.io,
+                config,
+                keys,
+                fetch_mode,
+                flush_every,
+                sort,
+                fail_fast,
+                retries,
+                retry_backoff,
+                &tree_suffixes,
+                want_stats,
+                ctx.opts.recursive,
+                ctx.opts.max_depth.max(0) as u32,
+            ),
+            FetchType::History => {
+                fetch_history(&ctx.core.io, config, keys, repo, flush_every, sort)
+                    .map(|()| FetchStats::default())
+            }
+            FetchType::Both => fetch_files(
+                &ctx.core.io,
+                config,
+                keys.clone(),
+                fetch_mode,
+                ctx.opts.aux_only,
+                content_hash,
+                flush_every,
+                sort,
+                fail_fast,
+                retries,
+                retry_backoff,
+                false,
+                // Always count bytes in 'both' mode: its end-of-run summary reports them.
+                true,
+                None,
+                false,
+            )
+            .and_then(|mut file_stats| {
+                let tree_stats = fetch_trees(
+                    &ctx.core.io,
+                    config,
+                    keys,
+                    fetch_mode,
+                    flush_every,
+                    sort,
+                    fail_fast,
+                    retries,
+                    retry_backoff,
+                    &tree_suffixes,
+                    true,
+                    false,
+                    0,
+                )?;
+                file_stats.merge(tree_stats);
+                Ok(file_stats)
+            }),
+        }
+    };
+    // Always flush, even on the error-return path, so that anything written before the
+    // error occurred is visible to the caller rather than sitting in the output buffer.
+    ctx.core.io.output().flush()?;
+    let stats = result?;
+    if ctx.opts.stats {
+        stats.print(&ctx.core.io, stats_start.elapsed())?;
+        ctx.core.io.output().flush()?;
+    }
+    if let Some(metrics_path) = &ctx.opts.metrics {
+        if metrics_path == "-" {
+            stats.write_prometheus(&mut ctx.core.io.output())?;
+            ctx.core.io.output().flush()?;
+        } else {
+            let mut file = std::fs::File::create(metrics_path)?;
+            stats.write_prometheus(&mut file)?;
+        }
+    }
+    if mode == FetchType::Both {
+        if ctx.opts.merge_summary {
+            stats.print_merged_summary(&ctx.core.io)?;
+        } else {
+            stats.print_breakdown_summary(&ctx.core.io)?;
+        }
+        ctx.core.io.output().flush()?;
     }
 
     Ok(0)
 }
 
+/// If `fail_fast` is set and `missing` contains a key whose errors are all
+/// non-retryable (i.e. none of them look like a transient network error, so
+/// the retry loop wouldn't help), abort immediately with that key's errors
+/// instead of continuing to fetch or retry the rest of the keys.
+fn check_fail_fast(fail_fast: bool, missing: &HashMap<Key, Vec<Error>>) -> Result<()> {
+    if !fail_fast {
+        return Ok(());
+    }
+    if let Some((key, errors)) = missing
+        .iter()
+        .find(|(_, errors)| !errors.iter().any(types::errors::is_network_error))
+    {
+        abort!("--fail-fast: failed to fetch {key:#?}\nError: {errors:?}");
+    }
+    Ok(())
+}
+
+/// Retry keys in `missing` that look like they failed with a transient network error, up
+/// to `retries` times with a backoff that doubles after each attempt starting from
+/// `retry_backoff`. `retry_fetch` is given the keys to retry and must return the subset
+/// that's still missing (with their errors); any side effects of a successful retry
+/// (recording stats, printing a success line, enqueuing child work, etc.) are the
+/// caller's responsibility, since those differ by fetch mode, so `retry_fetch` performs
+/// them itself before returning.
+///
+/// Returns the number of keys that were in `missing` before this call but are no longer
+/// missing afterwards, so callers can report "recovered on retry" distinctly from a
+/// clean first-attempt fetch.
+fn retry_missing_keys(
+    missing: &mut HashMap<Key, Vec<Error>>,
+    retries: u32,
+    retry_backoff: Duration,
+    mut retry_fetch: impl FnMut(Vec<Key>) -> HashMap<Key, Vec<Error>>,
+) -> u64 {
+    let mut recovered = 0;
+    let mut backoff = retry_backoff;
+    for _ in 0..retries {
+        let retry_keys: Vec<Key> = missing
+            .iter()
+            .filter(|(_, errors)| errors.iter().any(types::errors::is_network_error))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if retry_keys.is_empty() {
+            break;
+        }
+        std::thread::sleep(backoff);
+        backoff *= 2;
+        let still_missing = retry_fetch(retry_keys.clone());
+        for key in &retry_keys {
+            if !still_missing.contains_key(key) {
+                recovered += 1;
+            }
+            missing.remove(key);
+        }
+        missing.extend(still_missing);
+    }
+    recovered
+}
+
 fn fetch_files(
     io: &IO,
     config: &dyn Config,
     keys: Vec<Key>,
-    local: bool,
+    fetch_mode: FetchMode,
     aux_only: bool,
-) -> Result<()> {
+    content_hash: Option<ExpectedContentHash>,
+    flush_every: usize,
+    sort: bool,
+    fail_fast: bool,
+    retries: u32,
+    retry_backoff: Duration,
+    json: bool,
+    stats: bool,
+    output_dir: Option<PathBuf>,
+    force: bool,
+) -> Result<FetchStats> {
     let file_builder = FileStoreBuilder::new(&config);
     let store = file_builder.build()?;
 
-    let mut stdout = io.output();
+    if let Some(output_dir) = &output_dir {
+        std::fs::create_dir_all(output_dir)?;
+    }
 
-    let fetch_mode = if local {
-        FetchMode::LocalOnly
-    } else {
-        FetchMode::AllowRemote
-    };
+    let mut sink = OutputSink::new(io.output(), flush_every, sort);
+    let mut aux_completeness: HashMap<Key, AuxCompleteness> = HashMap::new();
+    let mut fetch_stats = FetchStats::default();
+    let total_keys = keys.len() as u64;
 
-    let mut fetch_and_display_successes =
-        |keys: Vec<Key>, attrs: FileAttributes| -> HashMap<Key, Vec<Error>> {
-            let fetch_result = store.fetch(keys.into_iter(), attrs, fetch_mode);
+    let mut fetch_and_display_successes = |sink: &mut OutputSink,
+                                            fetch_stats: &mut FetchStats,
+                                            keys: Vec<Key>,
+                                            attrs: FileAttributes|
+     -> HashMap<Key, Vec<Error>> {
+        let fetch_start = Instant::now();
+        let fetch_result = store.fetch(keys.into_iter(), attrs, fetch_mode);
+        if stats {
+            fetch_stats.record_latency(fetch_start.elapsed());
+        }
 
-            let (found, missing, _errors) = fetch_result.consume();
-            for (_, file) in found.into_iter() {
-                let _ = write!(stdout, "Successfully fetched file: {:#?}\n", file);
+        let (found, missing, _errors) = fetch_result.consume();
+        for (key, mut file) in found.into_iter() {
+            aux_completeness.insert(key.clone(), AuxCompleteness::from_attrs(attrs));
+            let content = if attrs.content {
+                file.file_content().ok()
+            } else {
+                None
+            };
+            if stats {
+                if let Some(ref content) = content {
+                    fetch_stats.add_file_bytes(content.len() as u64);
+                }
+            }
+            let mut line = format!("Successfully fetched file: {:#?}\n", file);
+            if let Some(ref content_hash) = content_hash {
+                match content_hash.verify(&file) {
+                    Ok(()) => line.push_str("Content hash verified\n"),
+                    Err(e) => line.push_str(&format!("Content hash verification failed: {e}\n")),
+                };
+            }
+            if let (Some(output_dir), Some(content)) = (&output_dir, &content) {
+                match write_fetched_content(output_dir, force, &key, content) {
+                    Ok(status) => line.push_str(&status),
+                    Err(e) => line.push_str(&format!("Failed to write content to disk: {e}\n")),
+                }
             }
+            let _ = sink.emit(&key, line);
+        }
 
-            missing
-        };
+        missing
+    };
 
     let mut missing = fetch_and_display_successes(
+        &mut sink,
+        &mut fetch_stats,
         keys,
         FileAttributes {
             content: !aux_only,
@@ -157,7 +786,12 @@ fn fetch_files(
     if !aux_only {
         // Maybe we failed because only one of content or aux data is available.
         // The API doesn't let us say "aux data if present", so try each separately.
+        // Whichever of these two succeeds for a given key is recorded in
+        // `aux_completeness` above, so the end-of-run summary can tell a
+        // content-only or aux-only key apart from one that got both.
         missing = fetch_and_display_successes(
+            &mut sink,
+            &mut fetch_stats,
             missing.into_keys().collect(),
             FileAttributes {
                 content: true,
@@ -165,6 +799,8 @@ fn fetch_files(
             },
         );
         missing = fetch_and_display_successes(
+            &mut sink,
+            &mut fetch_stats,
             missing.into_keys().collect(),
             FileAttributes {
                 content: false,
@@ -173,40 +809,995 @@ fn fetch_files(
         );
     }
 
+    check_fail_fast(fail_fast, &missing)?;
+
+    if !fetch_mode.is_local() {
+        let recovered = retry_missing_keys(&mut missing, retries, retry_backoff, |retry_keys| {
+            fetch_and_display_successes(
+                &mut sink,
+                &mut fetch_stats,
+                retry_keys,
+                FileAttributes {
+                    content: !aux_only,
+                    aux_data: true,
+                },
+            )
+        });
+        fetch_stats.record_retries_recovered(recovered);
+    }
+
+    fetch_stats.set_file_counts(total_keys - missing.len() as u64, missing.len() as u64);
+
+    // --aux-only --local skips the content/aux fallback above (it's only done when
+    // !aux_only) and the network retry loop (fetch_mode.is_local()), so a key that's
+    // missing here gives no hint as to why. Disambiguate "aux data just isn't cached
+    // locally yet" from "this entity isn't known locally at all" with a cheap
+    // local-only content probe, since that's exactly what operators use this
+    // combination to diagnose.
+    let aux_only_local_hints: HashMap<Key, &'static str> =
+        if aux_only && fetch_mode.is_local() && !missing.is_empty() {
+            let probe_keys: Vec<Key> = missing.keys().cloned().collect();
+            let (content_found, _missing, _errors) = store
+                .fetch(
+                    probe_keys.into_iter(),
+                    FileAttributes {
+                        content: true,
+                        aux_data: false,
+                    },
+                    FetchMode::LocalOnly,
+                )
+                .consume();
+            missing
+                .keys()
+                .map(|key| {
+                    let hint = if content_found.contains_key(key) {
+                        "aux data not cached locally (content is present)"
+                    } else {
+                        "entity unknown locally (neither content nor aux data present)"
+                    };
+                    (key.clone(), hint)
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
     for (key, errors) in missing.into_iter() {
+        let category = error_category(&errors);
+        fetch_stats.record_error(category);
+        let mut line = format!(
+            "Failed to fetch file: {key:#?}\nError category: {category}\nError: {errors:?}\n"
+        );
+        if let Some(hint) = aux_only_local_hints.get(&key) {
+            line.push_str(&format!("Hint: {hint}\n"));
+        }
+        sink.emit(&key, line)?;
+    }
+
+    sink.finish()?;
+
+    if !aux_only {
+        print_aux_completeness_summary(io, &aux_completeness, json)?;
+    }
+
+    Ok(fetch_stats)
+}
+
+/// Fetch only aux data for `keys`, split across `concurrency` concurrent
+/// `FileStore::fetch` calls, and print an aux-specific throughput summary
+/// (aux entries/sec, aux bytes/sec). Unlike `fetch_files`, a key that comes
+/// back missing is not retried with a content-only or combined content+aux
+/// fetch, and `--local` misses aren't probed for a diagnostic hint: both of
+/// those exist in `fetch_files` to help diagnose a single key, which would
+/// only distort the throughput of a benchmark over a large key set.
+fn fetch_aux_bench(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    fetch_mode: FetchMode,
+    concurrency: usize,
+) -> Result<FetchStats> {
+    let store = Arc::new(FileStoreBuilder::new(config).build()?);
+
+    let chunk_size = keys.len().div_ceil(concurrency).max(1);
+    let chunks: Vec<Vec<Key>> = keys.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
+
+    let start = Instant::now();
+    let handles: Vec<_> = chunks
+        .into_iter()
+        .map(|chunk| {
+            let store = Arc::clone(&store);
+            async_runtime::spawn_blocking(move || {
+                let (found, missing, _errors) = store
+                    .fetch(
+                        chunk.into_iter(),
+                        FileAttributes {
+                            content: false,
+                            aux_data: true,
+                        },
+                        fetch_mode,
+                    )
+                    .consume();
+                let entries = found.len() as u64;
+                let aux_bytes: u64 = found
+                    .into_values()
+                    .filter_map(|file| file.aux_data().ok())
+                    .map(|aux| aux.total_size)
+                    .sum();
+                (entries, aux_bytes, missing.len() as u64)
+            })
+        })
+        .collect();
+
+    let mut aux_entries = 0u64;
+    let mut aux_bytes = 0u64;
+    let mut missing_entries = 0u64;
+    for handle in handles {
+        let (entries, bytes, missing) = block_on(handle)?;
+        aux_entries += entries;
+        aux_bytes += bytes;
+        missing_entries += missing;
+    }
+    let elapsed = start.elapsed();
+
+    let elapsed_secs = elapsed.as_secs_f64();
+    let entries_per_sec = if elapsed_secs > 0.0 {
+        aux_entries as f64 / elapsed_secs
+    } else {
+        0.0
+    };
+    let throughput_mbps = if elapsed_secs > 0.0 {
+        (aux_bytes as f64 / (1024.0 * 1024.0)) / elapsed_secs
+    } else {
+        0.0
+    };
+
+    write!(
+        io.output(),
+        "Aux bench: {aux_entries} aux entries, {missing_entries} missing, {aux_bytes} aux bytes, \
+         {elapsed_secs:.3}s elapsed, {entries_per_sec:.2} entries/s, {throughput_mbps:.2} MB/s\n",
+    )?;
+
+    Ok(FetchStats::default())
+}
+
+/// Which combination of `content`/`aux_data` attributes a file's successful
+/// fetch satisfied. The content+aux fallback logic in `fetch_files` can only
+/// say "content and/or aux data, whichever is available", so this records
+/// which of those actually came back for a given key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AuxCompleteness {
+    ContentAndAux,
+    ContentOnly,
+    AuxOnly,
+}
+
+impl AuxCompleteness {
+    fn from_attrs(attrs: FileAttributes) -> Self {
+        match (attrs.content, attrs.aux_data) {
+            (true, true) => Self::ContentAndAux,
+            (true, false) => Self::ContentOnly,
+            (false, true) => Self::AuxOnly,
+            (false, false) => unreachable!("fetch_files never fetches with no attributes"),
+        }
+    }
+}
+
+/// Print a per-classification breakdown of which keys came back with both
+/// content and aux data versus only one of the two, so that callers can tell
+/// which keys are aux-incomplete and may need a backfill.
+fn print_aux_completeness_summary(
+    io: &IO,
+    aux_completeness: &HashMap<Key, AuxCompleteness>,
+    json: bool,
+) -> Result<()> {
+    let mut content_only: Vec<&Key> = Vec::new();
+    let mut aux_only: Vec<&Key> = Vec::new();
+    let mut content_and_aux: Vec<&Key> = Vec::new();
+    for (key, completeness) in aux_completeness {
+        match completeness {
+            AuxCompleteness::ContentAndAux => content_and_aux.push(key),
+            AuxCompleteness::ContentOnly => content_only.push(key),
+            AuxCompleteness::AuxOnly => aux_only.push(key),
+        }
+    }
+    content_only.sort();
+    aux_only.sort();
+    content_and_aux.sort();
+
+    if json {
+        let summary = serde_json::json!({
+            "content_and_aux": content_and_aux,
+            "content_only": content_only,
+            "aux_only": aux_only,
+        });
+        let mut stdout = io.output();
+        serde_json::to_writer(&mut stdout, &summary)?;
+        write!(stdout, "\n")?;
+    } else {
         write!(
-            stdout,
-            "Failed to fetch file: {key:#?}\nError: {errors:?}\n"
+            io.output(),
+            "Aux completeness summary: {} with content and aux, {} content-only, {} aux-only\n",
+            content_and_aux.len(),
+            content_only.len(),
+            aux_only.len(),
         )?;
+        for key in &content_only {
+            write!(io.output(), "content-only: {key:#?}\n")?;
+        }
+        for key in &aux_only {
+            write!(io.output(), "aux-only: {key:#?}\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch every key twice, once with `FetchMode::LocalOnly` and once with
+/// `FetchMode::RemoteOnly`, and report any key whose content diverges between
+/// the two sources (or that is present in only one of them). This is a
+/// consistency auditor for catching local cache corruption, built directly on
+/// top of the two single-source fetch modes.
+fn compare_sources(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    flush_every: usize,
+    sort: bool,
+) -> Result<()> {
+    let store = FileStoreBuilder::new(config).build()?;
+
+    let attrs = FileAttributes {
+        content: true,
+        aux_data: true,
+    };
+
+    let (mut local_found, local_missing, _errors) = store
+        .fetch(keys.clone().into_iter(), attrs, FetchMode::LocalOnly)
+        .consume();
+    let (mut remote_found, remote_missing, _errors) = store
+        .fetch(keys.into_iter(), attrs, FetchMode::RemoteOnly)
+        .consume();
+
+    let mut keys: Vec<Key> = local_found
+        .keys()
+        .chain(remote_found.keys())
+        .chain(local_missing.keys())
+        .chain(remote_missing.keys())
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut sink = OutputSink::new(io.output(), flush_every, sort);
+
+    for key in keys {
+        let local = local_found.remove(&key);
+        let remote = remote_found.remove(&key);
+        let line = match (local, remote) {
+            (Some(mut local), Some(mut remote)) => {
+                match (local.file_content(), remote.file_content()) {
+                    (Ok(local_bytes), Ok(remote_bytes)) if local_bytes == remote_bytes => {
+                        format!("OK: {key:#?}: local and remote content match\n")
+                    }
+                    (Ok(_), Ok(_)) => {
+                        let describe_hash = |file: &StoreFile| match file.aux_data() {
+                            Ok(aux) => format!("sha256:{}", aux.sha256),
+                            Err(e) => format!("<failed to compute hash: {e}>"),
+                        };
+                        format!(
+                            "MISMATCH: {key:#?}: local content ({}) differs from remote content ({})\n",
+                            describe_hash(&local),
+                            describe_hash(&remote),
+                        )
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        format!("Failed to read content for {key:#?}: {e}\n")
+                    }
+                }
+            }
+            (Some(_), None) => format!("MISSING: {key:#?}: found locally but not remotely\n"),
+            (None, Some(_)) => format!("MISSING: {key:#?}: found remotely but not locally\n"),
+            (None, None) => format!("MISSING: {key:#?}: not found locally or remotely\n"),
+        };
+        sink.emit(&key, line)?;
     }
 
+    sink.finish()
+}
+
+/// Flush `stdout` every `flush_every` calls (tracked via `processed`, which the caller
+/// increments once per fetched item). `flush_every == 0` disables periodic flushing.
+fn maybe_flush(stdout: &mut impl Write, processed: usize, flush_every: usize) -> Result<()> {
+    if flush_every != 0 && processed % flush_every == 0 {
+        stdout.flush()?;
+    }
     Ok(())
 }
 
-fn fetch_trees(io: &IO, config: &dyn Config, keys: Vec<Key>, local: bool) -> Result<()> {
+/// Destination for a fetch result line. In streaming mode (the default), lines are
+/// written to stdout (and periodically flushed) as soon as they're produced, same as
+/// before `--sort` existed. In sorted mode, lines are buffered keyed by `Key` (whose
+/// derived `Ord` is path then hgid) and only written, in sorted order, once fetching
+/// is complete -- trading streaming output for deterministic, diffable runs.
+struct OutputSink {
+    stdout: IOOutput,
+    flush_every: usize,
+    processed: usize,
+    sorted: Option<Vec<(Key, String)>>,
+}
+
+impl OutputSink {
+    fn new(stdout: IOOutput, flush_every: usize, sort: bool) -> Self {
+        Self {
+            stdout,
+            flush_every,
+            processed: 0,
+            sorted: if sort { Some(Vec::new()) } else { None },
+        }
+    }
+
+    fn emit(&mut self, key: &Key, line: String) -> Result<()> {
+        match &mut self.sorted {
+            Some(lines) => {
+                lines.push((key.clone(), line));
+                Ok(())
+            }
+            None => {
+                write!(self.stdout, "{line}")?;
+                self.processed += 1;
+                maybe_flush(&mut self.stdout, self.processed, self.flush_every)
+            }
+        }
+    }
+
+    /// Write out any buffered (sorted-mode) lines. No-op in streaming mode, since
+    /// lines were already written by `emit`.
+    fn finish(mut self) -> Result<()> {
+        if let Some(mut lines) = self.sorted.take() {
+            lines.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, line) in lines {
+                write!(self.stdout, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the content length of successfully fetched files and trees so that
+/// `--stats` can report a throughput summary at the end of the run. Counting happens
+/// from the already-fetched results (alongside the existing per-key success lines), so
+/// it doesn't add extra fetch work or change fetch concurrency.
+#[derive(Default)]
+struct FetchStats {
+    file_bytes: u64,
+    tree_bytes: u64,
+    files_found: u64,
+    files_missing: u64,
+    trees_found: u64,
+    trees_missing: u64,
+    /// Count of missing keys by `error_category`, accumulated across both files and
+    /// trees. Keyed by the same `&'static str` categories `error_category` returns, so
+    /// this never needs its own enum.
+    error_categories: HashMap<&'static str, u64>,
+    /// Wall time of each underlying `store.fetch`/`fetch_batch` call (one entry per
+    /// batch, not per key, since that's the granularity the store APIs expose).
+    /// Only populated when `--stats` or `--metrics` is requested, since timing every
+    /// batch is pure overhead otherwise.
+    latencies: Vec<Duration>,
+    /// Count of keys that were missing after the initial fetch attempt but succeeded
+    /// on a retry (see --retries), tracked separately from `files_found`/`trees_found`
+    /// so operators can tell "flaky but eventually fine" apart from a clean
+    /// first-attempt fetch.
+    retries_recovered: u64,
+}
+
+impl FetchStats {
+    fn add_file_bytes(&mut self, bytes: u64) {
+        self.file_bytes += bytes;
+    }
+
+    fn add_tree_bytes(&mut self, bytes: u64) {
+        self.tree_bytes += bytes;
+    }
+
+    /// Record a missing key's error category, so `--metrics` can report an
+    /// `errors_total` breakdown alongside the found/missing counters.
+    fn record_error(&mut self, category: &'static str) {
+        *self.error_categories.entry(category).or_insert(0) += 1;
+    }
+
+    /// Record the wall time of one `store.fetch`/`fetch_batch` call, for the
+    /// `--metrics` latency percentiles.
+    fn record_latency(&mut self, elapsed: Duration) {
+        self.latencies.push(elapsed);
+    }
+
+    /// Record that `count` keys which were missing after the initial fetch attempt
+    /// succeeded on a retry.
+    fn record_retries_recovered(&mut self, count: u64) {
+        self.retries_recovered += count;
+    }
+
+    /// Return the `p`th percentile (0.0..=1.0) of recorded batch-fetch latencies, in
+    /// seconds, or 0.0 if none were recorded. Uses nearest-rank interpolation, which is
+    /// precise enough for a handful of quantiles over what's usually a small number of
+    /// batches.
+    fn latency_percentile(&self, p: f64) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        let mut secs: Vec<f64> = self.latencies.iter().map(Duration::as_secs_f64).collect();
+        secs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = ((secs.len() - 1) as f64 * p).round() as usize;
+        secs[rank]
+    }
+
+    /// Record the file found/missing tally for this fetch, so `mode both` can report it
+    /// (merged with the tree tally) in its end-of-run summary.
+    fn set_file_counts(&mut self, found: u64, missing: u64) {
+        self.files_found = found;
+        self.files_missing = missing;
+    }
+
+    /// Record the top-level tree found/missing tally for this fetch. Trees enqueued by
+    /// `--recursive` aren't counted, since they aren't part of the requested key set.
+    fn set_tree_counts(&mut self, found: u64, missing: u64) {
+        self.trees_found = found;
+        self.trees_missing = missing;
+    }
+
+    /// Fold another `FetchStats` into this one. Used by `mode both` to combine the file
+    /// fetch's stats with the tree fetch's stats before printing.
+    fn merge(&mut self, other: FetchStats) {
+        self.file_bytes += other.file_bytes;
+        self.tree_bytes += other.tree_bytes;
+        self.files_found += other.files_found;
+        self.files_missing += other.files_missing;
+        self.trees_found += other.trees_found;
+        self.trees_missing += other.trees_missing;
+        for (category, count) in other.error_categories {
+            *self.error_categories.entry(category).or_insert(0) += count;
+        }
+        self.latencies.extend(other.latencies);
+        self.retries_recovered += other.retries_recovered;
+    }
+
+    /// Write the end-of-run counters in Prometheus text exposition format: requested,
+    /// found, and missing by entity type, errors by category, bytes by entity type, and
+    /// fetch latency percentiles. A `type` row is only emitted for an entity type that
+    /// was actually fetched, so e.g. `mode file` doesn't emit a zeroed-out `type="tree"`
+    /// series.
+    fn write_prometheus(&self, out: &mut dyn Write) -> Result<()> {
+        let by_type = [
+            ("file", self.files_found, self.files_missing, self.file_bytes),
+            ("tree", self.trees_found, self.trees_missing, self.tree_bytes),
+        ];
+
+        write!(
+            out,
+            "# HELP scmstore_requested_total Keys requested, by entity type.\n\
+             # TYPE scmstore_requested_total counter\n"
+        )?;
+        for (entity_type, found, missing, _) in &by_type {
+            if found + missing > 0 {
+                write!(
+                    out,
+                    "scmstore_requested_total{{type=\"{entity_type}\"}} {}\n",
+                    found + missing
+                )?;
+            }
+        }
+
+        write!(
+            out,
+            "# HELP scmstore_found_total Keys successfully fetched, by entity type.\n\
+             # TYPE scmstore_found_total counter\n"
+        )?;
+        for (entity_type, found, missing, _) in &by_type {
+            if found + missing > 0 {
+                write!(out, "scmstore_found_total{{type=\"{entity_type}\"}} {found}\n")?;
+            }
+        }
+
+        write!(
+            out,
+            "# HELP scmstore_missing_total Keys that failed to fetch, by entity type.\n\
+             # TYPE scmstore_missing_total counter\n"
+        )?;
+        for (entity_type, found, missing, _) in &by_type {
+            if found + missing > 0 {
+                write!(out, "scmstore_missing_total{{type=\"{entity_type}\"}} {missing}\n")?;
+            }
+        }
+
+        write!(
+            out,
+            "# HELP scmstore_errors_total Missing keys, by error category.\n\
+             # TYPE scmstore_errors_total counter\n"
+        )?;
+        for category in ["network", "other"] {
+            let count = self.error_categories.get(category).copied().unwrap_or(0);
+            write!(out, "scmstore_errors_total{{category=\"{category}\"}} {count}\n")?;
+        }
+
+        write!(
+            out,
+            "# HELP scmstore_retries_recovered_total Keys that were missing after the \
+             initial fetch attempt but succeeded on a retry.\n\
+             # TYPE scmstore_retries_recovered_total counter\n\
+             scmstore_retries_recovered_total {}\n",
+            self.retries_recovered
+        )?;
+
+        write!(
+            out,
+            "# HELP scmstore_bytes_total Bytes of content fetched, by entity type.\n\
+             # TYPE scmstore_bytes_total counter\n"
+        )?;
+        for (entity_type, found, missing, bytes) in &by_type {
+            if found + missing > 0 {
+                write!(out, "scmstore_bytes_total{{type=\"{entity_type}\"}} {bytes}\n")?;
+            }
+        }
+
+        write!(
+            out,
+            "# HELP scmstore_fetch_latency_seconds Wall time of each underlying store \
+             fetch call (one observation per batch, not per key).\n\
+             # TYPE scmstore_fetch_latency_seconds summary\n"
+        )?;
+        for quantile in ["0.5", "0.9", "0.99"] {
+            let p: f64 = quantile.parse().unwrap();
+            write!(
+                out,
+                "scmstore_fetch_latency_seconds{{quantile=\"{quantile}\"}} {}\n",
+                self.latency_percentile(p)
+            )?;
+        }
+        let latency_sum: f64 = self.latencies.iter().map(Duration::as_secs_f64).sum();
+        write!(out, "scmstore_fetch_latency_seconds_sum {latency_sum}\n")?;
+        write!(
+            out,
+            "scmstore_fetch_latency_seconds_count {}\n",
+            self.latencies.len()
+        )?;
+
+        Ok(())
+    }
+
+    /// Print the found/missing tally and byte total for files and trees separately, for
+    /// `mode both` without `--merge-summary`.
+    fn print_breakdown_summary(&self, io: &IO) -> Result<()> {
+        write!(
+            io.output(),
+            "Summary: files: {} found, {} missing, {} bytes; trees: {} found, {} missing, {} bytes\n",
+            self.files_found,
+            self.files_missing,
+            self.file_bytes,
+            self.trees_found,
+            self.trees_missing,
+            self.tree_bytes,
+        )?;
+        Ok(())
+    }
+
+    /// Print one combined found/missing tally and byte total across files and trees, for
+    /// `mode both --merge-summary`.
+    fn print_merged_summary(&self, io: &IO) -> Result<()> {
+        let found = self.files_found + self.trees_found;
+        let missing = self.files_missing + self.trees_missing;
+        let total_bytes = self.file_bytes + self.tree_bytes;
+        write!(
+            io.output(),
+            "Summary: {found} found, {missing} missing, {total_bytes} total bytes\n",
+        )?;
+        Ok(())
+    }
+
+    fn print(&self, io: &IO, elapsed: Duration) -> Result<()> {
+        let total_bytes = self.file_bytes + self.tree_bytes;
+        let elapsed_secs = elapsed.as_secs_f64();
+        let throughput_mbps = if elapsed_secs > 0.0 {
+            (total_bytes as f64 / (1024.0 * 1024.0)) / elapsed_secs
+        } else {
+            0.0
+        };
+        write!(
+            io.output(),
+            "Stats: {} file bytes, {} tree bytes, {} total bytes, {:.3}s elapsed, {:.2} MB/s, \
+             {} recovered on retry\n",
+            self.file_bytes,
+            self.tree_bytes,
+            total_bytes,
+            elapsed_secs,
+            throughput_mbps,
+            self.retries_recovered,
+        )?;
+        Ok(())
+    }
+}
+
+/// Classify a set of errors for a single key into a coarse category, so that
+/// scripted callers and humans can tell transient network issues apart from
+/// other failures without parsing the full error chain.
+fn error_category(errors: &[Error]) -> &'static str {
+    if errors.iter().any(types::errors::is_network_error) {
+        "network"
+    } else {
+        "other"
+    }
+}
+
+/// Write a fetched file's content to `output_dir/<hgid>`. Returns a status
+/// line describing the outcome, for appending to the key's existing summary
+/// line. Refuses to clobber an existing file unless `force` is set.
+fn write_fetched_content(
+    output_dir: &Path,
+    force: bool,
+    key: &Key,
+    content: &[u8],
+) -> Result<String> {
+    let path = output_dir.join(key.hgid.to_string());
+
+    let mut open_opts = std::fs::OpenOptions::new();
+    open_opts.write(true);
+    if force {
+        open_opts.create(true).truncate(true);
+    } else {
+        open_opts.create_new(true);
+    }
+
+    match open_opts.open(&path) {
+        Ok(mut f) => {
+            f.write_all(content)?;
+            Ok(format!("Wrote content to {}\n", path.display()))
+        }
+        Err(e) if !force && e.kind() == std::io::ErrorKind::AlreadyExists => {
+            Ok(format!("Skipped writing {} (already exists)\n", path.display()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn fetch_trees(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    fetch_mode: FetchMode,
+    flush_every: usize,
+    sort: bool,
+    fail_fast: bool,
+    retries: u32,
+    retry_backoff: Duration,
+    tree_suffixes: &[String],
+    stats: bool,
+    recursive: bool,
+    max_depth: u32,
+) -> Result<FetchStats> {
+    if tree_suffixes.len() != 1 {
+        return fetch_trees_multi_suffix(
+            io,
+            config,
+            keys,
+            fetch_mode,
+            flush_every,
+            sort,
+            fail_fast,
+            retries,
+            retry_backoff,
+            tree_suffixes,
+        )
+        .map(|()| FetchStats::default());
+    }
+    fetch_trees_single_suffix(
+        io,
+        config,
+        keys,
+        fetch_mode,
+        flush_every,
+        sort,
+        fail_fast,
+        retries,
+        retry_backoff,
+        &tree_suffixes[0],
+        stats,
+        recursive,
+        max_depth,
+    )
+}
+
+/// Fetch every key under a single suffix and print a line per key describing whether it was
+/// found. This is the original single-suffix behavior of `fetch_trees`, kept byte-for-byte so
+/// the common (single `--tree-suffix`) case is unaffected by multi-suffix support.
+///
+/// When `recursive` is set, each successfully fetched tree's directory entries are enqueued
+/// and fetched in turn, down to `max_depth` additional levels, so the whole subtree is walked
+/// the way a real checkout would rather than stopping at the keys given on the command line.
+fn fetch_trees_single_suffix(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    fetch_mode: FetchMode,
+    flush_every: usize,
+    sort: bool,
+    fail_fast: bool,
+    retries: u32,
+    retry_backoff: Duration,
+    tree_suffix: &str,
+    stats: bool,
+    recursive: bool,
+    max_depth: u32,
+) -> Result<FetchStats> {
     let mut tree_builder = TreeStoreBuilder::new(config);
-    tree_builder = tree_builder.suffix("manifests");
+    tree_builder = tree_builder.suffix(tree_suffix);
     let store = tree_builder.build()?;
 
-    let mut stdout = io.output();
+    let mut sink = OutputSink::new(io.output(), flush_every, sort);
+    let mut fetch_stats = FetchStats::default();
 
-    let fetch_mode = if local {
-        FetchMode::LocalOnly
-    } else {
-        FetchMode::AllowRemote
+    let mut current_keys = keys;
+    let mut depth = 0;
+    loop {
+        if current_keys.is_empty() {
+            break;
+        }
+        let mut children = Vec::new();
+        let mut depth0_found: u64 = 0;
+
+        let fetch_start = Instant::now();
+        let fetch_result = store.fetch_batch(current_keys.into_iter(), fetch_mode);
+        if stats {
+            fetch_stats.record_latency(fetch_start.elapsed());
+        }
+
+        let (found, mut missing, mut fetch_errors) = fetch_result.consume();
+        for mut complete in found.into_iter() {
+            if depth == 0 {
+                depth0_found += 1;
+            }
+            if stats {
+                add_tree_bytes(&mut fetch_stats, &mut complete.1);
+            }
+            if recursive && depth < max_depth {
+                enqueue_child_trees(&complete.0, &mut complete.1, &mut children);
+            }
+            let line = format!("Successfully fetched tree: {:#?}\n", complete);
+            sink.emit(&complete.0, line)?;
+        }
+
+        check_fail_fast(fail_fast, &missing)?;
+
+        if !fetch_mode.is_local() {
+            let recovered = retry_missing_keys(&mut missing, retries, retry_backoff, |retry_keys| {
+                let retry_start = Instant::now();
+                let retry_result = store.fetch_batch(retry_keys.into_iter(), fetch_mode);
+                if stats {
+                    fetch_stats.record_latency(retry_start.elapsed());
+                }
+                let (retry_found, retry_missing, retry_errors) = retry_result.consume();
+                for mut complete in retry_found.into_iter() {
+                    if depth == 0 {
+                        depth0_found += 1;
+                    }
+                    if stats {
+                        add_tree_bytes(&mut fetch_stats, &mut complete.1);
+                    }
+                    if recursive && depth < max_depth {
+                        enqueue_child_trees(&complete.0, &mut complete.1, &mut children);
+                    }
+                    let line = format!("Successfully fetched tree: {:#?}\n", complete);
+                    let _ = sink.emit(&complete.0, line);
+                }
+                fetch_errors.extend(retry_errors);
+                retry_missing
+            });
+            fetch_stats.record_retries_recovered(recovered);
+        }
+
+        if depth == 0 {
+            fetch_stats.set_tree_counts(depth0_found, missing.len() as u64);
+        }
+
+        for (key, errors) in missing.into_iter() {
+            let category = error_category(&errors);
+            fetch_stats.record_error(category);
+            let line =
+                format!("Tree not found: {key:#?}\nError category: {category}\nError: {errors:?}\n");
+            sink.emit(&key, line)?;
+        }
+
+        // Errors that aren't associated with any one key (e.g. the whole batch
+        // request failed) used to be silently dropped by `consume()`, hiding real
+        // failures behind what looked like a clean "not found" run. Report them
+        // separately from the per-key "not found" lines above so the two cases
+        // aren't conflated.
+        if !fetch_errors.is_empty() {
+            write!(
+                io.output(),
+                "Tree fetch errored: {} error(s) not associated with a specific key: {fetch_errors:?}\n",
+                fetch_errors.len(),
+            )?;
+        }
+
+        current_keys = children;
+        depth += 1;
+    }
+
+    sink.finish()?;
+    Ok(fetch_stats)
+}
+
+/// Append a `Key` for each subdirectory entry in `tree` to `out`, so `fetch_trees_single_suffix`
+/// can enqueue a tree's children for the next `--recursive` depth. Trees whose content can't be
+/// parsed into a manifest entry (e.g. aux-data-only results) contribute no children, same as
+/// `add_tree_bytes`.
+fn enqueue_child_trees(parent: &Key, tree: &mut StoreTree, out: &mut Vec<Key>) {
+    let entry = match tree.manifest_tree_entry() {
+        Ok(entry) => entry,
+        Err(_) => return,
     };
+    for element in entry.elements() {
+        let element = match element {
+            Ok(element) => element,
+            Err(_) => continue,
+        };
+        if element.flag == Flag::Directory {
+            let mut path = parent.path.clone();
+            path.push(element.component.as_path_component());
+            out.push(Key::new(path, element.hgid));
+        }
+    }
+}
+
+/// Add a tree's serialized byte length to `fetch_stats`, ignoring trees whose content
+/// couldn't be parsed into a manifest entry (e.g. aux-data-only results).
+fn add_tree_bytes(fetch_stats: &mut FetchStats, tree: &mut StoreTree) {
+    if let Ok(entry) = tree.manifest_tree_entry() {
+        fetch_stats.add_tree_bytes(entry.0.len() as u64);
+    }
+}
+
+/// Fetch every key under each of `tree_suffixes` and print a line per key listing which
+/// suffixes had it and which didn't, so suffix-routing bugs between manifests and other tree
+/// namespaces (where the same key might be, or should be, present under more than one suffix)
+/// are easy to spot.
+fn fetch_trees_multi_suffix(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    fetch_mode: FetchMode,
+    flush_every: usize,
+    sort: bool,
+    fail_fast: bool,
+    retries: u32,
+    retry_backoff: Duration,
+    tree_suffixes: &[String],
+) -> Result<()> {
+    let mut found_by_key: HashMap<Key, Vec<String>> = HashMap::new();
+    let mut missing_by_key: HashMap<Key, Vec<String>> = HashMap::new();
+    let mut fetch_errors = Vec::new();
+
+    for tree_suffix in tree_suffixes {
+        let (found, missing, errors) = fetch_trees_for_suffix(
+            config,
+            keys.clone(),
+            fetch_mode,
+            fail_fast,
+            retries,
+            retry_backoff,
+            tree_suffix,
+        )?;
+        for key in found {
+            found_by_key.entry(key).or_default().push(tree_suffix.clone());
+        }
+        for key in missing.into_keys() {
+            missing_by_key.entry(key).or_default().push(tree_suffix.clone());
+        }
+        fetch_errors.extend(errors);
+    }
+
+    let mut sink = OutputSink::new(io.output(), flush_every, sort);
+    for key in &keys {
+        let found_suffixes = found_by_key.get(key).cloned().unwrap_or_default();
+        let missing_suffixes = missing_by_key.get(key).cloned().unwrap_or_default();
+        let line = format!(
+            "Tree {key:#?}: found under suffixes {found_suffixes:?}, missing under suffixes {missing_suffixes:?}\n"
+        );
+        sink.emit(key, line)?;
+    }
+
+    if !fetch_errors.is_empty() {
+        write!(
+            io.output(),
+            "Tree fetch errored: {} error(s) not associated with a specific key: {fetch_errors:?}\n",
+            fetch_errors.len(),
+        )?;
+    }
+
+    sink.finish()
+}
+
+/// Fetch every key under a single suffix, retrying transient failures like
+/// `fetch_trees_single_suffix`, but only tracking which keys were found rather than printing
+/// their content. Used by `fetch_trees_multi_suffix` to check each suffix in turn.
+fn fetch_trees_for_suffix(
+    config: &dyn Config,
+    keys: Vec<Key>,
+    fetch_mode: FetchMode,
+    fail_fast: bool,
+    retries: u32,
+    retry_backoff: Duration,
+    tree_suffix: &str,
+) -> Result<(HashSet<Key>, HashMap<Key, Vec<Error>>, Vec<Error>)> {
+    let mut tree_builder = TreeStoreBuilder::new(config);
+    tree_builder = tree_builder.suffix(tree_suffix);
+    let store = tree_builder.build()?;
 
     let fetch_result = store.fetch_batch(keys.into_iter(), fetch_mode);
+    let (found, mut missing, mut fetch_errors) = fetch_result.consume();
+    let mut found_keys: HashSet<Key> = found.into_keys().collect();
+
+    check_fail_fast(fail_fast, &missing)?;
 
-    let (found, missing, _errors) = fetch_result.consume();
-    for complete in found.into_iter() {
-        write!(stdout, "Successfully fetched tree: {:#?}\n", complete)?;
+    if !fetch_mode.is_local() {
+        retry_missing_keys(&mut missing, retries, retry_backoff, |retry_keys| {
+            let retry_result = store.fetch_batch(retry_keys.into_iter(), fetch_mode);
+            let (retry_found, retry_missing, retry_errors) = retry_result.consume();
+            found_keys.extend(retry_found.into_keys());
+            fetch_errors.extend(retry_errors);
+            retry_missing
+        });
     }
-    for incomplete in missing.into_iter() {
-        write!(stdout, "Failed to fetch tree: {:#?}\n", incomplete)?;
+
+    Ok((found_keys, missing, fetch_errors))
+}
+
+/// Fetch history entries for the given keys and print their linknode (the
+/// commit that introduced the entry), flagging linknodes that look corrupt:
+/// either null (missing) or dangling (pointing at a commit absent from the
+/// repo's DAG). Only local/cache history data is consulted; this does not
+/// make a remote request.
+fn fetch_history(
+    io: &IO,
+    config: &dyn Config,
+    keys: Vec<Key>,
+    repo: &mut Repo,
+    flush_every: usize,
+    sort: bool,
+) -> Result<()> {
+    let store = MetadataStoreBuilder::new(config).build()?;
+    let dag_commits = repo.dag_commits()?;
+
+    let mut sink = OutputSink::new(io.output(), flush_every, sort);
+
+    for key in keys {
+        let line = match store.get_node_info(&key)? {
+            None => format!("Failed to fetch history: {key:#?}\nError: not found locally\n"),
+            Some(info) => {
+                let linknode = info.linknode;
+                let is_dangling = !linknode.is_null() && !block_on(async {
+                    dag_commits
+                        .read()
+                        .contains_vertex_name(&Vertex::copy_from(linknode.as_ref()))
+                        .await
+                })?;
+                let mut line = format!("Successfully fetched history: {:#?}\n", info);
+                if linknode.is_null() {
+                    line.push_str(&format!("WARNING: linknode is null for {key:#?}\n"));
+                } else if is_dangling {
+                    line.push_str(&format!(
+                        "WARNING: linknode {linknode} for {key:#?} does not point to a commit in this repo\n"
+                    ));
+                }
+                line
+            }
+        };
+        sink.emit(&key, line)?;
     }
 
-    Ok(())
+    sink.finish()
 }
 
 pub fn aliases() -> &'static str {
@@ -220,3 +1811,91 @@ pub fn doc() -> &'static str {
 pub fn synopsis() -> Option<&'static str> {
     None
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prometheus_text(stats: &FetchStats) -> String {
+        let mut out = Vec::new();
+        stats.write_prometheus(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_prometheus_omits_entity_types_that_were_not_fetched() {
+        let mut stats = FetchStats::default();
+        stats.set_file_counts(3, 1);
+        stats.add_file_bytes(100);
+
+        let text = prometheus_text(&stats);
+        assert!(text.contains("scmstore_requested_total{type=\"file\"} 4\n"));
+        assert!(text.contains("scmstore_found_total{type=\"file\"} 3\n"));
+        assert!(text.contains("scmstore_missing_total{type=\"file\"} 1\n"));
+        assert!(text.contains("scmstore_bytes_total{type=\"file\"} 100\n"));
+        assert!(!text.contains("type=\"tree\""));
+    }
+
+    #[test]
+    fn write_prometheus_reports_error_categories_and_retries_recovered() {
+        let mut stats = FetchStats::default();
+        stats.record_error("network");
+        stats.record_error("network");
+        stats.record_error("other");
+        stats.record_retries_recovered(2);
+
+        let text = prometheus_text(&stats);
+        assert!(text.contains("scmstore_errors_total{category=\"network\"} 2\n"));
+        assert!(text.contains("scmstore_errors_total{category=\"other\"} 1\n"));
+        assert!(text.contains("scmstore_retries_recovered_total 2\n"));
+    }
+
+    #[test]
+    fn write_prometheus_with_no_latencies_reports_zeroed_percentiles() {
+        let stats = FetchStats::default();
+
+        let text = prometheus_text(&stats);
+        assert!(text.contains("scmstore_fetch_latency_seconds{quantile=\"0.5\"} 0\n"));
+        assert!(text.contains("scmstore_fetch_latency_seconds_sum 0\n"));
+        assert!(text.contains("scmstore_fetch_latency_seconds_count 0\n"));
+    }
+
+    #[test]
+    fn latency_percentile_with_no_latencies_is_zero() {
+        let stats = FetchStats::default();
+        assert_eq!(stats.latency_percentile(0.5), 0.0);
+        assert_eq!(stats.latency_percentile(0.99), 0.0);
+    }
+
+    #[test]
+    fn latency_percentile_picks_nearest_rank() {
+        let mut stats = FetchStats::default();
+        for ms in [10, 20, 30, 40, 50] {
+            stats.record_latency(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.latency_percentile(0.0), 0.010);
+        assert_eq!(stats.latency_percentile(0.5), 0.030);
+        assert_eq!(stats.latency_percentile(1.0), 0.050);
+    }
+
+    #[test]
+    fn merge_combines_error_categories_latencies_and_retries_recovered() {
+        let mut a = FetchStats::default();
+        a.record_error("network");
+        a.record_latency(Duration::from_millis(10));
+        a.record_retries_recovered(1);
+
+        let mut b = FetchStats::default();
+        b.record_error("network");
+        b.record_error("other");
+        b.record_latency(Duration::from_millis(20));
+        b.record_retries_recovered(2);
+
+        a.merge(b);
+
+        assert_eq!(a.error_categories.get("network").copied(), Some(2));
+        assert_eq!(a.error_categories.get("other").copied(), Some(1));
+        assert_eq!(a.latencies.len(), 2);
+        assert_eq!(a.retries_recovered, 3);
+    }
+}
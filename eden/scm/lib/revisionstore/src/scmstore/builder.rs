@@ -278,6 +278,37 @@ impl<'a> FileStoreBuilder<'a> {
         Ok(Some(Arc::new(LfsStore::shared(cache_path, self.config)?)))
     }
 
+    /// Describe the layers and settings this builder would configure if `build()` were
+    /// called, without actually constructing any stores. Used by `debugscmstore --explain`
+    /// to make config precedence (cache paths, edenapi usage, aux-data settings) visible
+    /// without having to reverse-engineer it from a failed or surprising fetch.
+    #[context("failed to describe file store configuration")]
+    pub fn describe(&self) -> Result<String> {
+        let mut out = String::new();
+        out.push_str("FileStore:\n");
+        out.push_str(&format!("  local_path: {}\n", describe_opt_path(&self.local_path)));
+        out.push_str(&format!(
+            "  cache_path: {}\n",
+            describe_opt_path(&cache_path(self.config, &self.suffix)?)
+        ));
+        out.push_str(&format!("  store_aux_data: {}\n", self.store_aux_data));
+        out.push_str(&format!("  use_edenapi: {}\n", self.use_edenapi()?));
+        out.push_str(&format!("  use_lfs: {}\n", self.use_lfs()?));
+        out.push_str(&format!(
+            "  lfs_threshold_bytes: {}\n",
+            describe_opt(self.get_lfs_threshold()?.map(|b| b.value()))
+        ));
+        out.push_str(&format!("  edenapi_retries: {}\n", self.get_edenapi_retries()));
+        out.push_str(&format!(
+            "  contentstore_fallback: {}\n",
+            self.contentstore.is_some()
+                || self
+                    .config
+                    .get_or_default::<bool>("scmstore", "contentstorefallback")?
+        ));
+        Ok(out)
+    }
+
     #[context("failed to build config revisionstore")]
     pub fn build(mut self) -> Result<FileStore> {
         tracing::trace!(target: "revisionstore::filestore", "checking cache");
@@ -436,6 +467,24 @@ impl<'a> FileStoreBuilder<'a> {
     }
 }
 
+/// Render an `Option<PathBuf>` for `describe()` output, using "<none>" instead of Rust's
+/// `None`/`Some(..)` debug formatting so the output reads like a config dump.
+fn describe_opt_path(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => path.display().to_string(),
+        None => "<none>".to_string(),
+    }
+}
+
+/// Render an arbitrary `Option<T: Display>` for `describe()` output, same rationale as
+/// `describe_opt_path`.
+fn describe_opt<T: std::fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "<none>".to_string(),
+    }
+}
+
 // Return remotefilelog cache path, or None if there is no cache path
 // (e.g. because we have no repo name).
 fn cache_path(config: &dyn Config, suffix: &Option<PathBuf>) -> Result<Option<PathBuf>> {
@@ -593,6 +642,33 @@ impl<'a> TreeStoreBuilder<'a> {
         )?)))
     }
 
+    /// Describe the layers and settings this builder would configure if `build()` were
+    /// called, without actually constructing any stores. Used by `debugscmstore --explain`.
+    #[context("failed to describe tree store configuration")]
+    pub fn describe(&self) -> Result<String> {
+        let mut out = String::new();
+        out.push_str("TreeStore:\n");
+        out.push_str(&format!("  local_path: {}\n", describe_opt_path(&self.local_path)));
+        out.push_str(&format!(
+            "  cache_path: {}\n",
+            describe_opt_path(&cache_path(self.config, &self.suffix)?)
+        ));
+        out.push_str(&format!(
+            "  suffix: {}\n",
+            describe_opt_path(&self.suffix)
+        ));
+        out.push_str(&format!("  use_edenapi: {}\n", self.use_edenapi()?));
+        out.push_str(&format!(
+            "  contentstore_fallback: {}\n",
+            self.contentstore.is_some()
+                || self
+                    .config
+                    .get_or_default::<bool>("scmstore", "contentstorefallback")?
+        ));
+        out.push_str(&format!("  filestore_shared: {}\n", self.filestore.is_some()));
+        Ok(out)
+    }
+
     #[context("failed to build revision store")]
     pub fn build(mut self) -> Result<TreeStore> {
         // TODO(meyer): Clean this up, just copied and pasted from the other version & did some ugly hacks to get this
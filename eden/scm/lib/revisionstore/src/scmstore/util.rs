@@ -9,6 +9,7 @@ use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use futures::future;
 use futures::stream::Stream;
@@ -23,30 +24,46 @@ use types::HgId;
 use types::Key;
 use types::RepoPathBuf;
 
+fn parse_key_line(line: &str) -> Result<Key> {
+    let hgid_path: Vec<_> = line.splitn(2, ',').collect();
+    let hgid = HgId::from_str(hgid_path[0])?;
+    let path = hgid_path
+        .get(1)
+        .ok_or_else(|| anyhow!("malformed line, no comma found"))?;
+    let path = RepoPathBuf::from_string(path.to_string())?;
+    Ok(Key::new(path, hgid))
+}
+
 // TODO(meyer): Find a better place for this. testutil? A debug command isn't really a test.
 // Maybe refactor so less logic happens in commands / pyrevisionstore, and migrate the actual
 // business logic into revisionstore::scmstore::util or something.
-pub async fn file_to_async_key_stream(path: PathBuf) -> Result<impl Stream<Item = Key>> {
+//
+// `skip_bad_lines` controls what happens when a line fails to parse: if true, the bad line is
+// logged (with its 1-based line number and content) and skipped; if false (the default calling
+// convention), the error is instead yielded from the stream so the caller can abort the whole
+// run on the first malformed line.
+pub async fn file_to_async_key_stream(
+    path: PathBuf,
+    skip_bad_lines: bool,
+) -> Result<impl Stream<Item = Result<Key>>> {
     let file = BufReader::new(File::open(&path).await?);
     let lines = LinesStream::new(file.lines());
     Ok(lines
-        .map(|line| {
+        .enumerate()
+        .map(|(index, line)| {
+            let line_number = index + 1;
             let line = line?;
-            let hgid_path: Vec<_> = line.splitn(2, ',').collect();
-            let hgid = HgId::from_str(hgid_path[0])?;
-            let path = hgid_path
-                .get(1)
-                .ok_or_else(|| anyhow!("malformed line, no comma found"))?;
-            let path = RepoPathBuf::from_string(path.to_string())?;
-            anyhow::Ok(Key::new(path, hgid))
+            parse_key_line(&line)
+                .with_context(|| format!("malformed requests file line {line_number}: {line:?}"))
         })
-        .filter_map(|res| {
+        .filter_map(move |res| {
             future::ready(match res {
-                Ok(key) => Some(key),
-                Err(e) => {
-                    error!({ error = %e }, "error reading key from line");
+                Ok(key) => Some(Ok(key)),
+                Err(e) if skip_bad_lines => {
+                    error!({ error = %e }, "skipping malformed requests file line");
                     None
                 }
+                Err(e) => Some(Err(e)),
             })
         }))
 }